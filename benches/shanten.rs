@@ -0,0 +1,33 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use riichi_trainer::shanten_lookup::{build_shanten_lookup_tables, make_test_corpus};
+use riichi_trainer::ukeire_calculator::*;
+use riichi_trainer::user_settings::DEFAULT_SCORE_SETTINGS;
+use std::hint::black_box;
+
+fn bench_shanten_calculation(criterion: &mut Criterion) {
+    let corpus = make_test_corpus(50);
+
+    // the lookup tables are built before measuring
+    build_shanten_lookup_tables();
+
+    criterion.bench_function("shanten with lookup", |bencher| {
+        bencher.iter(|| {
+            for tiles in &corpus {
+                black_box(calculate_shanten(black_box(tiles), &DEFAULT_SCORE_SETTINGS));
+            }
+        })
+    });
+    criterion.bench_function("shanten with recursion", |bencher| {
+        bencher.iter(|| {
+            for tiles in &corpus {
+                black_box(calculate_shanten_recursive(
+                    black_box(tiles),
+                    &DEFAULT_SCORE_SETTINGS,
+                ));
+            }
+        })
+    });
+}
+
+criterion_group!(benches, bench_shanten_calculation);
+criterion_main!(benches);
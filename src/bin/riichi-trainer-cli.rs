@@ -0,0 +1,181 @@
+// plays the same training loop as the telegram bot in a terminal, the state is not saved between runs
+use riichi_trainer::game_logic::*;
+use riichi_trainer::image_render::*;
+use riichi_trainer::input_output::*;
+use riichi_trainer::quizzes::Quiz;
+use riichi_trainer::trainer::*;
+use riichi_trainer::user_state::*;
+use std::io::{BufRead, Write};
+use std::path::Path;
+
+static DEFAULT_IMAGE_PATH: &str = "./table.png";
+const USAGE_TEXT: &str = "Usage: riichi-trainer-cli [--glyphs]
+  --glyphs - print the tiles with unicode mahjong glyphs instead of the MPSZ notation";
+const HELP_TEXT: &str =
+    "Send /start to deal a new hand, then type the tiles to discard, e.g. 5m or \"chun\"
+/settings - show the settings and the other commands
+/save_image [path] - save the picture of the table to a png file
+/help - show this text
+/quit - exit";
+
+#[derive(Copy, Clone)]
+enum TileNotation {
+    Mpsz,
+    Glyphs,
+}
+
+fn get_tiles_text(tiles: &[Tile], notation: TileNotation) -> String {
+    return match notation {
+        TileNotation::Mpsz => get_short_tiles_text(tiles),
+        TileNotation::Glyphs => get_tiles_glyph_text(tiles),
+    };
+}
+
+fn print_game_state(game_state: &GameState, notation: TileNotation) {
+    println!(
+        "Dora indicators: {}",
+        get_tiles_text(get_opened_dora_indicators(game_state), notation)
+    );
+    for (i, discards) in game_state.discards.iter().enumerate() {
+        if i == 0 {
+            println!("Your discards: {}", get_tiles_text(discards, notation));
+        } else {
+            println!(
+                "Player {} discards: {}",
+                i + 1,
+                get_tiles_text(discards, notation)
+            );
+        }
+    }
+
+    // e.g. "Hand: 23456m4789p11z 1m + 555s", the drawn tile is separated from the rest
+    let hand = &game_state.hands[0];
+    let mut hand_text = get_tiles_text(&hand.tiles[0..get_closed_hand_size(hand)], notation);
+    if hand.tiles[13] != EMPTY_TILE {
+        hand_text += " ";
+        hand_text += &get_tiles_text(&[hand.tiles[13]], notation);
+    }
+    for meld in &hand.melds {
+        hand_text += " + ";
+        hand_text += &get_tiles_text(&meld.tiles, notation);
+    }
+    println!("Hand: {}", hand_text);
+}
+
+// the images can't be shown in the terminal, the tiles they show are printed instead
+fn print_response(response: &Response, user_state: &UserState, notation: TileNotation) {
+    if !response.text.is_empty() {
+        println!("{}", response.text);
+    }
+    if response.image.is_none() {
+        return;
+    }
+
+    if !response.discard_options.is_empty() {
+        if let Some(game_state) = &user_state.game_state {
+            print_game_state(game_state, notation);
+        }
+    } else if let Some(Quiz::Waits { hand_tiles } | Quiz::Shanten { hand_tiles }) = &user_state.quiz
+    {
+        println!("Hand: {}", get_tiles_text(hand_tiles, notation));
+    }
+}
+
+fn save_game_state_image(
+    user_state: &UserState,
+    static_data: &StaticData,
+    path: &Path,
+) -> Result<(), String> {
+    let Some(game_state) = &user_state.game_state else {
+        return Err("No hand is in progress, send /start to start a new hand".to_string());
+    };
+    return render_game_state(game_state, &static_data.render_data)
+        .save_with_format(path, image::ImageFormat::Png)
+        .map_err(|err| format!("Failed to save the image: {}", err));
+}
+
+fn main() {
+    let mut notation = TileNotation::Mpsz;
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--glyphs" => notation = TileNotation::Glyphs,
+            _ => {
+                eprintln!("{}", USAGE_TEXT);
+                return;
+            }
+        }
+    }
+
+    let static_data = load_static_data(Path::new(PUZZLE_PACKS_PATH));
+    let mut user_state = get_default_user_state();
+    println!("{}", HELP_TEXT);
+
+    let stdin = std::io::stdin();
+    loop {
+        print!("> ");
+        if let Err(err) = std::io::stdout().flush() {
+            eprintln!("Failed to write to the terminal: {}", err);
+            return;
+        }
+
+        let mut line = String::new();
+        match stdin.lock().read_line(&mut line) {
+            Ok(0) => return,
+            Ok(_) => {}
+            Err(err) => {
+                eprintln!("Failed to read the input: {}", err);
+                return;
+            }
+        }
+        let line = line.trim();
+
+        let mut line_split = line.split_whitespace();
+        match line_split.next() {
+            None => {}
+            Some("/quit") => return,
+            Some("/help") => println!("{}", HELP_TEXT),
+            Some("/save_image") => {
+                let path = Path::new(line_split.next().unwrap_or(DEFAULT_IMAGE_PATH));
+                match save_game_state_image(&user_state, &static_data, path) {
+                    Ok(()) => println!("Saved the table to {}", path.display()),
+                    Err(err) => println!("{}", err),
+                }
+            }
+            Some(_) => {
+                for response in process_user_message(&mut user_state, line, &static_data) {
+                    print_response(&response, &user_state, notation);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_table_image_is_saved() {
+        let static_data = load_static_data(Path::new("resources/puzzles"));
+        let mut user_state = get_default_user_state();
+        let image_path = std::env::temp_dir().join(format!(
+            "riichi_trainer_cli_test_{}.png",
+            std::process::id()
+        ));
+
+        assert!(save_game_state_image(&user_state, &static_data, &image_path).is_err());
+        assert!(!image_path.exists());
+
+        process_user_message(&mut user_state, "/start", &static_data);
+        save_game_state_image(&user_state, &static_data, &image_path).unwrap();
+        let saved_image = image::open(&image_path).unwrap();
+        let rendered_image = render_game_state(
+            user_state.game_state.as_ref().unwrap(),
+            &static_data.render_data,
+        );
+        assert_eq!(saved_image.width(), rendered_image.width());
+        assert_eq!(saved_image.height(), rendered_image.height());
+
+        std::fs::remove_file(&image_path).unwrap();
+    }
+}
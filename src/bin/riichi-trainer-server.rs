@@ -0,0 +1,340 @@
+// a local http server with a json api for the tools built on the trainer engine,
+// the hands and the tiles are given in the MPSZ notation, e.g. /waits?hand=123456789m11p22z
+use riichi_trainer::game_logic::*;
+use riichi_trainer::image_render::*;
+use riichi_trainer::input_output::*;
+use riichi_trainer::puzzles::*;
+use riichi_trainer::shanten_lookup::build_shanten_lookup_tables;
+use riichi_trainer::ukeire_calculator::*;
+use riichi_trainer::user_settings::get_default_settings;
+use serde_json::json;
+use std::collections::HashMap;
+use std::io::Cursor;
+use tiny_http::{Header, Method, Server};
+
+static DEFAULT_ADDRESS: &str = "127.0.0.1:8080";
+const USAGE_TEXT: &str = "Usage: riichi-trainer-server [--address <host:port>]";
+const ENDPOINTS_TEXT: &str = "The endpoints are /shanten, /ukeire1, /ukeire2, /waits, and /render";
+const RENDER_GAME_SETTINGS: GameSettings = GameSettings {
+    deal_first_tile: false,
+    include_honors: true,
+    red_fives_per_suit: 1,
+    simulate_opponents: false,
+    single_suit: false,
+};
+
+struct ApiResponse {
+    status_code: u16,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+fn json_response(status_code: u16, value: serde_json::Value) -> ApiResponse {
+    return ApiResponse {
+        status_code: status_code,
+        content_type: "application/json",
+        body: value.to_string().into_bytes(),
+    };
+}
+
+fn error_response(status_code: u16, message: &str) -> ApiResponse {
+    return json_response(status_code, json!({ "error": message }));
+}
+
+// only the percent-encoded characters are decoded, "+" is kept as the meld separator of MPSZ
+fn decode_query_value(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut result = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let encoded_byte = value
+            .get(i + 1..i + 3)
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], encoded_byte) {
+            (b'%', Some(byte)) => {
+                result.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                result.push(byte);
+                i += 1;
+            }
+        }
+    }
+    return String::from_utf8_lossy(&result).to_string();
+}
+
+fn parse_query(query: &str) -> HashMap<String, String> {
+    return query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (decode_query_value(name), decode_query_value(value))
+        })
+        .collect();
+}
+
+fn get_hand_parameter(parameters: &HashMap<String, String>) -> Result<Hand, String> {
+    let Some(hand) = parameters.get("hand") else {
+        return Err("The hand parameter is required, e.g. hand=123456789m11p22z".to_string());
+    };
+    return make_hand_from_string(hand).map_err(|err| format!("Invalid hand: {}", err));
+}
+
+// the parameters with tiles other than the hand are optional
+fn get_tiles_parameter(
+    parameters: &HashMap<String, String>,
+    name: &str,
+) -> Result<Vec<Tile>, String> {
+    return match parameters.get(name) {
+        Some(tiles) => make_tile_sequence_from_string(tiles)
+            .map_err(|err| format!("Invalid {} parameter: {}", name, err)),
+        None => Ok(Vec::new()),
+    };
+}
+
+// the tiles of the hand and the tiles given in the visible parameter (discards, dora indicators)
+// are not counted as the tiles that can still be drawn
+fn get_visible_tiles_table(
+    hand: &Hand,
+    parameters: &HashMap<String, String>,
+) -> Result<TileFrequencyTable, String> {
+    let visible_tiles: Vec<Tile> = get_closed_tiles(hand)
+        .into_iter()
+        .chain(
+            hand.melds
+                .iter()
+                .flat_map(|meld| meld.tiles.iter().copied()),
+        )
+        .chain(get_tiles_parameter(parameters, "visible")?)
+        .collect();
+    let visible_tiles_table = make_frequency_table(&visible_tiles);
+    if visible_tiles_table.iter().any(|count| *count > 4) {
+        return Err("There are only four copies of each tile".to_string());
+    }
+    return Ok(visible_tiles_table);
+}
+
+fn get_shanten_json(
+    parameters: &HashMap<String, String>,
+    settings: &ScoreCalculationSettings,
+) -> Result<serde_json::Value, String> {
+    let hand = get_hand_parameter(parameters)?;
+    get_visible_tiles_table(&hand, parameters)?;
+    let shanten = calculate_shanten(&get_closed_tiles(&hand), settings).get_calculated_shanten();
+    return Ok(json!({ "shanten": shanten }));
+}
+
+// the discards are sorted from the best one
+fn get_discards_json(
+    parameters: &HashMap<String, String>,
+    settings: &ScoreCalculationSettings,
+    lookahead_depth: LookaheadDepth,
+) -> Result<serde_json::Value, String> {
+    let hand = get_hand_parameter(parameters)?;
+    let mut visible_tiles = get_visible_tiles_table(&hand, parameters)?;
+    let closed_tiles = get_closed_tiles(&hand);
+    if closed_tiles.len() % 3 != 2 {
+        return Err("The hand should have 14 tiles, counting three for every meld".to_string());
+    }
+    let shanten = calculate_shanten(&closed_tiles, settings).get_calculated_shanten();
+    if shanten < 0 {
+        return Err("The hand is already complete".to_string());
+    }
+
+    let discards = match lookahead_depth {
+        LookaheadDepth::Ukeire1 => {
+            calculate_best_discards_ukeire1(&closed_tiles, shanten, &visible_tiles, settings)
+        }
+        _ => calculate_best_discards_ukeire2(&closed_tiles, shanten, &mut visible_tiles, settings),
+    };
+    let discards: Vec<serde_json::Value> = discards
+        .iter()
+        .map(|discard| {
+            json!({
+                "tile": get_short_tiles_text(&[discard.tile]),
+                "score": discard.score,
+                "improving_tiles": get_short_tiles_text(&discard.tiles_improving_shanten),
+                "improving_tile_count": find_potentially_available_tile_count(
+                    &visible_tiles,
+                    &discard.tiles_improving_shanten
+                ),
+            })
+        })
+        .collect();
+    return Ok(json!({ "shanten": shanten, "discards": discards }));
+}
+
+// for a hand that is not tenpai the waits are the tiles that improve its shanten
+fn get_waits_json(
+    parameters: &HashMap<String, String>,
+    settings: &ScoreCalculationSettings,
+) -> Result<serde_json::Value, String> {
+    let hand = get_hand_parameter(parameters)?;
+    let visible_tiles = get_visible_tiles_table(&hand, parameters)?;
+    let closed_tiles = get_closed_tiles(&hand);
+    if closed_tiles.len() % 3 != 1 {
+        return Err("The hand should have 13 tiles, counting three for every meld".to_string());
+    }
+
+    let shanten_calculator = calculate_shanten(&closed_tiles, settings);
+    let shanten = shanten_calculator.get_calculated_shanten();
+    let best_waits = convert_frequency_table_to_flat_vec(shanten_calculator.get_best_waits());
+    let wait_tiles = if shanten == 0 {
+        filter_tiles_finishing_hand(&closed_tiles, &best_waits, settings)
+    } else {
+        filter_tiles_improving_shanten(&closed_tiles, &best_waits, shanten, settings)
+    };
+    return Ok(json!({
+        "shanten": shanten,
+        "tenpai": shanten == 0,
+        "waits": get_short_tiles_text(&wait_tiles),
+        "wait_tile_count": find_potentially_available_tile_count(&visible_tiles, &wait_tiles),
+    }));
+}
+
+// the hand is rendered on a table with the given discards and dora indicators
+fn get_render_response(
+    parameters: &HashMap<String, String>,
+    render_data: &ImageRenderData,
+) -> Result<ApiResponse, String> {
+    let puzzle = Puzzle {
+        id: "render".to_string(),
+        pack_name: String::new(),
+        hand: get_hand_parameter(parameters)?,
+        discards: get_tiles_parameter(parameters, "discards")?,
+        dora_indicators: get_tiles_parameter(parameters, "dora")?,
+        explanation: None,
+    };
+    if puzzle.dora_indicators.len() > MAX_DORA_INDICATORS {
+        return Err(format!(
+            "There can't be more than {} dora indicators",
+            MAX_DORA_INDICATORS
+        ));
+    }
+    let game_state = make_puzzle_game_state(&puzzle, 1, &RENDER_GAME_SETTINGS, 0)?;
+
+    let mut buf: Cursor<Vec<u8>> = Cursor::new(Vec::new());
+    render_game_state(&game_state, render_data)
+        .write_to(&mut buf, image::ImageOutputFormat::Png)
+        .map_err(|err| format!("Failed to convert image to png: {}", err))?;
+    return Ok(ApiResponse {
+        status_code: 200,
+        content_type: "image/png",
+        body: buf.into_inner(),
+    });
+}
+
+fn handle_request(url: &str, render_data: &ImageRenderData) -> ApiResponse {
+    let (path, query) = url.split_once('?').unwrap_or((url, ""));
+    let parameters = parse_query(query);
+    let settings = get_default_settings().score_settings;
+
+    let result = match path {
+        "/shanten" => get_shanten_json(&parameters, &settings).map(|json| json_response(200, json)),
+        "/ukeire1" => get_discards_json(&parameters, &settings, LookaheadDepth::Ukeire1)
+            .map(|json| json_response(200, json)),
+        "/ukeire2" => get_discards_json(&parameters, &settings, LookaheadDepth::Ukeire2)
+            .map(|json| json_response(200, json)),
+        "/waits" => get_waits_json(&parameters, &settings).map(|json| json_response(200, json)),
+        "/render" => get_render_response(&parameters, render_data),
+        _ => return error_response(404, ENDPOINTS_TEXT),
+    };
+    return match result {
+        Ok(response) => response,
+        Err(err) => error_response(400, &err),
+    };
+}
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let mut address = DEFAULT_ADDRESS.to_string();
+    while let Some(arg) = args.next() {
+        match (arg.as_str(), args.next()) {
+            ("--address", Some(value)) => address = value,
+            _ => {
+                eprintln!("{}", USAGE_TEXT);
+                return;
+            }
+        }
+    }
+
+    let render_data = load_static_render_data();
+    build_shanten_lookup_tables();
+    let server = match Server::http(&address) {
+        Ok(server) => server,
+        Err(err) => {
+            eprintln!("Failed to start the server on {}: {}", address, err);
+            return;
+        }
+    };
+    println!("Listening on http://{}", address);
+
+    for request in server.incoming_requests() {
+        let api_response = if *request.method() == Method::Get {
+            handle_request(request.url(), &render_data)
+        } else {
+            error_response(405, "Only GET requests are supported")
+        };
+
+        // the web frontends are expected to be served from a different origin
+        let headers = [
+            Header::from_bytes("Content-Type", api_response.content_type),
+            Header::from_bytes("Access-Control-Allow-Origin", "*"),
+        ];
+        let mut response = tiny_http::Response::from_data(api_response.body)
+            .with_status_code(api_response.status_code);
+        for header in headers.into_iter().flatten() {
+            response.add_header(header);
+        }
+        if let Err(err) = request.respond(response) {
+            eprintln!("Failed to send the response: {}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_json(url: &str) -> serde_json::Value {
+        let response = handle_request(url, &load_static_render_data());
+        assert_eq!(response.content_type, "application/json");
+        return serde_json::from_slice(&response.body).unwrap();
+    }
+
+    #[test]
+    fn test_hand_is_analyzed() {
+        assert_eq!(get_json("/shanten?hand=123456789m11p22z")["shanten"], 0);
+
+        let waits = get_json("/waits?hand=123456789m11p22z&visible=1p");
+        assert_eq!(waits["tenpai"], true);
+        assert_eq!(waits["waits"], "1p2z");
+        assert_eq!(waits["wait_tile_count"], 3);
+
+        let discards = get_json("/ukeire1?hand=123456789m11p22z%2B777s");
+        assert!(discards["error"].is_string());
+        let discards = get_json("/ukeire2?hand=123456789m11p22z9s");
+        assert_eq!(discards["discards"][0]["tile"], "9s");
+        assert_eq!(discards["discards"][0]["improving_tiles"], "1p2z");
+    }
+
+    #[test]
+    fn test_invalid_requests_are_rejected() {
+        let render_data = load_static_render_data();
+        assert_eq!(
+            handle_request("/waits?hand=abc", &render_data).status_code,
+            400
+        );
+        assert_eq!(
+            handle_request("/waits?hand=11111m", &render_data).status_code,
+            400
+        );
+        assert_eq!(handle_request("/unknown", &render_data).status_code, 404);
+
+        let image = handle_request("/render?hand=123456789m11p22z&dora=5s", &render_data);
+        assert_eq!(image.status_code, 200);
+        assert_eq!(image.content_type, "image/png");
+    }
+}
@@ -1,8 +1,11 @@
 use crate::rand::prelude::SliceRandom;
-use rand::thread_rng;
-use serde::{Deserialize, Serialize};
+use crate::ukeire_calculator::*;
+use rand::{thread_rng, Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
-#[derive(Debug, Copy, Clone, Eq, Ord, PartialEq, PartialOrd)]
+#[derive(Debug, Copy, Clone, Eq, Ord, PartialEq, PartialOrd, Serialize, Deserialize)]
 pub enum Suit {
     Man,     // printed as "m"
     Pin,     // printed as "p"
@@ -14,44 +17,175 @@ pub enum Suit {
 pub struct Tile {
     pub suit: Suit,
     pub value: u8,
+    pub is_red: bool, // only fives of suited tiles can be red (printed as "0")
 }
 
 pub const EMPTY_TILE: Tile = Tile {
     suit: Suit::Special,
     value: 0,
+    is_red: false,
 };
 
+// tiles are saved in the short form to keep the saved hands small, e.g. "5m", "0p" for a red five,
+// and "0z" for an empty tile
+impl Serialize for Tile {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let suit_letter = match self.suit {
+            Suit::Man => 'm',
+            Suit::Pin => 'p',
+            Suit::Sou => 's',
+            Suit::Special => 'z',
+        };
+        let value = if self.is_red { 0 } else { self.value };
+        return serializer.serialize_str(&format!("{}{}", value, suit_letter));
+    }
+}
+
+impl<'de> Deserialize<'de> for Tile {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let text = String::deserialize(deserializer)?;
+        let mut chars = text.chars();
+        let (Some(value), Some(suit_letter), None) = (
+            chars.next().and_then(|c| c.to_digit(10)),
+            chars.next(),
+            chars.next(),
+        ) else {
+            return Err(D::Error::custom(format!("Invalid tile '{}'", text)));
+        };
+        let suit = match suit_letter {
+            'm' => Suit::Man,
+            'p' => Suit::Pin,
+            's' => Suit::Sou,
+            'z' => Suit::Special,
+            _ => return Err(D::Error::custom(format!("Invalid tile '{}'", text))),
+        };
+
+        let is_red = value == 0 && suit != Suit::Special;
+        return Ok(Tile {
+            suit: suit,
+            value: if is_red { 5 } else { value as u8 },
+            is_red: is_red,
+        });
+    }
+}
+
 pub type HandTiles = [Tile; 14];
 pub type DeadWall = [Tile; 14];
 
-#[derive(Clone)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub enum MeldType {
+    Chi,
+    Pon,
+    OpenKan,
+    ClosedKan,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Meld {
+    pub meld_type: MeldType,
+    pub tiles: Vec<Tile>,
+    pub called_tile: Tile, // EMPTY_TILE for closed kans
+    // position of the player the tile was called from relative to the meld owner
+    // 1 - right, 2 - across, 3 - left, 0 for closed kans
+    pub called_from: u8,
+}
+
+// closed tiles are stored in the beginning of the tiles array, the last element is for the drawn tile
+// with every meld the closed part of the hand becomes three tiles shorter
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Hand {
     pub tiles: HandTiles,
+    pub melds: Vec<Meld>,
 }
 
 pub const EMPTY_HAND: Hand = Hand {
     tiles: [EMPTY_TILE; 14],
+    melds: Vec::new(),
 };
 
 #[derive(Clone, Serialize, Deserialize)]
 pub struct GameSettings {
     pub deal_first_tile: bool,
     pub include_honors: bool,
+    pub red_fives_per_suit: u8,
+    pub simulate_opponents: bool,
+    pub single_suit: bool, // only one suit is in the wall, the suit is chosen by the seed
 }
 
 // store tiles as cumulative frequency distribution (store count of every possible tile in a hand)
+pub const SUITED_SUITS: [Suit; 3] = [Suit::Man, Suit::Pin, Suit::Sou];
+
 pub type TileFrequencyTable = [u8; 37];
 pub const EMPTY_FREQUENCY_TABLE: TileFrequencyTable = [0; 37];
+pub const MAX_DORA_INDICATORS: usize = 5;
+
+// how the wall was dealt, only the shuffled walls can be dealt again from the seed
+#[derive(Debug, Copy, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub enum DealMode {
+    Shuffled,
+    SingleSuit, // the suit is chosen by the seed
+    #[default]
+    GivenHand, // the hand and the discards were given, the seed shuffled only the rest of the wall
+}
 
-#[derive(Clone)]
+// the game settings that decide which wall is dealt from a seed
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DealSettings {
+    pub include_honors: bool,
+    pub red_fives_per_suit: u8,
+    pub simulate_opponents: bool,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct GameState {
     pub hands: Vec<Hand>,
     pub discards: Vec<Vec<Tile>>,
+    #[serde(with = "frequency_table_serde")]
     pub total_discards_table: TileFrequencyTable,
-    pub _dead_wall: DeadWall,
-    pub dora_indicators: [Tile; 8], // 1-3 - dora indicators, 4-7 - uradora indicators
+    // 0-3 - replacement tiles for kans, 4-8 - dora indicators, 9-13 - uradora indicators
+    pub dead_wall: DeadWall,
+    // 0-4 - dora indicators, 5-7 - the first uradora indicators, the uradora are not scored
+    pub dora_indicators: [Tile; 8],
     pub opened_dora_indicators: u8,
     pub live_wall: Vec<Tile>,
+    pub seed: u64, // the same seed with the same settings produces the same wall
+    // the sessions in the database are not updated by the json updaters,
+    // the hands saved before the mode was stored are not offered to be replayed
+    #[serde(default)]
+    pub deal_mode: DealMode,
+    // None for the given hands and the hands saved before the deal settings were stored
+    #[serde(default)]
+    pub deal_settings: Option<DealSettings>,
+}
+
+// serde implements the traits only for arrays of up to 32 elements
+mod frequency_table_serde {
+    use super::TileFrequencyTable;
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S>(table: &TileFrequencyTable, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        return table.as_slice().serialize(serializer);
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<TileFrequencyTable, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let values = Vec::<u8>::deserialize(deserializer)?;
+        return values
+            .try_into()
+            .map_err(|_| D::Error::custom("Invalid size of the tile frequency table"));
+    }
 }
 
 pub fn get_tile_index(tile: &Tile) -> usize {
@@ -65,11 +199,93 @@ pub fn get_tile_index(tile: &Tile) -> usize {
     return (tile.value + shift - 1) as usize;
 }
 
+// same as get_tile_index, but red fives get their own indexes (9, 19, and 29) that are not used by
+// other tiles, should be used only for displaying tiles, not for the frequency tables
+pub fn get_display_tile_index(tile: &Tile) -> usize {
+    if tile.is_red {
+        return get_tile_index(tile) + 5;
+    }
+
+    return get_tile_index(tile);
+}
+
+pub fn is_same_tile_ignoring_red(first: &Tile, second: &Tile) -> bool {
+    return first.suit == second.suit && first.value == second.value;
+}
+
+// find the exact tile first, and if it is not found, then a tile that differs only by being red
+pub fn find_tile_position(tiles: &[Tile], tile: &Tile) -> Option<usize> {
+    return tiles.iter().position(|t| t == tile).or_else(|| {
+        tiles
+            .iter()
+            .position(|t| is_same_tile_ignoring_red(t, tile))
+    });
+}
+
+// the dora is the tile next to the indicator, the order wraps around inside suits, winds and dragons
+pub fn get_dora_from_indicator(indicator: &Tile) -> Tile {
+    let value = match indicator.suit {
+        Suit::Special if indicator.value == 4 => 1,
+        Suit::Special if indicator.value == 7 => 5,
+        Suit::Special => indicator.value + 1,
+        _ if indicator.value == 9 => 1,
+        _ => indicator.value + 1,
+    };
+
+    return Tile {
+        suit: indicator.suit,
+        value: value,
+        is_red: false,
+    };
+}
+
+// every kan opens one more indicator, up to five after four kans
+pub fn get_opened_dora_indicators(game: &GameState) -> &[Tile] {
+    let opened_count = (game.opened_dora_indicators as usize).min(MAX_DORA_INDICATORS);
+    return &game.dora_indicators[0..opened_count];
+}
+
+pub fn get_closed_hand_size(hand: &Hand) -> usize {
+    return 13 - 3 * hand.melds.len();
+}
+
+// closed tiles of the hand including the drawn tile (if there is one)
+pub fn get_closed_tiles(hand: &Hand) -> Vec<Tile> {
+    let mut result = hand.tiles[0..get_closed_hand_size(hand)].to_vec();
+    if hand.tiles[13] != EMPTY_TILE {
+        result.push(hand.tiles[13]);
+    }
+    return result;
+}
+
+// put the tiles to the closed part of the hand, the tile that doesn't fit goes to the drawn tile slot
+fn set_closed_tiles(hand: &mut Hand, mut tiles: Vec<Tile>) {
+    let closed_hand_size = get_closed_hand_size(hand);
+    assert!(
+        tiles.len() == closed_hand_size || tiles.len() == closed_hand_size + 1,
+        "Incorrect number of closed tiles {} for a hand with {} melds",
+        tiles.len(),
+        hand.melds.len()
+    );
+
+    hand.tiles[13] = if tiles.len() > closed_hand_size {
+        tiles.pop().unwrap_or(EMPTY_TILE)
+    } else {
+        EMPTY_TILE
+    };
+    tiles.sort();
+    hand.tiles[0..closed_hand_size].copy_from_slice(&tiles);
+    hand.tiles[closed_hand_size..13].fill(EMPTY_TILE);
+}
+
 fn sort_hand(hand: &mut Hand) {
+    let closed_hand_size = get_closed_hand_size(hand);
     if hand.tiles[13] == EMPTY_TILE {
-        hand.tiles[0..13].sort();
-    } else {
+        hand.tiles[0..closed_hand_size].sort();
+    } else if closed_hand_size == 13 {
         hand.tiles.sort();
+    } else {
+        set_closed_tiles(hand, get_closed_tiles(hand));
     }
 }
 
@@ -77,28 +293,31 @@ fn populate_full_set(game_settings: &GameSettings) -> Vec<Tile> {
     let mut result = Vec::with_capacity((9 * 3 + 7) * 4);
 
     for i in 1..=9 {
-        for _j in 0..4 {
+        for j in 0..4 {
             result.push(Tile {
                 suit: Suit::Man,
                 value: i,
+                is_red: i == 5 && j < game_settings.red_fives_per_suit,
             });
         }
     }
 
     for i in 1..=9 {
-        for _j in 0..4 {
+        for j in 0..4 {
             result.push(Tile {
                 suit: Suit::Pin,
                 value: i,
+                is_red: i == 5 && j < game_settings.red_fives_per_suit,
             });
         }
     }
 
     for i in 1..=9 {
-        for _j in 0..4 {
+        for j in 0..4 {
             result.push(Tile {
                 suit: Suit::Sou,
                 value: i,
+                is_red: i == 5 && j < game_settings.red_fives_per_suit,
             });
         }
     }
@@ -109,6 +328,7 @@ fn populate_full_set(game_settings: &GameSettings) -> Vec<Tile> {
                 result.push(Tile {
                     suit: Suit::Special,
                     value: i,
+                    is_red: false,
                 });
             }
         }
@@ -117,18 +337,30 @@ fn populate_full_set(game_settings: &GameSettings) -> Vec<Tile> {
     return result;
 }
 
+pub fn generate_random_seed() -> u64 {
+    return thread_rng().gen();
+}
+
 pub fn generate_normal_dealt_game(
     player_count: u32,
     game_settings: &GameSettings,
+    seed: u64,
 ) -> Result<GameState, String> {
     let mut tiles = populate_full_set(game_settings);
-    tiles.shuffle(&mut thread_rng());
+    if game_settings.single_suit {
+        let suit = SUITED_SUITS[(seed % SUITED_SUITS.len() as u64) as usize];
+        tiles.retain(|tile| tile.suit == suit);
+    }
+    if tiles.len() < 14 + 13 * player_count as usize {
+        return Err(format!("Not enough tiles to deal {} hands", player_count));
+    }
+    tiles.shuffle(&mut ChaCha8Rng::seed_from_u64(seed));
 
     let dead_wall: [Tile; 14] = tiles
         .split_off(tiles.len() - 14)
         .try_into()
         .map_err(|_| "Incorrect number of tiles")?;
-    // 1-3 - dora indicators, 4-7 - uradora indicators
+    // 0-4 - dora indicators, 5-7 - the first uradora indicators
     let dora_indicators: [Tile; 8] = dead_wall[4..12]
         .try_into()
         .map_err(|_| "Incorrect number of tiles")?;
@@ -141,6 +373,7 @@ pub fn generate_normal_dealt_game(
             tiles: new_tiles
                 .try_into()
                 .map_err(|_| "Incorrect number of tiles")?,
+            melds: Vec::new(),
         });
         sort_hand(&mut hands[i as usize]);
         discards.push(Vec::new());
@@ -150,10 +383,21 @@ pub fn generate_normal_dealt_game(
         hands: hands,
         discards: discards,
         total_discards_table: EMPTY_FREQUENCY_TABLE,
-        _dead_wall: dead_wall,
+        dead_wall: dead_wall,
         dora_indicators: dora_indicators,
         opened_dora_indicators: 1,
         live_wall: tiles,
+        seed: seed,
+        deal_mode: if game_settings.single_suit {
+            DealMode::SingleSuit
+        } else {
+            DealMode::Shuffled
+        },
+        deal_settings: Some(DealSettings {
+            include_honors: game_settings.include_honors,
+            red_fives_per_suit: game_settings.red_fives_per_suit,
+            simulate_opponents: player_count > 1,
+        }),
     };
 
     if game_settings.deal_first_tile {
@@ -168,6 +412,7 @@ pub fn generate_dealt_game_with_hand_and_discards(
     predefined_hand: Hand,
     predefined_discards: Vec<Tile>,
     game_settings: &GameSettings,
+    seed: u64,
 ) -> Result<GameState, String> {
     if predefined_hand.tiles[0] == EMPTY_TILE {
         return Err("Incorrect hand".to_string());
@@ -179,19 +424,27 @@ pub fn generate_dealt_game_with_hand_and_discards(
             || predefined_hand
                 .tiles
                 .iter()
+                .chain(predefined_hand.melds.iter().flat_map(|meld| &meld.tiles))
                 .position(|&t| t.suit == Suit::Special)
                 .is_some()
             || predefined_discards
                 .iter()
                 .position(|&t| t.suit == Suit::Special)
                 .is_some(),
+        red_fives_per_suit: game_settings.red_fives_per_suit,
+        simulate_opponents: game_settings.simulate_opponents,
+        single_suit: false,
     };
 
     let mut tiles = populate_full_set(&game_settings);
 
-    for tile in predefined_hand.tiles {
+    let meld_tiles = predefined_hand
+        .melds
+        .iter()
+        .flat_map(|meld| meld.tiles.iter().copied());
+    for tile in predefined_hand.tiles.into_iter().chain(meld_tiles) {
         if tile != EMPTY_TILE {
-            let index_result = tiles.iter().position(|&t| t == tile);
+            let index_result = find_tile_position(&tiles, &tile);
 
             if let Some(index) = index_result {
                 tiles.remove(index);
@@ -199,27 +452,43 @@ pub fn generate_dealt_game_with_hand_and_discards(
         }
     }
 
-    tiles.shuffle(&mut thread_rng());
+    tiles.shuffle(&mut ChaCha8Rng::seed_from_u64(seed));
 
     let mut dead_wall: [Tile; 14] = tiles
         .split_off(tiles.len() - 14)
         .try_into()
         .map_err(|_| "Incorrect number of tiles left to form dead wall")?;
 
-    if predefined_discards.len() > 0 && predefined_discards[0] != EMPTY_TILE {
+    if !predefined_discards.is_empty() && predefined_discards[0] != EMPTY_TILE {
         dead_wall[4] = predefined_discards[0];
     }
 
-    // 1-3 - dora indicators, 4-7 - uradora indicators
+    // 0-4 - dora indicators, 5-7 - the first uradora indicators
     let dora_indicators: [Tile; 8] = dead_wall[4..12]
         .try_into()
         .map_err(|_| "Incorrect number of tiles in dead wall")?;
 
-    let mut hands = Vec::with_capacity(player_count as usize);
-    let mut discards = Vec::with_capacity(player_count as usize);
+    // the discards are taken before the other hands are dealt, so none of them is skipped
+    let mut discards = vec![Vec::new(); player_count as usize];
+    let mut total_discards_table = EMPTY_FREQUENCY_TABLE;
+    {
+        let mut player_index = 0;
+        for tile in predefined_discards.iter().skip(1) {
+            if *tile != EMPTY_TILE {
+                // find first tile in tiles
+                let index_result = find_tile_position(&tiles, tile);
+                if let Some(index) = index_result {
+                    let tile = tiles.remove(index);
+                    total_discards_table[get_tile_index(&tile)] += 1;
+                    discards[player_index].push(tile);
+                    player_index = (player_index + 1) % player_count as usize;
+                }
+            }
+        }
+    }
 
+    let mut hands = Vec::with_capacity(player_count as usize);
     hands.push(predefined_hand);
-    discards.push(Vec::new());
 
     for i in 1..player_count {
         let new_tiles = [tiles.split_off(tiles.len() - 13), [EMPTY_TILE].to_vec()].concat();
@@ -227,35 +496,22 @@ pub fn generate_dealt_game_with_hand_and_discards(
             tiles: new_tiles
                 .try_into()
                 .map_err(|_| "Incorrect number of tiles left to form player hand")?,
+            melds: Vec::new(),
         });
         sort_hand(&mut hands[i as usize]);
-        discards.push(Vec::new());
-    }
-
-    {
-        let mut player_index = 0;
-        for i in 1..predefined_discards.len() {
-            let tile = predefined_discards[i];
-            if tile != EMPTY_TILE {
-                // find first tile in tiles
-                let index_result = tiles.iter().position(|&t| t == tile);
-                if let Some(index) = index_result {
-                    tiles.remove(index);
-                    discards[player_index].push(tile);
-                    player_index = (player_index + 1) % player_count as usize;
-                }
-            }
-        }
     }
 
     let mut game_state = GameState {
         hands: hands,
         discards: discards,
-        total_discards_table: EMPTY_FREQUENCY_TABLE,
-        _dead_wall: dead_wall,
+        total_discards_table: total_discards_table,
+        dead_wall: dead_wall,
         dora_indicators: dora_indicators,
         opened_dora_indicators: 1,
         live_wall: tiles,
+        seed: seed,
+        deal_mode: DealMode::GivenHand,
+        deal_settings: None,
     };
 
     if game_state.hands[0].tiles[13] == EMPTY_TILE && game_settings.deal_first_tile {
@@ -282,14 +538,302 @@ pub fn draw_tile_to_hand(game: &mut GameState, hand_index: usize) {
     game.hands[hand_index].tiles[13] = game.live_wall.split_off(game.live_wall.len() - 1)[0];
 }
 
+// simple efficiency bot for simulated opponents, chooses the discard with the best ukeire1
+fn choose_discard_for_opponent(
+    game: &GameState,
+    hand_index: usize,
+    settings: &ScoreCalculationSettings,
+) -> usize {
+    let hand = &game.hands[hand_index];
+    let closed_tiles = get_closed_tiles(hand);
+    let shanten = calculate_shanten(&closed_tiles, settings).get_calculated_shanten();
+    let best_discards = calculate_best_discards_ukeire1(
+        &closed_tiles,
+        shanten,
+        &get_visible_tiles(game, hand_index),
+        settings,
+    );
+
+    // if no discard improves the hand (e.g. the hand is already complete) just discard the drawn tile
+    return match best_discards.first() {
+        Some(best_discard) => find_tile_position(&hand.tiles, &best_discard.tile).unwrap_or(13),
+        None => 13,
+    };
+}
+
+// let all the other players draw and discard a tile until it is the given player's turn again
+// returns the tiles discarded by the other players in the order they were discarded
+pub fn play_other_players_turns(
+    game: &mut GameState,
+    hand_index: usize,
+    settings: &ScoreCalculationSettings,
+) -> Vec<Tile> {
+    let player_count = game.hands.len();
+    let mut discarded_tiles = Vec::with_capacity(player_count - 1);
+
+    for i in 1..player_count {
+        if game.live_wall.is_empty() {
+            break;
+        }
+
+        let other_hand_index = (hand_index + i) % player_count;
+        draw_tile_to_hand(game, other_hand_index);
+        let tile_index = choose_discard_for_opponent(game, other_hand_index, settings);
+        discarded_tiles.push(discard_tile(game, other_hand_index, tile_index));
+    }
+
+    return discarded_tiles;
+}
+
 pub fn discard_tile(game: &mut GameState, hand_index: usize, tile_index: usize) -> Tile {
-    let discarded_tile = game.hands[hand_index].tiles[tile_index];
-    game.hands[hand_index].tiles[tile_index..14].rotate_left(1);
-    game.hands[hand_index].tiles[13] = EMPTY_TILE;
+    let hand = &mut game.hands[hand_index];
+    let discarded_tile = hand.tiles[tile_index];
     // we sort in the tile that was added the last
-    sort_hand(&mut game.hands[hand_index]);
+    hand.tiles[tile_index] = hand.tiles[13];
+    hand.tiles[13] = EMPTY_TILE;
+    sort_hand(hand);
 
     game.total_discards_table[get_tile_index(&discarded_tile)] += 1;
     game.discards[hand_index].push(discarded_tile);
     return discarded_tile;
 }
+
+// returns the tiles taken from the closed part of the hand and the tiles that are left there
+fn split_closed_tiles(hand: &Hand, tiles: &[Tile]) -> Result<(Vec<Tile>, Vec<Tile>), String> {
+    let mut closed_tiles = get_closed_tiles(hand);
+    let mut taken_tiles = Vec::with_capacity(tiles.len());
+    for tile in tiles {
+        let Some(index) = find_tile_position(&closed_tiles, tile) else {
+            return Err("The hand doesn't have the tiles to form the meld".to_string());
+        };
+        taken_tiles.push(closed_tiles.remove(index));
+    }
+
+    return Ok((taken_tiles, closed_tiles));
+}
+
+fn is_valid_meld(meld_type: MeldType, tiles: &[Tile]) -> bool {
+    let mut tiles = tiles.to_vec();
+    tiles.sort();
+    return match meld_type {
+        MeldType::Chi => {
+            tiles.len() == 3
+                && tiles[0].suit != Suit::Special
+                && tiles.iter().all(|t| t.suit == tiles[0].suit)
+                && tiles[1].value == tiles[0].value + 1
+                && tiles[2].value == tiles[0].value + 2
+        }
+        MeldType::Pon => {
+            tiles.len() == 3
+                && tiles
+                    .iter()
+                    .all(|t| is_same_tile_ignoring_red(t, &tiles[0]))
+        }
+        MeldType::OpenKan | MeldType::ClosedKan => {
+            tiles.len() == 4
+                && tiles
+                    .iter()
+                    .all(|t| is_same_tile_ignoring_red(t, &tiles[0]))
+        }
+    };
+}
+
+// makes a meld from tiles that are not in the game yet (e.g. for predefined hands)
+pub fn make_meld(meld_type: MeldType, tiles: Vec<Tile>, called_from: u8) -> Result<Meld, String> {
+    if !is_valid_meld(meld_type, &tiles) {
+        return Err(format!("Tiles don't form a valid {:?}", meld_type));
+    }
+
+    let called_tile = if meld_type == MeldType::ClosedKan {
+        EMPTY_TILE
+    } else {
+        tiles[0]
+    };
+
+    let mut tiles = tiles;
+    tiles.sort();
+    return Ok(Meld {
+        meld_type,
+        tiles,
+        called_tile,
+        called_from: if meld_type == MeldType::ClosedKan {
+            0
+        } else {
+            called_from
+        },
+    });
+}
+
+// kan replacement tiles are taken from the dead wall, each kan also opens a new dora indicator
+fn draw_replacement_tile_to_hand(game: &mut GameState, hand_index: usize) -> Result<(), String> {
+    let kan_count = (game.opened_dora_indicators - 1) as usize;
+    if kan_count >= 4 {
+        return Err("No replacement tiles left in the dead wall".to_string());
+    }
+    if game.live_wall.is_empty() {
+        return Err("No tiles left in the live wall".to_string());
+    }
+
+    game.hands[hand_index].tiles[13] = game.dead_wall[kan_count];
+    game.opened_dora_indicators += 1;
+    // the dead wall is replenished from the end of the live wall
+    game.live_wall.remove(0);
+    return Ok(());
+}
+
+pub fn declare_closed_kan(
+    game: &mut GameState,
+    hand_index: usize,
+    tile: &Tile,
+) -> Result<(), String> {
+    let hand = &game.hands[hand_index];
+    if hand.tiles[13] == EMPTY_TILE {
+        return Err("Kan can be declared only after drawing a tile".to_string());
+    }
+    if hand.melds.len() >= 4 {
+        return Err("Can't have more than four melds".to_string());
+    }
+    if game.opened_dora_indicators >= 5 {
+        return Err("No replacement tiles left in the dead wall".to_string());
+    }
+    if game.live_wall.is_empty() {
+        return Err("No tiles left in the live wall".to_string());
+    }
+
+    let same_tiles: Vec<Tile> = get_closed_tiles(hand)
+        .into_iter()
+        .filter(|t| is_same_tile_ignoring_red(t, tile))
+        .collect();
+    if same_tiles.len() != 4 {
+        return Err("Need four identical tiles to declare a kan".to_string());
+    }
+
+    let (meld_tiles, closed_tiles) = split_closed_tiles(hand, &same_tiles)?;
+    let hand = &mut game.hands[hand_index];
+    hand.melds
+        .push(make_meld(MeldType::ClosedKan, meld_tiles, 0)?);
+    set_closed_tiles(hand, closed_tiles);
+
+    return draw_replacement_tile_to_hand(game, hand_index);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input_output;
+    use crate::user_settings::{DEFAULT_GAME_SETTINGS, DEFAULT_SCORE_SETTINGS};
+
+    const TEST_GAME_SETTINGS: GameSettings = GameSettings {
+        deal_first_tile: false,
+        simulate_opponents: true,
+        ..DEFAULT_GAME_SETTINGS
+    };
+
+    #[test]
+    fn test_closed_kan_draws_replacement_tile_from_dead_wall() {
+        let hand = input_output::make_hand_from_string("1111m234567p789s1z").unwrap();
+        let mut game =
+            generate_dealt_game_with_hand_and_discards(1, hand, Vec::new(), &TEST_GAME_SETTINGS, 0)
+                .unwrap();
+        let live_wall_size = game.live_wall.len();
+
+        let kan_tile = input_output::get_tile_from_input("1m");
+        declare_closed_kan(&mut game, 0, &kan_tile).unwrap();
+
+        let hand = &game.hands[0];
+        assert_eq!(hand.melds.len(), 1);
+        assert_eq!(hand.melds[0].meld_type, MeldType::ClosedKan);
+        assert_eq!(hand.tiles[13], game.dead_wall[0]);
+        assert_eq!(get_closed_tiles(hand).len(), 11);
+        assert_eq!(game.opened_dora_indicators, 2);
+        assert_eq!(game.live_wall.len(), live_wall_size - 1);
+    }
+
+    #[test]
+    fn test_fifth_dora_indicator_is_opened_after_four_kans() {
+        let hand = input_output::make_hand_from_string("1111222333444m5m").unwrap();
+        let mut game =
+            generate_dealt_game_with_hand_and_discards(1, hand, Vec::new(), &TEST_GAME_SETTINGS, 0)
+                .unwrap();
+
+        for kan_tile in ["1m", "2m", "3m", "4m"] {
+            let kan_tile = input_output::get_tile_from_input(kan_tile);
+            // the fourth tile of every next kan is drawn instead of the replacement tile
+            if !game.hands[0].melds.is_empty() {
+                game.hands[0].tiles[13] = kan_tile;
+            }
+            declare_closed_kan(&mut game, 0, &kan_tile).unwrap();
+        }
+
+        assert_eq!(game.hands[0].melds.len(), 4);
+        assert_eq!(game.opened_dora_indicators, 5);
+        assert_eq!(
+            get_opened_dora_indicators(&game),
+            &game.dora_indicators[0..5]
+        );
+        assert_eq!(get_opened_dora_indicators(&game)[4], game.dead_wall[8]);
+    }
+
+    #[test]
+    fn test_other_players_draw_and_discard_from_the_shared_wall() {
+        let mut game = generate_normal_dealt_game(4, &TEST_GAME_SETTINGS, 0).unwrap();
+        let live_wall_size = game.live_wall.len();
+        assert_eq!(live_wall_size, 136 - 14 - 13 * 4);
+
+        let discarded_tiles = play_other_players_turns(&mut game, 0, &DEFAULT_SCORE_SETTINGS);
+
+        assert_eq!(discarded_tiles.len(), 3);
+        assert_eq!(game.live_wall.len(), live_wall_size - 3);
+        assert_eq!(
+            game.total_discards_table,
+            make_frequency_table(&discarded_tiles)
+        );
+        for i in 1..4 {
+            assert_eq!(game.discards[i], [discarded_tiles[i - 1]]);
+            assert_eq!(game.hands[i].tiles[13], EMPTY_TILE);
+        }
+    }
+
+    #[test]
+    fn test_the_same_seed_deals_the_same_game() {
+        let first_game = generate_normal_dealt_game(4, &TEST_GAME_SETTINGS, 42).unwrap();
+        let second_game = generate_normal_dealt_game(4, &TEST_GAME_SETTINGS, 42).unwrap();
+        let other_game = generate_normal_dealt_game(4, &TEST_GAME_SETTINGS, 43).unwrap();
+
+        assert_eq!(first_game.seed, 42);
+        assert_eq!(first_game.live_wall, second_game.live_wall);
+        assert_eq!(first_game.dead_wall, second_game.dead_wall);
+        for i in 0..4 {
+            assert_eq!(first_game.hands[i].tiles, second_game.hands[i].tiles);
+        }
+        assert_ne!(first_game.live_wall, other_game.live_wall);
+    }
+
+    #[test]
+    fn test_seed_deals_the_same_hand_in_every_version() {
+        // changing the random generator or the shuffle makes the old seeds deal other hands
+        let game = generate_normal_dealt_game(4, &TEST_GAME_SETTINGS, 42).unwrap();
+        assert_eq!(
+            input_output::get_hand_text(&game.hands[0]),
+            "369m89p459s12266z"
+        );
+    }
+
+    #[test]
+    fn test_single_suit_game_has_only_one_suit() {
+        let game_settings = GameSettings {
+            single_suit: true,
+            ..TEST_GAME_SETTINGS
+        };
+        let game = generate_normal_dealt_game(1, &game_settings, 5).unwrap();
+        let suit = game.hands[0].tiles[0].suit;
+
+        assert!(game
+            .live_wall
+            .iter()
+            .chain(game.dead_wall.iter())
+            .chain(game.hands[0].tiles[..13].iter())
+            .all(|tile| tile.suit == suit));
+        assert!(generate_normal_dealt_game(4, &game_settings, 5).is_err());
+    }
+}
@@ -4,6 +4,7 @@ use image::{DynamicImage, GenericImage, GenericImageView, ImageBuffer, Rgba, Sub
 
 use crate::game_logic::*;
 use crate::ukeire_calculator::*;
+use crate::yaku_calculator::*;
 
 pub type ImageBuf = ImageBuffer<Rgba<u8>, Vec<u8>>;
 
@@ -20,9 +21,9 @@ pub struct ImageRenderData {
 
 pub fn load_sized_image_data(path: &str) -> SizedImageData {
     let atlas = ImageReader::open(path)
-        .expect(&format!("file '{}' not found", path))
+        .unwrap_or_else(|_| panic!("file '{}' not found", path))
         .decode()
-        .expect(&format!("file '{}' can't be decoded", path));
+        .unwrap_or_else(|_| panic!("file '{}' can't be decoded", path));
     let tile_width = atlas.width() / 10;
     let tile_height = atlas.height() / 4;
 
@@ -45,7 +46,7 @@ pub fn load_static_render_data() -> ImageRenderData {
 }
 
 fn get_tile_image<'a>(tile: &Tile, render_data: &'a SizedImageData) -> SubImage<&'a DynamicImage> {
-    let index = get_tile_index(tile);
+    let index = get_display_tile_index(tile);
     let x = (index % 10) as u32;
     let y = (index / 10) as u32;
     render_data.tiles_atlas.view(
@@ -56,7 +57,7 @@ fn get_tile_image<'a>(tile: &Tile, render_data: &'a SizedImageData) -> SubImage<
     )
 }
 
-fn get_back_side_image<'a>(render_data: &'a SizedImageData) -> SubImage<&'a DynamicImage> {
+fn get_back_side_image(render_data: &SizedImageData) -> SubImage<&DynamicImage> {
     render_data.tiles_atlas.view(
         9 * render_data.tile_width,
         3 * render_data.tile_height,
@@ -65,16 +66,102 @@ fn get_back_side_image<'a>(render_data: &'a SizedImageData) -> SubImage<&'a Dyna
     )
 }
 
+fn get_meld_tiles_in_display_order(meld: &Meld) -> Vec<Tile> {
+    let mut tiles = meld.tiles.clone();
+    if meld.meld_type == MeldType::ClosedKan {
+        return tiles;
+    }
+
+    // the called tile is turned sideways and placed according to the player it was called from
+    let called_tile_position = tiles
+        .iter()
+        .position(|t| *t == meld.called_tile)
+        .unwrap_or(0);
+    let called_tile = tiles.remove(called_tile_position);
+    let display_position = match meld.called_from {
+        3 => 0,
+        2 => 1,
+        _ => tiles.len(),
+    };
+    tiles.insert(display_position, called_tile);
+    return tiles;
+}
+
+fn is_displayed_sideways(meld: &Meld, display_position: usize) -> bool {
+    return match meld.called_from {
+        3 => display_position == 0,
+        2 => display_position == 1,
+        1 => display_position == meld.tiles.len() - 1,
+        _ => false,
+    };
+}
+
+fn get_meld_width(meld: &Meld, render_data: &SizedImageData) -> u32 {
+    let sideways_tiles = if meld.meld_type == MeldType::ClosedKan {
+        0
+    } else {
+        1
+    };
+    return render_data.tile_width * (meld.tiles.len() as u32 - sideways_tiles)
+        + render_data.tile_height * sideways_tiles;
+}
+
+fn get_hand_width(hand: &Hand, render_data: &SizedImageData, drawn_tile_gap: u32) -> u32 {
+    let mut width =
+        render_data.tile_width * (get_closed_hand_size(hand) as u32 + 1) + drawn_tile_gap;
+    for meld in &hand.melds {
+        width += drawn_tile_gap + get_meld_width(meld, render_data);
+    }
+    return width;
+}
+
+fn render_meld_to_image(
+    img: &mut impl GenericImage<Pixel = Rgba<u8>>,
+    meld: &Meld,
+    render_data: &SizedImageData,
+    x: u32,
+    y: u32,
+) {
+    let tiles = get_meld_tiles_in_display_order(meld);
+    let mut pos_x = x;
+    for (i, tile) in tiles.iter().enumerate() {
+        let is_face_down = meld.meld_type == MeldType::ClosedKan && (i == 0 || i == 3);
+        let tile_sprite = if is_face_down {
+            get_back_side_image(render_data).to_image()
+        } else {
+            get_tile_image(tile, render_data).to_image()
+        };
+
+        let copy_result = if is_displayed_sideways(meld, i) {
+            let result = img.copy_from(
+                &image::imageops::rotate270(&tile_sprite),
+                pos_x,
+                y + render_data.tile_height - render_data.tile_width,
+            );
+            pos_x += render_data.tile_height;
+            result
+        } else {
+            let result = img.copy_from(&tile_sprite, pos_x, y);
+            pos_x += render_data.tile_width;
+            result
+        };
+        if let Err(e) = copy_result {
+            eprintln!("Error rendering meld tile: {}", e);
+        }
+    }
+}
+
 fn render_hand_to_image(
-    img: &mut (impl GenericImageView<Pixel = Rgba<u8>> + GenericImage),
+    img: &mut impl GenericImage<Pixel = Rgba<u8>>,
     hand: &Hand,
     render_data: &SizedImageData,
     x: u32,
     y: u32,
     drawn_tile_gap: u32,
 ) {
-    for i in 0..13 {
-        let tile_sprite_view = get_tile_image(&hand.tiles[i], &render_data);
+    let closed_hand_size = get_closed_hand_size(hand);
+    for i in 0..closed_hand_size {
+        let tile_sprite_view = get_tile_image(&hand.tiles[i], render_data);
         let copy_result = img.copy_from(
             &tile_sprite_view.to_image(),
             x + render_data.tile_width * i as u32,
@@ -86,20 +173,28 @@ fn render_hand_to_image(
     }
 
     if hand.tiles[13] != EMPTY_TILE {
-        let tile_sprite_view = get_tile_image(&hand.tiles[13], &render_data);
+        let tile_sprite_view = get_tile_image(&hand.tiles[13], render_data);
         let copy_result = img.copy_from(
             &tile_sprite_view.to_image(),
-            x + render_data.tile_width * 13 + drawn_tile_gap,
+            x + render_data.tile_width * closed_hand_size as u32 + drawn_tile_gap,
             y,
         );
         if let Err(e) = copy_result {
             eprintln!("Error rendering drawn tile in hand: {}", e);
         }
     }
+
+    // melds are placed to the right from the drawn tile
+    let mut meld_x =
+        x + render_data.tile_width * (closed_hand_size as u32 + 1) + drawn_tile_gap * 2;
+    for meld in &hand.melds {
+        render_meld_to_image(img, meld, render_data, meld_x, y);
+        meld_x += get_meld_width(meld, render_data) + drawn_tile_gap;
+    }
 }
 
 fn render_discards_to_image(
-    img: &mut (impl GenericImageView<Pixel = Rgba<u8>> + GenericImage),
+    img: &mut impl GenericImage<Pixel = Rgba<u8>>,
     tiles: &[Tile],
     render_data: &SizedImageData,
     x: u32,
@@ -109,7 +204,7 @@ fn render_discards_to_image(
     let mut pos_x = 0;
     let mut pos_y = 0;
     for tile in tiles {
-        let tile_sprite_view = get_tile_image(&tile, &render_data);
+        let tile_sprite_view = get_tile_image(tile, render_data);
         let copy_result = img.copy_from(
             &tile_sprite_view.to_image(),
             x + pos_x * render_data.tile_width,
@@ -127,15 +222,20 @@ fn render_discards_to_image(
 }
 
 fn render_dora_indicators_to_image(
-    img: &mut (impl GenericImageView<Pixel = Rgba<u8>> + GenericImage),
+    img: &mut impl GenericImage<Pixel = Rgba<u8>>,
     dora_indicators: &[Tile],
+    opened_dora_indicators: u8,
     render_data: &SizedImageData,
     x: u32,
     y: u32,
 ) {
+    // the first indicator is always in the same place, if more are opened they shift to the left
+    let first_opened = min(4, 7 - opened_dora_indicators as u32);
+    let last_opened = first_opened + opened_dora_indicators as u32;
+
     for i in 0..7 {
-        if i != 4 {
-            let tile_sprite_view = get_back_side_image(&render_data);
+        if i < first_opened || i >= last_opened {
+            let tile_sprite_view = get_back_side_image(render_data);
             let copy_result = img.copy_from(
                 &tile_sprite_view.to_image(),
                 x + i * render_data.tile_width,
@@ -147,11 +247,12 @@ fn render_dora_indicators_to_image(
         }
     }
 
-    {
-        let tile_sprite_view = get_tile_image(&dora_indicators[0], &render_data);
+    for i in first_opened..last_opened {
+        let tile_sprite_view =
+            get_tile_image(&dora_indicators[(i - first_opened) as usize], render_data);
         let copy_result = img.copy_from(
             &tile_sprite_view.to_image(),
-            x + 4 * render_data.tile_width,
+            x + i * render_data.tile_width,
             y,
         );
         if let Err(e) = copy_result {
@@ -160,25 +261,48 @@ fn render_dora_indicators_to_image(
     }
 }
 
+fn render_other_players_discards_to_image(
+    img: &mut impl GenericImage<Pixel = Rgba<u8>>,
+    discards: &[Vec<Tile>],
+    render_data: &SizedImageData,
+    y: u32,
+    total_width: u32,
+) {
+    let gap = render_data.tile_height / 4;
+    let width = total_width / render_data.tile_width;
+    let x = (total_width - width * render_data.tile_width) / 2;
+    let mut pos_y = y;
+    for player_discards in discards {
+        render_discards_to_image(img, player_discards, render_data, x, pos_y, width);
+        let lines = max(1, (player_discards.len() as u32).div_ceil(width));
+        pos_y += lines * render_data.tile_height + gap;
+    }
+}
+
 pub fn render_game_state(game: &GameState, render_data: &ImageRenderData) -> ImageBuf {
     let total_width_tiles = 14;
     let total_height_tiles = 10;
 
     // choose middle size for game state as it seem to fit the best with the dimensions chosen above
+    let full_render_data = render_data;
     let render_data = &render_data.sizes[1];
 
     let drawn_tile_gap = render_data.tile_width / 4;
     let top_offset = render_data.tile_height / 4;
-    let total_width = render_data.tile_width * total_width_tiles + drawn_tile_gap;
+    // open melds may need more space than a closed hand
+    let total_width = max(
+        render_data.tile_width * total_width_tiles + drawn_tile_gap,
+        get_hand_width(&game.hands[0], render_data, drawn_tile_gap),
+    );
     let total_height = render_data.tile_height * total_height_tiles + top_offset;
 
     let mut img = ImageBuffer::from_pixel(total_width, total_height, render_data.bg_color);
-    let middle_x = (render_data.tile_width * total_width_tiles + drawn_tile_gap) / 2;
+    let middle_x = total_width / 2;
 
     render_hand_to_image(
         &mut img,
         &game.hands[0],
-        &render_data,
+        render_data,
         0,
         top_offset + render_data.tile_height * 9,
         drawn_tile_gap,
@@ -186,7 +310,7 @@ pub fn render_game_state(game: &GameState, render_data: &ImageRenderData) -> Ima
 
     let discards: &Vec<Tile> = &game.discards[0];
     if !discards.is_empty() {
-        let discards_width = min(max(6, 1 + (discards.len() - 1) / 6), 14) as u32;
+        let discards_width = (1 + (discards.len() - 1) / 6).clamp(6, 14) as u32;
         let mut discards_top_shift = (7 - (discards.len() - 1) / discards_width as usize) as u32;
         // after this size tiles won't fit normally anymore, reduce the gaps to fit more
         if discards.len() > 14 * 6 {
@@ -194,18 +318,30 @@ pub fn render_game_state(game: &GameState, render_data: &ImageRenderData) -> Ima
         }
         render_discards_to_image(
             &mut img,
-            &discards,
-            &render_data,
+            discards,
+            render_data,
             middle_x - render_data.tile_width * discards_width / 2,
             top_offset + discards_top_shift * render_data.tile_height,
             discards_width,
         );
     }
 
+    // other players' discards are drawn with smaller tiles under the dora indicators
+    if game.discards.len() > 1 {
+        render_other_players_discards_to_image(
+            &mut img,
+            &game.discards[1..],
+            &full_render_data.sizes[0],
+            top_offset + render_data.tile_height + top_offset,
+            total_width,
+        );
+    }
+
     render_dora_indicators_to_image(
         &mut img,
         &game.dora_indicators,
-        &render_data,
+        get_opened_dora_indicators(game).len() as u8,
+        render_data,
         middle_x - render_data.tile_width * 7 / 2,
         top_offset,
     );
@@ -213,8 +349,25 @@ pub fn render_game_state(game: &GameState, render_data: &ImageRenderData) -> Ima
     return img;
 }
 
+// only the hand of the player, for the quizzes where the table doesn't matter
+pub fn render_hand(hand: &Hand, render_data: &ImageRenderData) -> ImageBuf {
+    let render_data = &render_data.sizes[1];
+    let drawn_tile_gap = render_data.tile_width / 4;
+
+    let mut img = ImageBuffer::from_pixel(
+        get_hand_width(hand, render_data, drawn_tile_gap),
+        render_data.tile_height,
+        render_data.bg_color,
+    );
+    render_hand_to_image(&mut img, hand, render_data, 0, 0, drawn_tile_gap);
+
+    return img;
+}
+
+// the layout is passed explicitly, the same way as to the other render functions
+#[allow(clippy::too_many_arguments)]
 fn render_explanation_line_to_image(
-    img: &mut (impl GenericImageView<Pixel = Rgba<u8>> + GenericImage),
+    img: &mut impl GenericImage<Pixel = Rgba<u8>>,
     discard: &Tile,
     improvements: &[Tile],
     total_improvements: &[Tile],
@@ -224,7 +377,7 @@ fn render_explanation_line_to_image(
     gap_after_discard: u32,
 ) {
     {
-        let tile_sprite_view = get_tile_image(&discard, &render_data);
+        let tile_sprite_view = get_tile_image(discard, render_data);
         let copy_result = img.copy_from(&tile_sprite_view.to_image(), x, y);
         if let Err(e) = copy_result {
             eprintln!("Error rendering first tile in explanation line: {}", e);
@@ -232,9 +385,9 @@ fn render_explanation_line_to_image(
     }
 
     let mut local_i = 0;
-    for i in 0..total_improvements.len() {
-        if total_improvements[i] == improvements[local_i] {
-            let tile_sprite_view = get_tile_image(&improvements[local_i], &render_data);
+    for (i, improvement) in total_improvements.iter().enumerate() {
+        if *improvement == improvements[local_i] {
+            let tile_sprite_view = get_tile_image(&improvements[local_i], render_data);
             let copy_result = img.copy_from(
                 &tile_sprite_view.to_image(),
                 x + gap_after_discard + (i as u32 + 1) * render_data.tile_width,
@@ -262,18 +415,19 @@ pub fn render_move_explanation(
     );
 
     let mut visible_tiles = get_visible_tiles(&previous_move.game_state, previous_move.hand_index);
-    let best_discards = calculate_best_discards_ukeire2(
-        &previous_move.game_state.hands[previous_move.hand_index].tiles,
+    let best_discards = calculate_best_discards(
+        &previous_move.game_state.hands[previous_move.hand_index],
         previous_move.full_hand_shanten,
         &mut visible_tiles,
-        &score_settings,
+        &get_trainer_win_conditions(&previous_move.game_state, previous_move.hand_index),
+        score_settings,
     );
 
     let mut total_improvements: Vec<Tile> = Vec::new();
     for discard_info in &best_discards {
         for improvement in &discard_info.tiles_improving_shanten {
             // todo: linear search should probably be more efficient here
-            match total_improvements.binary_search(&improvement) {
+            match total_improvements.binary_search(improvement) {
                 Ok(_pos) => {}
                 Err(pos) => total_improvements.insert(pos, *improvement),
             }
@@ -320,7 +474,7 @@ pub fn render_move_explanation(
             &discard_info.tile,
             &discard_info.tiles_improving_shanten,
             &total_improvements,
-            &render_data,
+            render_data,
             horizontal_gap,
             pos_y,
             horizontal_gap,
@@ -330,3 +484,98 @@ pub fn render_move_explanation(
 
     return img;
 }
+
+fn draw_rect(img: &mut ImageBuf, x: u32, y: u32, width: u32, height: u32, color: Rgba<u8>) {
+    for pixel_x in x..min(x + width, img.width()) {
+        for pixel_y in y..min(y + height, img.height()) {
+            img.put_pixel(pixel_x, pixel_y, color);
+        }
+    }
+}
+
+// a bar for the efficiency of each hand and a line for the average, the values are from 0 to 1
+pub fn render_progress_chart(
+    efficiencies: &[f32],
+    average_efficiencies: &[f32],
+    render_data: &ImageRenderData,
+) -> ImageBuf {
+    const CHART_WIDTH: u32 = 600;
+    const CHART_HEIGHT: u32 = 300;
+    const MARGIN: u32 = 20;
+    const LINE_THICKNESS: u32 = 3;
+    let grid_color = Rgba([83, 131, 107, 255]);
+    let bar_color = Rgba([150, 190, 170, 255]);
+    let line_color = Rgba([250, 210, 80, 255]);
+
+    let mut img = ImageBuffer::from_pixel(
+        CHART_WIDTH + MARGIN * 2,
+        CHART_HEIGHT + MARGIN * 2,
+        render_data.sizes[0].bg_color,
+    );
+
+    // grid lines at every 25%
+    for i in 0..=4 {
+        draw_rect(
+            &mut img,
+            MARGIN,
+            MARGIN + i * CHART_HEIGHT / 4,
+            CHART_WIDTH,
+            1,
+            grid_color,
+        );
+    }
+
+    if efficiencies.is_empty() {
+        return img;
+    }
+
+    let bar_width = max(CHART_WIDTH / efficiencies.len() as u32, 1);
+    let get_value_y = |value: f32| -> u32 {
+        let height = (value.clamp(0.0, 1.0) * CHART_HEIGHT as f32).round() as u32;
+        return MARGIN + CHART_HEIGHT - height;
+    };
+
+    for (i, efficiency) in efficiencies.iter().enumerate() {
+        let y = get_value_y(*efficiency);
+        draw_rect(
+            &mut img,
+            MARGIN + i as u32 * bar_width + bar_width / 4,
+            y,
+            max(bar_width / 2, 1),
+            MARGIN + CHART_HEIGHT - y,
+            bar_color,
+        );
+    }
+
+    // the line goes through the centers of the bars
+    for (i, average) in average_efficiencies.iter().enumerate() {
+        let x = MARGIN + i as u32 * bar_width + bar_width / 2;
+        let y = get_value_y(*average);
+        let next_y = average_efficiencies
+            .get(i + 1)
+            .map_or(y, |next_average| get_value_y(*next_average));
+        let segment_width = if i + 1 < average_efficiencies.len() {
+            bar_width
+        } else {
+            1
+        };
+        // linear interpolation between the points, each column is filled to connect to the next one
+        for offset in 0..segment_width {
+            let column_y = y as i64 + (next_y as i64 - y as i64) * offset as i64 / bar_width as i64;
+            let next_column_y =
+                y as i64 + (next_y as i64 - y as i64) * (offset as i64 + 1) / bar_width as i64;
+            let top = min(column_y, next_column_y) as u32;
+            let bottom = max(column_y, next_column_y) as u32;
+            draw_rect(
+                &mut img,
+                x + offset,
+                top.saturating_sub(LINE_THICKNESS / 2),
+                1,
+                bottom - top + LINE_THICKNESS,
+                line_color,
+            );
+        }
+    }
+
+    return img;
+}
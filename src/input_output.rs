@@ -19,7 +19,7 @@ const TILE_ENGLISH: [&str; 37] = [
     "seven of man",
     "eight of man",
     "nine of man",
-    "err",
+    "red five of man",
     "one of pin",
     "two of pin",
     "three of pin",
@@ -29,7 +29,7 @@ const TILE_ENGLISH: [&str; 37] = [
     "seven of pin",
     "eight of pin",
     "nine of pin",
-    "err",
+    "red five of pin",
     "one of sou",
     "two of sou",
     "three of sou",
@@ -39,7 +39,7 @@ const TILE_ENGLISH: [&str; 37] = [
     "seven of sou",
     "eight of sou",
     "nine of sou",
-    "err",
+    "red five of sou",
     "east wind",
     "south wind",
     "west wind",
@@ -49,11 +49,13 @@ const TILE_ENGLISH: [&str; 37] = [
     "red dragon",
 ];
 
+#[rustfmt::skip]
 const TILE_JAPANESE: [&str; 37] = [
     "ii wan", "ryan wan", "san wan", "suu wan", "uu wan", "rou wan", "chii wan", "paa wan",
-    "kyuu wan", "err", "ii pin", "ryan pin", "san pin", "suu pin", "uu pin", "rou pin", "chii pin",
-    "paa pin", "kyuu pin", "err", "ii sou", "ryan sou", "san sou", "suu sou", "uu sou", "rou sou",
-    "chii sou", "paa sou", "kyuu sou", "err", "ton", "nan", "shaa", "pei", "haku", "hatsu", "chun",
+    "kyuu wan", "aka uu wan", "ii pin", "ryan pin", "san pin", "suu pin", "uu pin", "rou pin",
+    "chii pin", "paa pin", "kyuu pin", "aka uu pin", "ii sou", "ryan sou", "san sou", "suu sou",
+    "uu sou", "rou sou", "chii sou", "paa sou", "kyuu sou", "aka uu sou", "ton", "nan", "shaa",
+    "pei", "haku", "hatsu", "chun",
 ];
 
 pub fn get_printable_suit(suit: Suit, terms_display: TermsDisplayOption) -> &'static str {
@@ -87,7 +89,7 @@ pub enum TermsDisplayOption {
 pub fn get_capitalized(string: &str) -> String {
     string
         .chars()
-        .nth(0)
+        .next()
         .unwrap_or(' ')
         .to_uppercase()
         .to_string()
@@ -107,7 +109,7 @@ pub fn get_printable_tiles_set_text(tiles: &[Tile], terms_display: TermsDisplayO
             break;
         }
 
-        if result != "" {
+        if !result.is_empty() {
             if tile.suit != last_suit {
                 if last_suit != Suit::Special {
                     result += " ";
@@ -121,7 +123,13 @@ pub fn get_printable_tiles_set_text(tiles: &[Tile], terms_display: TermsDisplayO
 
         last_suit = tile.suit;
 
-        if tile.suit != Suit::Special {
+        if tile.is_red {
+            result += match terms_display {
+                TermsDisplayOption::EnglishTerms => "red ",
+                TermsDisplayOption::JapaneseTerms => "aka ",
+            };
+            result += &tile.value.to_string();
+        } else if tile.suit != Suit::Special {
             result += &tile.value.to_string();
         } else {
             result += tile_to_string(tile, terms_display);
@@ -136,10 +144,64 @@ pub fn get_printable_tiles_set_text(tiles: &[Tile], terms_display: TermsDisplayO
     return result;
 }
 
+// short MPSZ notation, e.g. "123m055p", the same format the hands are entered in
+pub fn get_short_tiles_text(tiles: &[Tile]) -> String {
+    let mut result = String::new();
+
+    for (i, tile) in tiles.iter().enumerate() {
+        result += &if tile.is_red { 0 } else { tile.value }.to_string();
+
+        let is_last_of_suit = tiles
+            .get(i + 1)
+            .is_none_or(|next_tile| next_tile.suit != tile.suit);
+        if is_last_of_suit {
+            result += match tile.suit {
+                Suit::Man => "m",
+                Suit::Pin => "p",
+                Suit::Sou => "s",
+                Suit::Special => "z",
+            };
+        }
+    }
+
+    return result;
+}
+
+// the format make_hand_from_string reads, closed kans are written the same way as open kans
+pub fn get_hand_text(hand: &Hand) -> String {
+    let mut result = get_short_tiles_text(&get_closed_tiles(hand));
+    for meld in &hand.melds {
+        result += "+";
+        result += &get_short_tiles_text(&meld.tiles);
+    }
+    return result;
+}
+
+// tiles from the Mahjong Tiles unicode block, red fives look the same as the other fives
+pub fn get_tiles_glyph_text(tiles: &[Tile]) -> String {
+    return tiles
+        .iter()
+        .map(|tile| {
+            let code_point = match tile.suit {
+                Suit::Man => 0x1F007 + tile.value as u32 - 1,
+                Suit::Sou => 0x1F010 + tile.value as u32 - 1,
+                Suit::Pin => 0x1F019 + tile.value as u32 - 1,
+                Suit::Special => match tile.value {
+                    5 => 0x1F006,                         // haku
+                    6 => 0x1F005,                         // hatsu
+                    7 => 0x1F004,                         // chun
+                    _ => 0x1F000 + tile.value as u32 - 1, // winds are in the same order as 1z-4z
+                },
+            };
+            char::from_u32(code_point).unwrap_or('?')
+        })
+        .collect();
+}
+
 pub fn tile_to_string(tile: &Tile, terms_display: TermsDisplayOption) -> &'static str {
     match terms_display {
-        TermsDisplayOption::EnglishTerms => TILE_ENGLISH[get_tile_index(&tile)],
-        TermsDisplayOption::JapaneseTerms => TILE_JAPANESE[get_tile_index(&tile)],
+        TermsDisplayOption::EnglishTerms => TILE_ENGLISH[get_display_tile_index(tile)],
+        TermsDisplayOption::JapaneseTerms => TILE_JAPANESE[get_display_tile_index(tile)],
     }
 }
 
@@ -151,6 +213,7 @@ pub fn get_tile_from_input(input: &str) -> Tile {
             Tile {
                 suit: Suit::Special,
                 value: 1,
+                is_red: false,
             },
         ),
         (
@@ -158,6 +221,7 @@ pub fn get_tile_from_input(input: &str) -> Tile {
             Tile {
                 suit: Suit::Special,
                 value: 2,
+                is_red: false,
             },
         ),
         (
@@ -165,6 +229,7 @@ pub fn get_tile_from_input(input: &str) -> Tile {
             Tile {
                 suit: Suit::Special,
                 value: 3,
+                is_red: false,
             },
         ),
         (
@@ -172,6 +237,7 @@ pub fn get_tile_from_input(input: &str) -> Tile {
             Tile {
                 suit: Suit::Special,
                 value: 4,
+                is_red: false,
             },
         ),
         (
@@ -179,6 +245,7 @@ pub fn get_tile_from_input(input: &str) -> Tile {
             Tile {
                 suit: Suit::Special,
                 value: 5,
+                is_red: false,
             },
         ),
         (
@@ -186,6 +253,7 @@ pub fn get_tile_from_input(input: &str) -> Tile {
             Tile {
                 suit: Suit::Special,
                 value: 6,
+                is_red: false,
             },
         ),
         (
@@ -193,6 +261,7 @@ pub fn get_tile_from_input(input: &str) -> Tile {
             Tile {
                 suit: Suit::Special,
                 value: 7,
+                is_red: false,
             },
         ),
         (
@@ -200,6 +269,7 @@ pub fn get_tile_from_input(input: &str) -> Tile {
             Tile {
                 suit: Suit::Special,
                 value: 1,
+                is_red: false,
             },
         ),
         (
@@ -207,6 +277,7 @@ pub fn get_tile_from_input(input: &str) -> Tile {
             Tile {
                 suit: Suit::Special,
                 value: 2,
+                is_red: false,
             },
         ),
         (
@@ -214,6 +285,7 @@ pub fn get_tile_from_input(input: &str) -> Tile {
             Tile {
                 suit: Suit::Special,
                 value: 3,
+                is_red: false,
             },
         ),
         (
@@ -221,6 +293,7 @@ pub fn get_tile_from_input(input: &str) -> Tile {
             Tile {
                 suit: Suit::Special,
                 value: 4,
+                is_red: false,
             },
         ),
         (
@@ -228,6 +301,7 @@ pub fn get_tile_from_input(input: &str) -> Tile {
             Tile {
                 suit: Suit::Special,
                 value: 5,
+                is_red: false,
             },
         ),
         (
@@ -235,6 +309,7 @@ pub fn get_tile_from_input(input: &str) -> Tile {
             Tile {
                 suit: Suit::Special,
                 value: 6,
+                is_red: false,
             },
         ),
         (
@@ -242,6 +317,7 @@ pub fn get_tile_from_input(input: &str) -> Tile {
             Tile {
                 suit: Suit::Special,
                 value: 7,
+                is_red: false,
             },
         ),
     ]);
@@ -303,15 +379,13 @@ pub fn get_tile_from_input(input: &str) -> Tile {
             Err(_) => return EMPTY_TILE,
         };
 
-        return Tile { suit, value };
+        return make_tile_from_digit(value, suit).unwrap_or(EMPTY_TILE);
     } else if input.contains(" ") {
         let mut parts = Vec::with_capacity(2);
-        let mut count = 0;
-        for part in input.split_whitespace() {
+        for (count, part) in input.split_whitespace().enumerate() {
             if count >= 2 {
                 return EMPTY_TILE;
             }
-            count += 1;
 
             parts.push(part);
         }
@@ -319,21 +393,20 @@ pub fn get_tile_from_input(input: &str) -> Tile {
             return EMPTY_TILE;
         }
 
-        let value: u8;
-        match values_names.get(parts[0]) {
-            Some(found_value) => value = *found_value,
+        let value: u8 = match values_names.get(parts[0]) {
+            Some(found_value) => *found_value,
             None => return EMPTY_TILE,
-        }
+        };
 
-        let suit: Suit;
-        match suit_names.get(parts[1]) {
-            Some(found_suit) => suit = *found_suit,
+        let suit: Suit = match suit_names.get(parts[1]) {
+            Some(found_suit) => *found_suit,
             None => return EMPTY_TILE,
-        }
+        };
 
         return Tile {
             suit: suit,
             value: value,
+            is_red: false,
         };
     }
 
@@ -343,43 +416,91 @@ pub fn get_tile_from_input(input: &str) -> Tile {
     };
 }
 
+// in MPSZ notation "0" means a red five
+fn make_tile_from_digit(value: u8, suit: Suit) -> Result<Tile, String> {
+    if value == 0 {
+        if suit == Suit::Special {
+            return Err("Only suited tiles can be red fives".to_string());
+        }
+        return Ok(Tile {
+            suit,
+            value: 5,
+            is_red: true,
+        });
+    }
+
+    return Ok(Tile {
+        suit,
+        value,
+        is_red: false,
+    });
+}
+
+// open melds can be added after the closed part of the hand separated by "+", e.g. "1234p55s+777z+456m"
+// the called tiles are assumed to be called from the player on the left
 pub fn make_hand_from_string(hand_string: &str) -> Result<Hand, String> {
     if hand_string.is_empty() {
         return Err("Empty hand".to_string());
     }
 
+    let mut hand_parts = hand_string.split('+');
+    let hand_string = hand_parts.next().unwrap_or_default();
+
+    let mut hand: Hand = EMPTY_HAND;
+    for meld_string in hand_parts {
+        hand.melds.push(make_meld_from_string(meld_string)?);
+    }
+    if hand.melds.len() > 4 {
+        return Err("Too many melds".to_string());
+    }
+    let closed_hand_size = get_closed_hand_size(&hand);
+
     // we can't have a valid hand less than 13 tiles + suit letter
-    if hand_string.len() < 14 {
+    if hand_string.len() < closed_hand_size + 1 {
         return Err("Invalid number of tiles".to_string());
     }
 
     let tiles_count = hand_string.chars().filter(|c| c.is_numeric()).count();
-    if tiles_count < 13 || tiles_count > 14 {
+    if !(closed_hand_size..=closed_hand_size + 1).contains(&tiles_count) {
         return Err("Invalid number of tiles".to_string());
     }
 
-    let mut hand: Hand = EMPTY_HAND;
     let mut current_suit: Option<Suit> = None;
     let mut tile_position = tiles_count;
     for i in (0..hand_string.len()).rev() {
         let Some(letter) = hand_string.chars().nth(i) else {
             return Err("Invalid character".to_string());
         };
-        let value = letter.to_string().parse().unwrap_or(0);
-        if value > 0 {
+        if let Some(value) = letter.to_digit(10) {
             let Some(current_suit) = current_suit else {
                 return Err("Invalid or not specified suit letter".to_string());
             };
             tile_position -= 1;
-            hand.tiles[tile_position].suit = current_suit;
-            hand.tiles[tile_position].value = value;
+            hand.tiles[tile_position] = make_tile_from_digit(value as u8, current_suit)?;
         } else {
             current_suit = get_suit_from_letter(letter);
         }
     }
+
+    // the last tile of a hand with melds goes to the drawn tile slot
+    if tiles_count > closed_hand_size {
+        hand.tiles.swap(closed_hand_size, 13);
+    }
     return Ok(hand);
 }
 
+fn make_meld_from_string(meld_string: &str) -> Result<Meld, String> {
+    let tiles = make_tile_sequence_from_string(meld_string)?;
+    let meld_type = match tiles.len() {
+        3 if is_same_tile_ignoring_red(&tiles[0], &tiles[1]) => MeldType::Pon,
+        3 => MeldType::Chi,
+        4 => MeldType::OpenKan,
+        _ => return Err("A meld should consist of three or four tiles".to_string()),
+    };
+
+    return make_meld(meld_type, tiles, 3);
+}
+
 pub fn make_tile_sequence_from_string(tile_string: &str) -> Result<Vec<Tile>, String> {
     let mut result = Vec::new();
 
@@ -388,12 +509,11 @@ pub fn make_tile_sequence_from_string(tile_string: &str) -> Result<Vec<Tile>, St
         let Some(letter) = tile_string.chars().nth(i) else {
             return Err("Invalid character".to_string());
         };
-        let value = letter.to_string().parse().unwrap_or(0);
-        if value > 0 {
+        if let Some(value) = letter.to_digit(10) {
             let Some(suit) = current_suit else {
                 return Err("Invalid or not specified suit letter".to_string());
             };
-            result.push(Tile { suit, value });
+            result.push(make_tile_from_digit(value as u8, suit)?);
         } else {
             current_suit = get_suit_from_letter(letter);
         }
@@ -407,3 +527,39 @@ pub fn make_tile_sequence_from_string(tile_string: &str) -> Result<Vec<Tile>, St
 
     return Ok(result);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tile_glyphs_have_the_mahjong_tiles_code_points() {
+        let expected_code_points = [
+            ("1z", 0x1F000), // east
+            ("2z", 0x1F001),
+            ("3z", 0x1F002),
+            ("4z", 0x1F003), // north
+            ("5z", 0x1F006), // haku
+            ("6z", 0x1F005), // hatsu
+            ("7z", 0x1F004), // chun
+            ("1m", 0x1F007),
+            ("9m", 0x1F00F),
+            ("1s", 0x1F010),
+            ("9s", 0x1F018),
+            ("1p", 0x1F019),
+            ("9p", 0x1F021),
+            ("0p", 0x1F01D), // the red five has the glyph of 5p
+        ];
+
+        for (tile_text, code_point) in expected_code_points {
+            let tiles = make_tile_sequence_from_string(tile_text).unwrap();
+            let glyph_text = get_tiles_glyph_text(&tiles);
+            assert_eq!(
+                glyph_text.chars().map(|c| c as u32).collect::<Vec<u32>>(),
+                [code_point],
+                "wrong glyph for {}",
+                tile_text
+            );
+        }
+    }
+}
@@ -73,6 +73,8 @@ use serde_json::Value as JsonValue;
 ///        c. document what happened for the future reference
 ///       This way you can limit the damage and make sure to merge the update paths as soon as
 ///       possible
+// the continuation lines of the list items are aligned with the text of the items
+#[allow(clippy::doc_overindented_list_items)]
 pub struct JsonFileUpdater {
     latest_version: String,
     version_field_name: String,
@@ -0,0 +1,22 @@
+// the trainer core that doesn't depend on any frontend,
+// the frontends send the text of the player and show the responses
+pub mod game_logic;
+pub mod image_render;
+pub mod input_output;
+pub mod json_file_updater;
+pub mod mistake_review;
+pub mod puzzles;
+pub mod quizzes;
+pub mod shanten_lookup;
+pub mod trainer;
+pub mod translations;
+pub mod ukeire_calculator;
+pub mod user_settings;
+pub mod user_state;
+pub mod user_state_storage;
+pub mod user_state_updaters;
+pub mod user_stats;
+pub mod win_rate_simulator;
+pub mod yaku_calculator;
+
+extern crate rand;
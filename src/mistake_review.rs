@@ -0,0 +1,282 @@
+use crate::game_logic::*;
+use crate::input_output::*;
+use crate::puzzles::Puzzle;
+use serde::{Deserialize, Serialize};
+
+// discards with lower efficiency than this are added to the mistake log
+pub const MISTAKE_EFFICIENCY_THRESHOLD: f32 = 0.8;
+// the oldest mistakes are removed to keep the saved states small
+const MAX_LOGGED_MISTAKES: usize = 200;
+// days until the next review after each correct answer in a row,
+// the mistake is removed after one more correct answer than there are intervals
+const REVIEW_INTERVALS_DAYS: [u64; 3] = [1, 3, 7];
+const SECONDS_IN_DAY: u64 = 24 * 60 * 60;
+
+// the position is stored in the same format as the puzzles to keep the saved states small
+#[derive(Clone, Serialize, Deserialize)]
+pub struct MistakeRecord {
+    pub hand: String,     // 14 tiles before the discard, open melds go after "+"
+    pub discards: String, // discards of all the players in the order of the turns
+    pub dora_indicators: String,
+    pub player_count: u32,
+    pub discarded_tile: String,
+    pub efficiency: f32,
+    pub timestamp: u64, // seconds since the unix epoch when the mistake was made
+    pub correct_answers_in_row: u32,
+    pub next_review_timestamp: u64,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct MistakeLog {
+    pub mistakes: Vec<MistakeRecord>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ReviewResult {
+    Repeat, // the answer was wrong, the position is going to be served again soon
+    NextReviewInDays(u64),
+    Mastered, // the position is removed from the log
+}
+
+// the discards are taken one by one from every player, the same order the puzzles deal them in
+fn get_discards_in_turn_order(discards: &[Vec<Tile>]) -> Vec<Tile> {
+    let turns = discards.iter().map(|d| d.len()).max().unwrap_or(0);
+    let mut result = Vec::new();
+    for turn in 0..turns {
+        for player_discards in discards {
+            if let Some(tile) = player_discards.get(turn) {
+                result.push(*tile);
+            }
+        }
+    }
+    return result;
+}
+
+// game_state is the state before the discard was made
+pub fn make_mistake_record(
+    game_state: &GameState,
+    discarded_tile: &Tile,
+    efficiency: f32,
+    timestamp: u64,
+) -> MistakeRecord {
+    return MistakeRecord {
+        hand: get_hand_text(&game_state.hands[0]),
+        discards: get_short_tiles_text(&get_discards_in_turn_order(&game_state.discards)),
+        dora_indicators: get_short_tiles_text(get_opened_dora_indicators(game_state)),
+        player_count: game_state.hands.len() as u32,
+        discarded_tile: get_short_tiles_text(&[*discarded_tile]),
+        efficiency: efficiency,
+        timestamp: timestamp,
+        correct_answers_in_row: 0,
+        next_review_timestamp: timestamp,
+    };
+}
+
+pub fn log_mistake(mistake_log: &mut MistakeLog, record: MistakeRecord) {
+    mistake_log.mistakes.push(record);
+    if mistake_log.mistakes.len() > MAX_LOGGED_MISTAKES {
+        let extra_mistakes = mistake_log.mistakes.len() - MAX_LOGGED_MISTAKES;
+        mistake_log.mistakes.drain(0..extra_mistakes);
+    }
+}
+
+// a discard taken back with /undo is not reviewed, its mistake is the last one logged
+// returns false if the last logged mistake is not of this discard
+pub fn remove_last_logged_mistake(
+    mistake_log: &mut MistakeLog,
+    game_state: &GameState,
+    discarded_tile: &Tile,
+) -> bool {
+    let Some(last_mistake) = mistake_log.mistakes.last() else {
+        return false;
+    };
+    let record = make_mistake_record(game_state, discarded_tile, 0.0, 0);
+    if last_mistake.hand != record.hand
+        || last_mistake.discards != record.discards
+        || last_mistake.discarded_tile != record.discarded_tile
+    {
+        return false;
+    }
+
+    mistake_log.mistakes.pop();
+    return true;
+}
+
+// the mistake that has been waiting for the review the longest
+pub fn find_due_mistake(mistake_log: &MistakeLog, now: u64) -> Option<usize> {
+    return mistake_log
+        .mistakes
+        .iter()
+        .enumerate()
+        .filter(|(_, mistake)| mistake.next_review_timestamp <= now)
+        .min_by_key(|(_, mistake)| mistake.next_review_timestamp)
+        .map(|(index, _)| index);
+}
+
+pub fn get_next_review_timestamp(mistake_log: &MistakeLog) -> Option<u64> {
+    return mistake_log
+        .mistakes
+        .iter()
+        .map(|mistake| mistake.next_review_timestamp)
+        .min();
+}
+
+pub fn make_review_puzzle(mistake: &MistakeRecord) -> Result<Puzzle, String> {
+    let discarded_tile = make_tile_sequence_from_string(&mistake.discarded_tile)?;
+    // mistakes on the first turn have no discards before them
+    let discards = if mistake.discards.is_empty() {
+        Vec::new()
+    } else {
+        make_tile_sequence_from_string(&mistake.discards)?
+    };
+    return Ok(Puzzle {
+        id: "review".to_string(),
+        pack_name: "Mistake review".to_string(),
+        hand: make_hand_from_string(&mistake.hand)?,
+        discards: discards,
+        dora_indicators: make_tile_sequence_from_string(&mistake.dora_indicators)?,
+        explanation: Some(format!(
+            "Last time you discarded {} with {}% efficiency",
+            get_short_tiles_text(&discarded_tile),
+            (100.0 * mistake.efficiency).floor()
+        )),
+    });
+}
+
+pub fn update_mistake_after_review(
+    mistake_log: &mut MistakeLog,
+    index: usize,
+    is_correct: bool,
+    now: u64,
+) -> ReviewResult {
+    let Some(mistake) = mistake_log.mistakes.get_mut(index) else {
+        eprintln!("Reviewed mistake {} is not in the log", index);
+        return ReviewResult::Repeat;
+    };
+
+    if !is_correct {
+        mistake.correct_answers_in_row = 0;
+        mistake.next_review_timestamp = now;
+        return ReviewResult::Repeat;
+    }
+
+    let Some(interval_days) = REVIEW_INTERVALS_DAYS
+        .get(mistake.correct_answers_in_row as usize)
+        .copied()
+    else {
+        mistake_log.mistakes.remove(index);
+        return ReviewResult::Mastered;
+    };
+    mistake.correct_answers_in_row += 1;
+    mistake.next_review_timestamp = now + interval_days * SECONDS_IN_DAY;
+    return ReviewResult::NextReviewInDays(interval_days);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::puzzles::make_puzzle_game_state;
+    use crate::user_settings::DEFAULT_GAME_SETTINGS;
+
+    const TEST_GAME_SETTINGS: GameSettings = GameSettings {
+        deal_first_tile: false,
+        red_fives_per_suit: 1,
+        simulate_opponents: true,
+        ..DEFAULT_GAME_SETTINGS
+    };
+
+    #[test]
+    fn test_mistake_position_is_restored() {
+        let hand = make_hand_from_string("2306m235p117s1z+555z").unwrap();
+        let discards = make_tile_sequence_from_string("1z9m2z3z8p4z6z7z").unwrap();
+        let game_state = generate_dealt_game_with_hand_and_discards(
+            4,
+            hand,
+            [[EMPTY_TILE].to_vec(), discards].concat(),
+            &TEST_GAME_SETTINGS,
+            3,
+        )
+        .unwrap();
+
+        let record = make_mistake_record(&game_state, &game_state.hands[0].tiles[0], 0.5, 100);
+        let puzzle = make_review_puzzle(&record).unwrap();
+        let restored_state = make_puzzle_game_state(&puzzle, 4, &TEST_GAME_SETTINGS, 7).unwrap();
+
+        assert_eq!(restored_state.hands[0].tiles, game_state.hands[0].tiles);
+        assert_eq!(restored_state.hands[0].melds.len(), 1);
+        assert_eq!(restored_state.discards, game_state.discards);
+        assert_eq!(
+            get_opened_dora_indicators(&restored_state),
+            get_opened_dora_indicators(&game_state)
+        );
+    }
+
+    #[test]
+    fn test_first_turn_mistake_is_restored() {
+        let hand = make_hand_from_string("2306m235p117s1z555z").unwrap();
+        let game_state = generate_dealt_game_with_hand_and_discards(
+            4,
+            hand,
+            [EMPTY_TILE].to_vec(),
+            &TEST_GAME_SETTINGS,
+            3,
+        )
+        .unwrap();
+
+        let record = make_mistake_record(&game_state, &game_state.hands[0].tiles[0], 0.5, 100);
+        assert_eq!(record.discards, "");
+        let puzzle = make_review_puzzle(&record).unwrap();
+        assert!(puzzle.discards.is_empty());
+        let restored_state = make_puzzle_game_state(&puzzle, 4, &TEST_GAME_SETTINGS, 7).unwrap();
+
+        assert_eq!(restored_state.hands[0].tiles, game_state.hands[0].tiles);
+        assert_eq!(restored_state.discards, game_state.discards);
+    }
+
+    #[test]
+    fn test_mistake_is_reviewed_until_mastered() {
+        let mut mistake_log = MistakeLog::default();
+        for timestamp in [200, 100] {
+            log_mistake(
+                &mut mistake_log,
+                MistakeRecord {
+                    hand: "123456789m1234p5z".to_string(),
+                    discards: String::new(),
+                    dora_indicators: "1m".to_string(),
+                    player_count: 1,
+                    discarded_tile: "1p".to_string(),
+                    efficiency: 0.5,
+                    timestamp: timestamp,
+                    correct_answers_in_row: 0,
+                    next_review_timestamp: timestamp,
+                },
+            );
+        }
+        assert_eq!(find_due_mistake(&mistake_log, 50), None);
+        assert_eq!(find_due_mistake(&mistake_log, 300), Some(1));
+
+        assert_eq!(
+            update_mistake_after_review(&mut mistake_log, 1, true, 300),
+            ReviewResult::NextReviewInDays(1)
+        );
+        assert_eq!(find_due_mistake(&mistake_log, 300), Some(0));
+        assert_eq!(
+            update_mistake_after_review(&mut mistake_log, 1, false, 400),
+            ReviewResult::Repeat
+        );
+        assert_eq!(mistake_log.mistakes[1].correct_answers_in_row, 0);
+
+        for interval_days in REVIEW_INTERVALS_DAYS {
+            assert_eq!(
+                update_mistake_after_review(&mut mistake_log, 1, true, 500),
+                ReviewResult::NextReviewInDays(interval_days)
+            );
+        }
+        assert_eq!(
+            update_mistake_after_review(&mut mistake_log, 1, true, 500),
+            ReviewResult::Mastered
+        );
+        assert_eq!(mistake_log.mistakes.len(), 1);
+        assert_eq!(get_next_review_timestamp(&mistake_log), Some(200));
+    }
+}
@@ -0,0 +1,286 @@
+use crate::game_logic::*;
+use crate::input_output::*;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+// A puzzle pack is a JSON file with this format:
+// {
+//     "name": "Basic efficiency",
+//     "puzzles": [
+//         {
+//             "id": "basic-1",
+//             "hand": "23456m2356p1179s1z",
+//             "discards": "9m1z",
+//             "dora_indicators": "4p",
+//             "explanation": "Honors that are not a pair are the least useful tiles"
+//         }
+//     ]
+// }
+// "hand" is 14 tiles before the discard in MPSZ notation, open melds go after "+" like in /start
+// "discards" are optional, they are given to the players in turns starting from the player
+// "dora_indicators" are one to five tiles
+// "explanation" is optional, it is shown after the answer together with the calculated explanation
+// the ids should be unique among all the packs
+
+#[derive(Deserialize)]
+struct PuzzlePackJson {
+    name: String,
+    puzzles: Vec<PuzzleJson>,
+}
+
+#[derive(Deserialize)]
+struct PuzzleJson {
+    id: String,
+    hand: String,
+    #[serde(default)]
+    discards: Option<String>,
+    dora_indicators: String,
+    #[serde(default)]
+    explanation: Option<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Puzzle {
+    pub id: String,
+    pub pack_name: String,
+    pub hand: Hand,
+    pub discards: Vec<Tile>,
+    pub dora_indicators: Vec<Tile>,
+    pub explanation: Option<String>,
+}
+
+pub struct PuzzlePack {
+    pub name: String,
+    pub puzzles: Vec<Puzzle>,
+}
+
+fn make_puzzle(puzzle_json: PuzzleJson, pack_name: &str) -> Result<Puzzle, String> {
+    let hand = make_hand_from_string(&puzzle_json.hand)?;
+    if hand.tiles[13] == EMPTY_TILE {
+        return Err("The hand should have a tile to discard".to_string());
+    }
+
+    let discards = match &puzzle_json.discards {
+        Some(discards) if !discards.is_empty() => make_tile_sequence_from_string(discards)?,
+        _ => Vec::new(),
+    };
+
+    let dora_indicators = make_tile_sequence_from_string(&puzzle_json.dora_indicators)?;
+    if dora_indicators.len() > MAX_DORA_INDICATORS {
+        return Err(format!(
+            "There can't be more than {} dora indicators",
+            MAX_DORA_INDICATORS
+        ));
+    }
+
+    let puzzle = Puzzle {
+        id: puzzle_json.id,
+        pack_name: pack_name.to_string(),
+        hand: hand,
+        discards: discards,
+        dora_indicators: dora_indicators,
+        explanation: puzzle_json.explanation,
+    };
+
+    // make sure there are enough tiles to deal the position
+    let game_settings = GameSettings {
+        deal_first_tile: false,
+        include_honors: true,
+        red_fives_per_suit: 0,
+        simulate_opponents: true,
+        single_suit: false,
+    };
+    make_puzzle_game_state(&puzzle, 4, &game_settings, 0)?;
+
+    return Ok(puzzle);
+}
+
+// the puzzles that can't be read are skipped, the errors are returned together with the pack
+pub fn parse_puzzle_pack(json: &str) -> Result<(PuzzlePack, Vec<String>), String> {
+    let pack_json: PuzzlePackJson =
+        serde_json::from_str(json).map_err(|err| format!("Invalid puzzle pack: {}", err))?;
+
+    let mut puzzles = Vec::with_capacity(pack_json.puzzles.len());
+    let mut errors = Vec::new();
+    for puzzle_json in pack_json.puzzles {
+        let id = puzzle_json.id.clone();
+        match make_puzzle(puzzle_json, &pack_json.name) {
+            Ok(puzzle) => puzzles.push(puzzle),
+            Err(err) => errors.push(format!("Puzzle \"{}\": {}", id, err)),
+        }
+    }
+
+    return Ok((
+        PuzzlePack {
+            name: pack_json.name,
+            puzzles: puzzles,
+        },
+        errors,
+    ));
+}
+
+// reads all the .json files from the directory
+pub fn load_puzzle_packs(directory: &Path) -> Vec<PuzzlePack> {
+    let mut result = Vec::new();
+
+    let entries = match std::fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(err) => {
+            eprintln!(
+                "Can't read puzzle packs from '{}': {}",
+                directory.display(),
+                err
+            );
+            return result;
+        }
+    };
+
+    let mut paths: Vec<_> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "json")
+        })
+        .collect();
+    paths.sort();
+
+    for path in paths {
+        let data = match std::fs::read_to_string(&path) {
+            Ok(data) => data,
+            Err(err) => {
+                eprintln!("Failed to read puzzle pack '{}': {}", path.display(), err);
+                continue;
+            }
+        };
+        match parse_puzzle_pack(&data) {
+            Ok((pack, errors)) => {
+                for error in errors {
+                    eprintln!("Skipped a puzzle from '{}': {}", path.display(), error);
+                }
+                result.push(pack);
+            }
+            Err(err) => eprintln!("Failed to import puzzle pack '{}': {}", path.display(), err),
+        }
+    }
+
+    return result;
+}
+
+pub fn find_puzzle<'a>(packs: &'a [PuzzlePack], id: &str) -> Option<&'a Puzzle> {
+    return packs
+        .iter()
+        .flat_map(|pack| &pack.puzzles)
+        .find(|puzzle| puzzle.id == id);
+}
+
+// the seed only affects the tiles that are not given in the puzzle
+pub fn make_puzzle_game_state(
+    puzzle: &Puzzle,
+    player_count: u32,
+    game_settings: &GameSettings,
+    seed: u64,
+) -> Result<GameState, String> {
+    // the first discard would be used as the dora indicator
+    let discards = [[EMPTY_TILE].to_vec(), puzzle.discards.clone()].concat();
+    let mut game_state = generate_dealt_game_with_hand_and_discards(
+        player_count,
+        puzzle.hand.clone(),
+        discards,
+        game_settings,
+        seed,
+    )?;
+
+    for (i, indicator) in puzzle.dora_indicators.iter().enumerate() {
+        let Some(position) = find_tile_position(&game_state.live_wall, indicator) else {
+            return Err(format!(
+                "No tiles left for the dora indicator {}",
+                get_short_tiles_text(&[*indicator])
+            ));
+        };
+        // swap the tiles to keep the number of each tile the same
+        std::mem::swap(
+            &mut game_state.live_wall[position],
+            &mut game_state.dead_wall[4 + i],
+        );
+        game_state.dora_indicators[i] = game_state.dead_wall[4 + i];
+    }
+    game_state.opened_dora_indicators = puzzle.dora_indicators.len().max(1) as u8;
+
+    return Ok(game_state);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_puzzle_pack_is_imported() {
+        let pack_json = r#"{
+            "name": "Test pack",
+            "puzzles": [
+                {
+                    "id": "first",
+                    "hand": "23456m2356p1179s1z",
+                    "discards": "9m1z",
+                    "dora_indicators": "4p",
+                    "explanation": "Isolated honor"
+                },
+                {
+                    "id": "second",
+                    "hand": "123m456p789s11z+555z",
+                    "dora_indicators": "1m2m"
+                },
+                {
+                    "id": "not enough tiles",
+                    "hand": "1111m23456p1179s1z",
+                    "dora_indicators": "1m"
+                },
+                {
+                    "id": "thirteen tiles",
+                    "hand": "23456m2356p1179s",
+                    "dora_indicators": "1m"
+                }
+            ]
+        }"#;
+
+        let (pack, errors) = parse_puzzle_pack(pack_json).unwrap();
+        assert_eq!(pack.name, "Test pack");
+        assert_eq!(pack.puzzles.len(), 2);
+        assert_eq!(errors.len(), 2);
+
+        let puzzle = find_puzzle(&[pack], "first").unwrap().clone();
+        assert_eq!(puzzle.discards.len(), 2);
+        assert_eq!(puzzle.explanation.as_deref(), Some("Isolated honor"));
+
+        let game_settings = GameSettings {
+            deal_first_tile: false,
+            include_honors: true,
+            red_fives_per_suit: 0,
+            simulate_opponents: true,
+            single_suit: false,
+        };
+        let game_state = make_puzzle_game_state(&puzzle, 4, &game_settings, 7).unwrap();
+        assert_eq!(
+            get_opened_dora_indicators(&game_state),
+            &puzzle.dora_indicators[..]
+        );
+        assert_eq!(game_state.hands[0].tiles, puzzle.hand.tiles);
+
+        let four_pin_count = [game_state.live_wall.clone(), game_state.dead_wall.to_vec()]
+            .concat()
+            .iter()
+            .chain(game_state.hands.iter().flat_map(|hand| hand.tiles.iter()))
+            .chain(game_state.discards.iter().flatten())
+            .filter(|tile| tile.suit == Suit::Pin && tile.value == 4)
+            .count();
+        assert_eq!(four_pin_count, 4);
+    }
+
+    #[test]
+    fn test_bundled_puzzle_packs_are_valid() {
+        let pack_json = include_str!("../resources/puzzles/basic_efficiency.json");
+        let (pack, errors) = parse_puzzle_pack(pack_json).unwrap();
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert!(!pack.puzzles.is_empty());
+    }
+}
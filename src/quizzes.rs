@@ -0,0 +1,336 @@
+use crate::game_logic::*;
+use crate::ukeire_calculator::*;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+#[cfg(test)]
+use crate::input_output;
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum QuizDifficulty {
+    Easy,   // one or two waits
+    Normal, // any number of waits
+    Hard,   // three or more waits
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct QuizSettings {
+    pub difficulty: QuizDifficulty,
+    pub single_suit: bool,
+    pub include_honors: bool,
+}
+
+// the question the player needs to answer with the next message
+#[derive(Clone, Serialize, Deserialize)]
+pub enum Quiz {
+    Waits { hand_tiles: Vec<Tile> },
+    Shanten { hand_tiles: Vec<Tile> },
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum HandForm {
+    Standard,
+    Chiitoitsu,
+    Kokushi,
+}
+
+pub struct WaitQuizResult {
+    pub missed: Vec<Tile>,
+    pub extra: Vec<Tile>,
+}
+
+const MAX_GENERATION_ATTEMPTS: usize = 1000;
+
+fn get_suit_size(suit: Suit) -> u8 {
+    return if suit == Suit::Special { 7 } else { 9 };
+}
+
+fn make_tile(suit: Suit, value: u8) -> Tile {
+    return Tile {
+        suit: suit,
+        value: value,
+        is_red: false,
+    };
+}
+
+fn get_allowed_suits(quiz_settings: &QuizSettings, rng: &mut impl Rng) -> Vec<Suit> {
+    if quiz_settings.single_suit {
+        return [SUITED_SUITS[rng.gen_range(0..SUITED_SUITS.len())]].to_vec();
+    }
+
+    let mut result = SUITED_SUITS.to_vec();
+    // with fewer suits the groups overlap more often and the waits get more complex
+    if quiz_settings.difficulty == QuizDifficulty::Hard {
+        result.remove(rng.gen_range(0..result.len()));
+    }
+    if quiz_settings.include_honors {
+        result.push(Suit::Special);
+    }
+    return result;
+}
+
+fn try_add_tiles(table: &mut TileFrequencyTable, tiles: &[Tile]) -> bool {
+    let mut new_table = *table;
+    for tile in tiles {
+        new_table[get_tile_index(tile)] += 1;
+        if new_table[get_tile_index(tile)] > 4 {
+            return false;
+        }
+    }
+    *table = new_table;
+    return true;
+}
+
+// a random complete hand of four sets and a pair
+fn generate_complete_hand(suits: &[Suit], rng: &mut impl Rng) -> Vec<Tile> {
+    loop {
+        let mut table = EMPTY_FREQUENCY_TABLE;
+        let mut tiles = Vec::with_capacity(14);
+
+        let pair_suit = suits[rng.gen_range(0..suits.len())];
+        let pair_tile = make_tile(pair_suit, rng.gen_range(1..=get_suit_size(pair_suit)));
+        try_add_tiles(&mut table, &[pair_tile; 2]);
+        tiles.extend([pair_tile; 2]);
+
+        for _ in 0..4 {
+            for _ in 0..MAX_GENERATION_ATTEMPTS {
+                let suit = suits[rng.gen_range(0..suits.len())];
+                let set = if suit != Suit::Special && rng.gen_bool(0.7) {
+                    let first_value = rng.gen_range(1..=7);
+                    [0, 1, 2]
+                        .map(|shift| make_tile(suit, first_value + shift))
+                        .to_vec()
+                } else {
+                    [make_tile(suit, rng.gen_range(1..=get_suit_size(suit))); 3].to_vec()
+                };
+                if try_add_tiles(&mut table, &set) {
+                    tiles.extend(set);
+                    break;
+                }
+            }
+        }
+
+        if tiles.len() == 14 {
+            return tiles;
+        }
+    }
+}
+
+fn is_wait_count_fitting_difficulty(wait_count: usize, difficulty: QuizDifficulty) -> bool {
+    return match difficulty {
+        QuizDifficulty::Easy => wait_count <= 2,
+        QuizDifficulty::Normal => true,
+        QuizDifficulty::Hard => wait_count >= 3,
+    };
+}
+
+// all the tiles that finish the hand, except the ones that the hand already has all four of
+pub fn get_quiz_waits(hand_tiles: &[Tile], settings: &ScoreCalculationSettings) -> Vec<Tile> {
+    let mut all_tiles = Vec::new();
+    for suit in [Suit::Man, Suit::Pin, Suit::Sou, Suit::Special] {
+        for value in 1..=get_suit_size(suit) {
+            all_tiles.push(make_tile(suit, value));
+        }
+    }
+
+    let hand_table = make_frequency_table(hand_tiles);
+    all_tiles.retain(|tile| hand_table[get_tile_index(tile)] < 4);
+    return filter_tiles_finishing_hand(hand_tiles, &all_tiles, settings);
+}
+
+// a sorted 13 tile hand that is one tile away from a complete hand
+pub fn generate_wait_quiz_hand(
+    quiz_settings: &QuizSettings,
+    score_settings: &ScoreCalculationSettings,
+    rng: &mut impl Rng,
+) -> Vec<Tile> {
+    let suits = get_allowed_suits(quiz_settings, rng);
+
+    let mut tiles = Vec::new();
+    for _ in 0..MAX_GENERATION_ATTEMPTS {
+        tiles = generate_complete_hand(&suits, rng);
+        tiles.remove(rng.gen_range(0..tiles.len()));
+        tiles.sort();
+
+        let wait_count = get_quiz_waits(&tiles, score_settings).len();
+        if wait_count > 0 && is_wait_count_fitting_difficulty(wait_count, quiz_settings.difficulty)
+        {
+            break;
+        }
+    }
+    return tiles;
+}
+
+// a complete hand with a few tiles replaced by random ones, the harder the more tiles are replaced
+pub fn generate_shanten_quiz_hand(quiz_settings: &QuizSettings, rng: &mut impl Rng) -> Vec<Tile> {
+    let suits = get_allowed_suits(quiz_settings, rng);
+    let replaced_tiles_count = match quiz_settings.difficulty {
+        QuizDifficulty::Easy => rng.gen_range(1..=2),
+        QuizDifficulty::Normal => rng.gen_range(2..=4),
+        QuizDifficulty::Hard => rng.gen_range(3..=6),
+    };
+
+    let mut tiles = generate_complete_hand(&suits, rng);
+    tiles.remove(rng.gen_range(0..tiles.len()));
+    let mut table = make_frequency_table(&tiles);
+    for _ in 0..replaced_tiles_count {
+        let position = rng.gen_range(0..tiles.len());
+        table[get_tile_index(&tiles[position])] -= 1;
+        loop {
+            let suit = suits[rng.gen_range(0..suits.len())];
+            let tile = make_tile(suit, rng.gen_range(1..=get_suit_size(suit)));
+            if try_add_tiles(&mut table, &[tile]) {
+                tiles[position] = tile;
+                break;
+            }
+        }
+    }
+    tiles.sort();
+    return tiles;
+}
+
+// which form of the hand gives the calculated shanten, standard hands go first when equal
+pub fn get_best_hand_form(tiles: &[Tile], settings: &ScoreCalculationSettings) -> HandForm {
+    let shanten = calculate_shanten(tiles, settings).get_calculated_shanten();
+    let standard_settings = ScoreCalculationSettings {
+        allow_chiitoitsu: false,
+        allow_kokushi: false,
+        ..settings.clone()
+    };
+    if calculate_shanten(tiles, &standard_settings).get_calculated_shanten() == shanten {
+        return HandForm::Standard;
+    }
+
+    let chiitoitsu_settings = ScoreCalculationSettings {
+        allow_chiitoitsu: true,
+        ..standard_settings
+    };
+    if settings.allow_chiitoitsu
+        && calculate_shanten(tiles, &chiitoitsu_settings).get_calculated_shanten() == shanten
+    {
+        return HandForm::Chiitoitsu;
+    }
+    return HandForm::Kokushi;
+}
+
+pub fn check_wait_quiz_answer(waits: &[Tile], answer: &[Tile]) -> WaitQuizResult {
+    let contains_tile = |tiles: &[Tile], tile: &Tile| {
+        tiles
+            .iter()
+            .any(|other| is_same_tile_ignoring_red(tile, other))
+    };
+
+    let missed = waits
+        .iter()
+        .filter(|wait| !contains_tile(answer, wait))
+        .copied()
+        .collect();
+
+    let mut extra: Vec<Tile> = Vec::new();
+    for tile in answer {
+        if !contains_tile(waits, tile) && !contains_tile(&extra, tile) {
+            extra.push(*tile);
+        }
+    }
+
+    return WaitQuizResult {
+        missed: missed,
+        extra: extra,
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::user_settings::DEFAULT_SCORE_SETTINGS;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_wait_quiz_hands_are_tenpai_and_follow_settings() {
+        let mut rng = StdRng::seed_from_u64(1);
+        for difficulty in [
+            QuizDifficulty::Easy,
+            QuizDifficulty::Normal,
+            QuizDifficulty::Hard,
+        ] {
+            for single_suit in [false, true] {
+                let quiz_settings = QuizSettings {
+                    difficulty: difficulty,
+                    single_suit: single_suit,
+                    include_honors: false,
+                };
+                for _ in 0..10 {
+                    let tiles =
+                        generate_wait_quiz_hand(&quiz_settings, &DEFAULT_SCORE_SETTINGS, &mut rng);
+                    assert_eq!(tiles.len(), 13);
+                    assert!(tiles.iter().all(|tile| tile.suit != Suit::Special));
+                    if single_suit {
+                        assert!(tiles.iter().all(|tile| tile.suit == tiles[0].suit));
+                    }
+
+                    let wait_count = get_quiz_waits(&tiles, &DEFAULT_SCORE_SETTINGS).len();
+                    assert!(is_wait_count_fitting_difficulty(wait_count, difficulty));
+                    assert!(wait_count > 0);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_wait_quiz_answer_is_checked() {
+        let tiles = input_output::make_tile_sequence_from_string("1112345678999m").unwrap();
+        let waits = get_quiz_waits(&tiles, &DEFAULT_SCORE_SETTINGS);
+        assert_eq!(waits.len(), 9);
+
+        let answer = input_output::make_tile_sequence_from_string("1234567m1z1z").unwrap();
+        let result = check_wait_quiz_answer(&waits, &answer);
+        assert_eq!(input_output::get_short_tiles_text(&result.missed), "89m");
+        assert_eq!(input_output::get_short_tiles_text(&result.extra), "1z");
+
+        // a tanki on the fifth tile can't be won
+        let tiles = input_output::make_tile_sequence_from_string("1111m234p567s999s").unwrap();
+        assert!(get_quiz_waits(&tiles, &DEFAULT_SCORE_SETTINGS).is_empty());
+    }
+
+    #[test]
+    fn test_shanten_quiz_hands_follow_settings() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let quiz_settings = QuizSettings {
+            difficulty: QuizDifficulty::Easy,
+            single_suit: true,
+            include_honors: true,
+        };
+        for _ in 0..20 {
+            let tiles = generate_shanten_quiz_hand(&quiz_settings, &mut rng);
+            assert_eq!(tiles.len(), 13);
+            assert!(tiles.iter().all(|tile| tile.suit == tiles[0].suit));
+            assert!(make_frequency_table(&tiles).iter().all(|count| *count <= 4));
+            // two replaced tiles can't make the hand more than two steps away
+            let shanten =
+                calculate_shanten(&tiles, &DEFAULT_SCORE_SETTINGS).get_calculated_shanten();
+            assert!(shanten <= 2);
+        }
+    }
+
+    #[test]
+    fn test_best_hand_form_is_found() {
+        let tiles = input_output::make_tile_sequence_from_string("1133557799m113p").unwrap();
+        assert_eq!(
+            get_best_hand_form(&tiles[0..13], &DEFAULT_SCORE_SETTINGS),
+            HandForm::Chiitoitsu
+        );
+
+        let tiles = input_output::make_tile_sequence_from_string("19m19p19s1234567z").unwrap();
+        assert_eq!(
+            get_best_hand_form(&tiles, &DEFAULT_SCORE_SETTINGS),
+            HandForm::Kokushi
+        );
+
+        let tiles = input_output::make_tile_sequence_from_string("123456789m1134p").unwrap();
+        assert_eq!(
+            get_best_hand_form(&tiles[0..13], &DEFAULT_SCORE_SETTINGS),
+            HandForm::Standard
+        );
+    }
+}
@@ -0,0 +1,479 @@
+use crate::game_logic::*;
+use rand::prelude::SliceRandom;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::sync::LazyLock;
+
+// one way to split the tiles of a single suit, all the splits with the same number of sets,
+// protoruns and pairs are merged together
+#[derive(Clone, Copy)]
+struct SuitDecomposition {
+    complete_sets: i8,
+    partial_sets: i8,
+    pair: i8,
+    waits: u16, // tiles completing the protoruns or the pair, bit per tile of the suit
+    single_tile_waits: u16, // tiles that can make a protorun or a pair with the tiles left
+}
+
+// the tables keep ten million decompositions, so they are stored in four bytes each:
+// bits 0-2 - sets, 3-5 - protoruns, 6 - pair, 7-15 - waits, 16-24 - single tile waits
+#[derive(Clone, Copy)]
+struct PackedSuitDecomposition(u32);
+
+fn pack_suit_decomposition(decomposition: &SuitDecomposition) -> PackedSuitDecomposition {
+    return PackedSuitDecomposition(
+        decomposition.complete_sets as u32
+            | (decomposition.partial_sets as u32) << 3
+            | (decomposition.pair as u32) << 6
+            | (decomposition.waits as u32) << 7
+            | (decomposition.single_tile_waits as u32) << 16,
+    );
+}
+
+fn unpack_suit_decomposition(packed: PackedSuitDecomposition) -> SuitDecomposition {
+    return SuitDecomposition {
+        complete_sets: (packed.0 & 0b111) as i8,
+        partial_sets: (packed.0 >> 3 & 0b111) as i8,
+        pair: (packed.0 >> 6 & 1) as i8,
+        waits: (packed.0 >> 7 & 0x1ff) as u16,
+        single_tile_waits: (packed.0 >> 16 & 0x1ff) as u16,
+    };
+}
+
+type SuitKey = usize;
+
+// the decompositions of every pattern of a suit, the pattern key is the base 5 number of the tile counts
+struct SuitTable {
+    // the decompositions of the pattern are at offsets[key]..offsets[key + 1]
+    offsets: Vec<u32>,
+    decompositions: Vec<PackedSuitDecomposition>,
+}
+
+// a closed hand never has more tiles of one suit than this
+const MAX_SUIT_TILES: u8 = 14;
+const MAX_SETS: usize = 4;
+
+// all 405 thousand patterns of a suit that fit in a hand are decomposed when the tables are built,
+// every pattern reuses the decompositions of a smaller one, so it takes seconds, not minutes
+static SUITED_TABLE: LazyLock<SuitTable> = LazyLock::new(|| build_suit_table(9, false));
+static HONORS_TABLE: LazyLock<SuitTable> = LazyLock::new(|| build_suit_table(7, true));
+
+// called on startup, so the first player doesn't wait for the tables
+pub fn build_shanten_lookup_tables() {
+    LazyLock::force(&SUITED_TABLE);
+    LazyLock::force(&HONORS_TABLE);
+}
+
+fn get_suit_key(counts: &[u8]) -> SuitKey {
+    let mut key = 0;
+    let mut multiplier = 1;
+    for count in counts {
+        key += *count as SuitKey * multiplier;
+        multiplier *= 5;
+    }
+    return key;
+}
+
+// sets and protoruns over four, or more than one pair, can't be a part of any hand
+fn is_possible_decomposition(complete_sets: i8, partial_sets: i8, pair: i8) -> bool {
+    return complete_sets <= MAX_SETS as i8
+        && pair <= 1
+        && (partial_sets == 0 || complete_sets + partial_sets <= MAX_SETS as i8);
+}
+
+fn add_suit_decomposition(result: &mut Vec<SuitDecomposition>, new: SuitDecomposition) {
+    if !is_possible_decomposition(new.complete_sets, new.partial_sets, new.pair) {
+        return;
+    }
+    let existing = result.iter_mut().find(|decomposition| {
+        decomposition.complete_sets == new.complete_sets
+            && decomposition.partial_sets == new.partial_sets
+            && decomposition.pair == new.pair
+    });
+    match existing {
+        Some(decomposition) => {
+            decomposition.waits |= new.waits;
+            decomposition.single_tile_waits |= new.single_tile_waits;
+        }
+        None => result.push(new),
+    }
+}
+
+fn get_single_tile_waits(i: usize, tile_count: usize, is_honors: bool) -> u16 {
+    if is_honors {
+        return 1 << i;
+    }
+    let mut waits = 0;
+    for j in i.saturating_sub(2)..=(i + 2).min(tile_count - 1) {
+        waits |= 1 << j;
+    }
+    return waits;
+}
+
+// the groups that use the first tile of the pattern are removed, and the rest of the pattern
+// is taken from the table, the rest always has a smaller key so it's already in the table
+fn decompose_pattern(
+    counts: &mut [u8],
+    is_honors: bool,
+    table: &SuitTable,
+    result: &mut Vec<SuitDecomposition>,
+) {
+    let Some(i) = counts.iter().position(|count| *count > 0) else {
+        result.push(SuitDecomposition {
+            complete_sets: 0,
+            partial_sets: 0,
+            pair: 0,
+            waits: 0,
+            single_tile_waits: 0,
+        });
+        return;
+    };
+    let tile_count = counts.len();
+    let first_count = counts[i];
+    let next_count = if !is_honors && i + 1 < tile_count {
+        counts[i + 1]
+    } else {
+        0
+    };
+    let after_next_count = if !is_honors && i + 2 < tile_count {
+        counts[i + 2]
+    } else {
+        0
+    };
+
+    for pair in 0..=(first_count / 2).min(1) {
+        let count = first_count - 2 * pair;
+        for triplets in 0..=(count / 3) {
+            let count = count - 3 * triplets;
+            for sequences in 0..=count.min(next_count).min(after_next_count) {
+                let count = count - sequences;
+                // edge or side wait protoruns
+                for open_protoruns in 0..=count.min(next_count - sequences) {
+                    // closed wait protoruns
+                    for closed_protoruns in
+                        0..=(count - open_protoruns).min(after_next_count - sequences)
+                    {
+                        let tiles_left = count - open_protoruns - closed_protoruns;
+                        // a pair is only a protorun when no other tiles are left
+                        for pair_protoruns in 0..=(tiles_left == 2) as u8 {
+                            let tiles_left = tiles_left - 2 * pair_protoruns;
+
+                            let mut waits: u16 = 0;
+                            if pair + pair_protoruns > 0 {
+                                waits |= 1 << i;
+                            }
+                            if open_protoruns > 0 {
+                                if i > 0 {
+                                    waits |= 1 << (i - 1);
+                                }
+                                if i + 2 < tile_count {
+                                    waits |= 1 << (i + 2);
+                                }
+                            }
+                            if closed_protoruns > 0 {
+                                waits |= 1 << (i + 1);
+                            }
+                            let single_tile_waits = if tiles_left == 1 {
+                                get_single_tile_waits(i, tile_count, is_honors)
+                            } else {
+                                0
+                            };
+
+                            let removed_tiles = [
+                                first_count,
+                                sequences + open_protoruns,
+                                sequences + closed_protoruns,
+                            ];
+                            for (j, removed) in removed_tiles.iter().enumerate() {
+                                if i + j < tile_count {
+                                    counts[i + j] -= removed;
+                                }
+                            }
+                            let rest_key = get_suit_key(counts);
+                            let rest_range = table.offsets[rest_key] as usize
+                                ..table.offsets[rest_key + 1] as usize;
+                            for rest in &table.decompositions[rest_range] {
+                                let rest = unpack_suit_decomposition(*rest);
+                                add_suit_decomposition(
+                                    result,
+                                    SuitDecomposition {
+                                        complete_sets: rest.complete_sets
+                                            + (triplets + sequences) as i8,
+                                        partial_sets: rest.partial_sets
+                                            + (open_protoruns + closed_protoruns + pair_protoruns)
+                                                as i8,
+                                        pair: rest.pair + pair as i8,
+                                        waits: rest.waits | waits,
+                                        single_tile_waits: rest.single_tile_waits
+                                            | single_tile_waits,
+                                    },
+                                );
+                            }
+                            for (j, removed) in removed_tiles.iter().enumerate() {
+                                if i + j < tile_count {
+                                    counts[i + j] += removed;
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn build_suit_table(tile_count: usize, is_honors: bool) -> SuitTable {
+    let pattern_count = 5usize.pow(tile_count as u32);
+    let mut table = SuitTable {
+        offsets: Vec::with_capacity(pattern_count + 1),
+        decompositions: Vec::new(),
+    };
+    table.offsets.push(0);
+    let mut counts = vec![0; tile_count];
+    let mut result = Vec::new();
+    for key in 0..pattern_count {
+        let mut rest = key;
+        for count in counts.iter_mut() {
+            *count = (rest % 5) as u8;
+            rest /= 5;
+        }
+        // the patterns that can't be in a hand are left empty
+        if counts.iter().sum::<u8>() <= MAX_SUIT_TILES {
+            result.clear();
+            decompose_pattern(&mut counts, is_honors, &table, &mut result);
+            table
+                .decompositions
+                .extend(result.iter().map(pack_suit_decomposition));
+        }
+        table.offsets.push(table.decompositions.len() as u32);
+    }
+    return table;
+}
+
+fn get_suit_decompositions(counts: &[u8], is_honors: bool) -> Vec<SuitDecomposition> {
+    let table: &SuitTable = if is_honors {
+        &HONORS_TABLE
+    } else {
+        &SUITED_TABLE
+    };
+    let key = get_suit_key(counts);
+    return table.decompositions[table.offsets[key] as usize..table.offsets[key + 1] as usize]
+        .iter()
+        .map(|packed| unpack_suit_decomposition(*packed))
+        .collect();
+}
+
+// sets (including melds), protoruns and pairs collected from the suits processed so far
+type CombinationState = (usize, usize, usize);
+// the best shanten that can be reached from the state, indexed by sets, protoruns and pair
+type BestShantenTable = [[[i8; 2]; MAX_SETS + 1]; MAX_SETS + 1];
+
+const UNREACHABLE: i8 = i8::MAX;
+
+fn add_decomposition(
+    state: CombinationState,
+    decomposition: &SuitDecomposition,
+) -> Option<CombinationState> {
+    let new_state = (
+        state.0 + decomposition.complete_sets as usize,
+        state.1 + decomposition.partial_sets as usize,
+        state.2 + decomposition.pair as usize,
+    );
+    // protoruns are not counted after there are four sets and protoruns in total
+    if new_state.0 > MAX_SETS
+        || new_state.2 > 1
+        || (new_state.1 > 0 && new_state.0 + new_state.1 > MAX_SETS)
+    {
+        return None;
+    }
+    return Some(new_state);
+}
+
+// gives the same results as the recursive ShantenCalculator for standard hands,
+// but each suit is split into sets only once, and then the suits are combined together
+// the tables are indexed by the state, iterators would hide which of the tables is read
+#[allow(clippy::needless_range_loop)]
+pub fn calculate_standard_shanten_with_lookup(
+    hand_table: &TileFrequencyTable,
+    meld_count: i8,
+) -> (i8, TileFrequencyTable) {
+    const SUIT_RANGES: [(usize, usize, bool); 4] = [
+        (0, 9, false),
+        (10, 19, false),
+        (20, 29, false),
+        (30, 37, true),
+    ];
+
+    let suits: Vec<Vec<SuitDecomposition>> = SUIT_RANGES
+        .iter()
+        .map(|(start, end, is_honors)| {
+            get_suit_decompositions(&hand_table[*start..*end], *is_honors)
+        })
+        .collect();
+
+    // best shanten reachable from every state before each suit is added, going from the last suit
+    let mut best_shanten_tables = [[[[UNREACHABLE; 2]; MAX_SETS + 1]; MAX_SETS + 1]; 5];
+    for sets in 0..=MAX_SETS {
+        for partial_sets in 0..=MAX_SETS {
+            for pair in 0..2 {
+                if partial_sets == 0 || sets + partial_sets <= MAX_SETS {
+                    best_shanten_tables[4][sets][partial_sets][pair] =
+                        8 - 2 * sets as i8 - partial_sets as i8 - pair as i8;
+                }
+            }
+        }
+    }
+    for suit_index in (0..4).rev() {
+        let mut table: BestShantenTable = [[[UNREACHABLE; 2]; MAX_SETS + 1]; MAX_SETS + 1];
+        for sets in 0..=MAX_SETS {
+            for partial_sets in 0..=MAX_SETS {
+                for pair in 0..2 {
+                    for decomposition in suits[suit_index].iter() {
+                        if let Some(new_state) =
+                            add_decomposition((sets, partial_sets, pair), decomposition)
+                        {
+                            let shanten = best_shanten_tables[suit_index + 1][new_state.0]
+                                [new_state.1][new_state.2];
+                            if shanten < table[sets][partial_sets][pair] {
+                                table[sets][partial_sets][pair] = shanten;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        best_shanten_tables[suit_index] = table;
+    }
+
+    let initial_state: CombinationState = (meld_count as usize, 0, 0);
+    let best_shanten = best_shanten_tables[0][initial_state.0][initial_state.1][initial_state.2];
+
+    // collect the waits from every decomposition of a suit that is a part of a best combination
+    let mut best_waits = EMPTY_FREQUENCY_TABLE;
+    let mut reachable_states = [initial_state].to_vec();
+    for (suit_index, (suit_start, suit_end, _)) in SUIT_RANGES.iter().enumerate() {
+        let mut next_states = Vec::new();
+        for state in &reachable_states {
+            for decomposition in suits[suit_index].iter() {
+                let Some(new_state) = add_decomposition(*state, decomposition) else {
+                    continue;
+                };
+                if best_shanten_tables[suit_index + 1][new_state.0][new_state.1][new_state.2]
+                    != best_shanten
+                {
+                    continue;
+                }
+
+                for i in 0..suit_end - suit_start {
+                    if decomposition.waits & (1 << i) != 0 {
+                        best_waits[suit_start + i] = 2;
+                    } else if decomposition.single_tile_waits & (1 << i) != 0
+                        && best_waits[suit_start + i] == 0
+                    {
+                        best_waits[suit_start + i] = 1;
+                    }
+                }
+                if !next_states.contains(&new_state) {
+                    next_states.push(new_state);
+                }
+            }
+        }
+        reachable_states = next_states;
+    }
+
+    return (best_shanten, best_waits);
+}
+
+fn make_tile_pool(suits: &[Suit]) -> Vec<Tile> {
+    let mut result = Vec::new();
+    for suit in suits {
+        let max_value = if *suit == Suit::Special { 7 } else { 9 };
+        for value in 1..=max_value {
+            for _ in 0..4 {
+                result.push(Tile {
+                    suit: *suit,
+                    value: value,
+                    is_red: false,
+                });
+            }
+        }
+    }
+    return result;
+}
+
+// random hands of every closed size, both from the full set and from a single suit,
+// shared by the tests and the benchmark
+#[doc(hidden)]
+pub fn make_test_corpus(hands_per_kind: usize) -> Vec<Vec<Tile>> {
+    let mut rng = StdRng::seed_from_u64(42);
+    let full_pool = make_tile_pool(&[Suit::Man, Suit::Pin, Suit::Sou, Suit::Special]);
+    let single_suit_pool = make_tile_pool(&[Suit::Pin]);
+
+    let mut result = Vec::new();
+    for hand_size in [14, 13, 11, 10, 8, 7, 5, 4, 2, 1] {
+        for pool in [&full_pool, &single_suit_pool] {
+            let mut pool = pool.clone();
+            for _ in 0..hands_per_kind {
+                pool.shuffle(&mut rng);
+                result.push(pool[0..hand_size].to_vec());
+            }
+        }
+    }
+    return result;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input_output;
+    use crate::ukeire_calculator::*;
+    use crate::user_settings::DEFAULT_SCORE_SETTINGS;
+
+    #[test]
+    fn test_lookup_matches_recursive_calculation() {
+        let corpus = make_test_corpus(300);
+        let settings_variants = [
+            DEFAULT_SCORE_SETTINGS,
+            ScoreCalculationSettings {
+                allow_kokushi: false,
+                allow_chiitoitsu: false,
+                ..DEFAULT_SCORE_SETTINGS
+            },
+        ];
+
+        for settings in &settings_variants {
+            for tiles in &corpus {
+                let lookup = calculate_shanten(tiles, settings);
+                let recursive = calculate_shanten_recursive(tiles, settings);
+                assert_eq!(
+                    lookup.get_calculated_shanten(),
+                    recursive.get_calculated_shanten(),
+                    "shanten differs for {:?}",
+                    tiles
+                );
+                assert_eq!(
+                    lookup.get_best_waits(),
+                    recursive.get_best_waits(),
+                    "waits differ for {:?}",
+                    tiles
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_lookup_finds_complete_and_tenpai_chinitsu_hands() {
+        let complete_hand =
+            input_output::make_tile_sequence_from_string("11122345678999m").unwrap();
+        let (shanten, _) =
+            calculate_standard_shanten_with_lookup(&make_frequency_table(&complete_hand), 0);
+        assert_eq!(shanten, -1);
+
+        let tenpai_hand = input_output::make_tile_sequence_from_string("1112345678999m").unwrap();
+        let (shanten, waits) =
+            calculate_standard_shanten_with_lookup(&make_frequency_table(&tenpai_hand), 0);
+        assert_eq!(shanten, 0);
+        // nine-sided wait, tanki waits come from a single tile left so they are weighted lower
+        assert!(waits[0..9].iter().all(|wait| *wait > 0));
+    }
+}
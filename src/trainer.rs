@@ -0,0 +1,2497 @@
+use rand::seq::SliceRandom;
+use rand::thread_rng;
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::game_logic::*;
+use crate::image_render::*;
+use crate::input_output::*;
+use crate::mistake_review::*;
+use crate::puzzles::*;
+use crate::quizzes::*;
+use crate::shanten_lookup::build_shanten_lookup_tables;
+use crate::translations::*;
+use crate::ukeire_calculator::*;
+use crate::user_settings::*;
+use crate::user_state::*;
+use crate::user_stats::*;
+use crate::win_rate_simulator::*;
+use crate::yaku_calculator::*;
+
+pub static PUZZLE_PACKS_PATH: &str = "./resources/puzzles";
+const INVALID_TILE_MESSAGE: &str = "Entered string doesn't seem to be a tile representation, tile should be a digit followed by 'm', 'p', 's', or 'z' (\"0\" for red fives) or a tile name (e.g. all \"7z\", \"red\", and \"chun\" are acceptable inputs for the red dragon tile)";
+const WIN_RATE_SIMULATION_COUNT: u32 = 200;
+const NO_HAND_IN_PROGRESS_MESSAGE: &str = "No hand is in progress, send /start to start a new hand";
+const SETTINGS_TEXT: &str = "
+Choose terminology:
+/terms_eng - English terminology
+/terms_jap - Japanese terminology
+
+Choose rules:
+/toggle_chiitoi - turn on/off counting for Chiitoitsu
+/toggle_kokushi - turn on/off counting for Kokushi musou
+/toggle_honors - turn on/off honor tiles (from the next game)
+/toggle_opponents - turn on/off simulated opponents drawing from the same wall (from the next game)
+/red_fives <n> - set the number of red fives of each suit (from the next game)
+/toggle_single_suit - turn on/off playing with a wall of only one suit, without opponents (from the next game)
+/toggle_red_five_tiebreak - turn on/off preferring to keep red fives between equal discards
+/toggle_value_ranking - turn on/off weighting the discard score by the value of the hand
+/depth <1|2|3|exact> - set how many steps ahead discards are evaluated (ukeire1, ukeire2, ukeire3, or until the hand is complete)
+
+Choose quiz options:
+/quiz_difficulty <easy|normal|hard> - set the difficulty of the quizzes
+/toggle_quiz_single_suit - turn on/off using only one suit in the quizzes
+/toggle_quiz_honors - turn on/off honor tiles in the quizzes
+
+Choose training aids:
+/toggle_hint - turn on/off /hint that shows the shanten and the number of discards that keep it
+/toggle_undo - turn on/off /undo that takes back the last discard";
+
+// a command of the player, see SETTINGS_TEXT for what the settings commands do
+#[derive(Clone)]
+pub enum TrainerCommand {
+    Start,
+    StartWithHand { hand: Hand, discards: Vec<Tile> },
+    Chinitsu(Option<u64>, Option<DealSettings>), // a random seed if it's not given
+    ChinitsuWaits,
+    Review,
+    Stats,
+    // shows the seed of the current hand if it's not given, the player's settings are used if the deal settings are not given
+    Seed(Option<u64>, Option<DealSettings>),
+    Puzzle(Option<String>), // a random puzzle if the id is not given
+    Puzzles,
+    WaitQuiz,
+    ShantenQuiz,
+    SetQuizDifficulty(QuizDifficulty),
+    ToggleQuizSingleSuit,
+    ToggleQuizHonors,
+    ToggleHint,
+    ToggleUndo,
+    Table,
+    Explain(Option<usize>), // the turn counted from one, the last turn if it's not given
+    ExplainDeep(Option<usize>, Option<usize>), // the turn to explain and the turn to simulate until
+    History,
+    Hint,
+    Undo,
+    Kan(Tile),
+    Settings,
+    SetTerms(TermsDisplayOption),
+    ToggleKokushi,
+    ToggleChiitoi,
+    ToggleHonors,
+    ToggleOpponents,
+    ToggleSingleSuit,
+    SetRedFives(u8),
+    ToggleRedFiveTiebreak,
+    ToggleValueRanking,
+    SetDepth(LookaheadDepth),
+    InfoScore,
+    // a tile to discard, or an answer to the running quiz or puzzle
+    Input(String),
+}
+
+// returns the text to send back to the player if the arguments of the command are invalid
+pub fn parse_command(message_text: &str) -> Result<TrainerCommand, String> {
+    let mut message_split = message_text.split_whitespace();
+
+    let command = match message_split.next() {
+        Some("/start") => match message_split.next() {
+            Some(hand_string) => {
+                let (hand_string, discards_string) = if hand_string.contains("-") {
+                    let mut split = hand_string.split("-");
+                    (split.next().unwrap_or_default(), split.next())
+                } else {
+                    (hand_string, message_split.next())
+                };
+
+                let hand = make_hand_from_string(hand_string).map_err(|err| {
+                    format!("Given string doesn't represent a valid hand: {}", err)
+                })?;
+                let discards = match discards_string {
+                    Some(discards_string) => make_tile_sequence_from_string(discards_string)
+                        .map_err(|err| format!("Discard has invalid format: {}", err))?,
+                    None => Vec::new(),
+                };
+                TrainerCommand::StartWithHand {
+                    hand: hand,
+                    discards: discards,
+                }
+            }
+            None => TrainerCommand::Start,
+        },
+        Some("/chinitsu") => TrainerCommand::Chinitsu(
+            parse_seed(message_split.next())?,
+            parse_deal_code(message_split.next())?,
+        ),
+        Some("/chinitsu_waits") => TrainerCommand::ChinitsuWaits,
+        Some("/review") => TrainerCommand::Review,
+        Some("/stats") => TrainerCommand::Stats,
+        Some("/seed") => TrainerCommand::Seed(
+            parse_seed(message_split.next())?,
+            parse_deal_code(message_split.next())?,
+        ),
+        Some("/puzzle") => TrainerCommand::Puzzle(message_split.next().map(str::to_string)),
+        Some("/puzzles") => TrainerCommand::Puzzles,
+        Some("/wait_quiz") => TrainerCommand::WaitQuiz,
+        Some("/shanten_quiz") => TrainerCommand::ShantenQuiz,
+        Some("/quiz_difficulty") => match message_split.next() {
+            Some("easy") => TrainerCommand::SetQuizDifficulty(QuizDifficulty::Easy),
+            Some("normal") => TrainerCommand::SetQuizDifficulty(QuizDifficulty::Normal),
+            Some("hard") => TrainerCommand::SetQuizDifficulty(QuizDifficulty::Hard),
+            _ => return Err(
+                "Specify the quiz difficulty: easy, normal, or hard, e.g. /quiz_difficulty hard"
+                    .to_string(),
+            ),
+        },
+        Some("/toggle_quiz_single_suit") => TrainerCommand::ToggleQuizSingleSuit,
+        Some("/toggle_quiz_honors") => TrainerCommand::ToggleQuizHonors,
+        Some("/toggle_hint") => TrainerCommand::ToggleHint,
+        Some("/toggle_undo") => TrainerCommand::ToggleUndo,
+        Some("/table") => TrainerCommand::Table,
+        Some("/explain") => TrainerCommand::Explain(parse_turn(message_split.next())?),
+        Some("/explain_deep") => TrainerCommand::ExplainDeep(
+            parse_turn(message_split.next())?,
+            parse_turn(message_split.next())?,
+        ),
+        Some("/history") => TrainerCommand::History,
+        Some("/hint") => TrainerCommand::Hint,
+        Some("/undo") => TrainerCommand::Undo,
+        Some("/kan") => {
+            let tile = get_tile_from_input(&message_split.collect::<Vec<&str>>().join(" "));
+            if tile == EMPTY_TILE {
+                return Err("Specify the tile to declare a kan with, e.g. /kan 7z".to_string());
+            }
+            TrainerCommand::Kan(tile)
+        }
+        Some("/settings") => TrainerCommand::Settings,
+        Some("/terms_eng") => TrainerCommand::SetTerms(TermsDisplayOption::EnglishTerms),
+        Some("/terms_jap") => TrainerCommand::SetTerms(TermsDisplayOption::JapaneseTerms),
+        Some("/toggle_kokushi") => TrainerCommand::ToggleKokushi,
+        Some("/toggle_chiitoi") => TrainerCommand::ToggleChiitoi,
+        Some("/toggle_honors") => TrainerCommand::ToggleHonors,
+        Some("/toggle_opponents") => TrainerCommand::ToggleOpponents,
+        Some("/toggle_single_suit") => TrainerCommand::ToggleSingleSuit,
+        Some("/red_fives") => match message_split.next().map(|count| count.parse::<u8>()) {
+            Some(Ok(count)) => TrainerCommand::SetRedFives(count),
+            _ => {
+                return Err(
+                    "Specify the number of red fives of each suit, e.g. /red_fives 1".to_string(),
+                )
+            }
+        },
+        Some("/toggle_red_five_tiebreak") => TrainerCommand::ToggleRedFiveTiebreak,
+        Some("/toggle_value_ranking") => TrainerCommand::ToggleValueRanking,
+        Some("/depth") => match message_split.next() {
+            Some("1") => TrainerCommand::SetDepth(LookaheadDepth::Ukeire1),
+            Some("2") => TrainerCommand::SetDepth(LookaheadDepth::Ukeire2),
+            Some("3") => TrainerCommand::SetDepth(LookaheadDepth::Ukeire3),
+            Some("exact") => TrainerCommand::SetDepth(LookaheadDepth::Exact),
+            _ => {
+                return Err(
+                    "Specify the lookahead depth: 1, 2, 3, or exact, e.g. /depth 2".to_string(),
+                )
+            }
+        },
+        Some("/info_score") => TrainerCommand::InfoScore,
+        _ => TrainerCommand::Input(message_text.to_string()),
+    };
+    return Ok(command);
+}
+
+fn parse_seed(seed: Option<&str>) -> Result<Option<u64>, String> {
+    let Some(seed) = seed else {
+        return Ok(None);
+    };
+    return match seed.parse::<u64>() {
+        Ok(seed) => Ok(Some(seed)),
+        Err(_) => Err("The seed should be a non-negative integer number".to_string()),
+    };
+}
+
+// the deal code is the number of red fives of each suit,
+// followed by h if the wall has honors and by o if it's dealt to opponents too, e.g. 1ho
+fn parse_deal_code(code: Option<&str>) -> Result<Option<DealSettings>, String> {
+    let Some(code) = code else {
+        return Ok(None);
+    };
+    let error = "The deal settings should be the number of red fives of each suit, followed by h for honors and o for opponents, e.g. 1ho".to_string();
+    let mut chars = code.chars();
+    let red_fives_per_suit = match chars.next().and_then(|c| c.to_digit(10)) {
+        Some(count) if count <= 4 => count as u8,
+        _ => return Err(error),
+    };
+    let (include_honors, simulate_opponents) = match chars.as_str() {
+        "" => (false, false),
+        "h" => (true, false),
+        "o" => (false, true),
+        "ho" => (true, true),
+        _ => return Err(error),
+    };
+    return Ok(Some(DealSettings {
+        include_honors: include_honors,
+        red_fives_per_suit: red_fives_per_suit,
+        simulate_opponents: simulate_opponents,
+    }));
+}
+
+fn make_deal_code(deal_settings: &DealSettings, deal_mode: DealMode) -> String {
+    let mut code = deal_settings.red_fives_per_suit.to_string();
+    // a single suit wall has no honors and no opponents
+    if deal_mode != DealMode::SingleSuit {
+        if deal_settings.include_honors {
+            code += "h";
+        }
+        if deal_settings.simulate_opponents {
+            code += "o";
+        }
+    }
+    return code;
+}
+
+fn get_deal_settings_text(deal_settings: &DealSettings, deal_mode: DealMode) -> String {
+    let red_fives_text = format!(
+        "{} red five{} of each suit",
+        deal_settings.red_fives_per_suit,
+        if deal_settings.red_fives_per_suit == 1 {
+            ""
+        } else {
+            "s"
+        }
+    );
+    if deal_mode == DealMode::SingleSuit {
+        return red_fives_text;
+    }
+    return format!(
+        "honors {}, {}, opponents {}",
+        if deal_settings.include_honors {
+            "on"
+        } else {
+            "off"
+        },
+        red_fives_text,
+        if deal_settings.simulate_opponents {
+            "on"
+        } else {
+            "off"
+        }
+    );
+}
+
+fn parse_turn(turn: Option<&str>) -> Result<Option<usize>, String> {
+    let Some(turn) = turn else {
+        return Ok(None);
+    };
+    return match turn.parse::<usize>() {
+        Ok(turn) => Ok(Some(turn)),
+        Err(_) => Err("The turn should be a number, e.g. /explain 5".to_string()),
+    };
+}
+
+// only the discard options of the last response that has them can be pressed as buttons,
+// the position changes with every new set of options and when a quiz takes the answers
+pub fn process_user_message(
+    user_state: &mut UserState,
+    message_text: &str,
+    static_data: &StaticData,
+) -> Vec<Response> {
+    let command = match parse_command(message_text) {
+        Ok(command) => command,
+        Err(err) => return text_response(err),
+    };
+    let starts_quiz = matches!(
+        command,
+        TrainerCommand::WaitQuiz | TrainerCommand::ShantenQuiz | TrainerCommand::ChinitsuWaits
+    );
+
+    let mut responses = process_command(user_state, command, static_data);
+    let has_discard_options = responses
+        .iter()
+        .any(|response| !response.discard_options.is_empty());
+    if starts_quiz || has_discard_options {
+        user_state.position_id += 1;
+    }
+    for response in &mut responses {
+        if !response.discard_options.is_empty() {
+            response.position_id = user_state.position_id;
+        }
+    }
+    return responses;
+}
+
+// e.g. "12 5m", the position is checked when the button is pressed
+pub fn make_discard_button_data(position_id: u64, tile_text: &str) -> String {
+    return format!("{} {}", position_id, tile_text);
+}
+
+pub fn process_discard_button(
+    user_state: &mut UserState,
+    button_data: &str,
+    static_data: &StaticData,
+) -> Vec<Response> {
+    let Some((position_id, tile_text)) = button_data.split_once(' ') else {
+        return text_response("Unknown button");
+    };
+    if position_id.parse::<u64>() != Ok(user_state.position_id) {
+        return text_response(
+            "This button is from an earlier position, use the buttons of the last message",
+        );
+    }
+    return process_user_message(user_state, tile_text, static_data);
+}
+
+fn start_game(user_state: &mut UserState, static_data: &StaticData) -> Vec<Response> {
+    start_new_move_history(user_state);
+    let Some(game_state) = &user_state.game_state else {
+        eprintln!("No game state when trying to start a game");
+        return text_response(NO_HAND_IN_PROGRESS_MESSAGE);
+    };
+
+    user_state.puzzle = None;
+    user_state.reviewed_mistake = None;
+    user_state.quiz = None;
+    user_state.current_score = 0;
+    user_state.best_score = 0;
+    user_state.efficiency_sum = 0.0;
+    user_state.moves = 0;
+    user_state.mistakes_by_shanten = [0; TRACKED_SHANTEN_LEVELS];
+    user_state.undo_count = 0;
+    let mut text = "Dealt new hand".to_string();
+    // 36 tiles are only enough for the dead wall, the hand and 9 draws
+    if game_state.deal_mode == DealMode::SingleSuit {
+        text += &format!(
+            "\nOnly {} tiles are left to draw in the wall of one suit",
+            game_state.live_wall.len()
+        );
+    }
+    return game_state_response(game_state, static_data, text);
+}
+
+// the moves of the previous hand are kept to be explained until the next hand is started
+fn start_new_move_history(user_state: &mut UserState) {
+    if !user_state.move_history.is_empty() {
+        user_state.last_hand_move_history = std::mem::take(&mut user_state.move_history);
+    }
+}
+
+// the moves of the previous hand are shown until the first discard of the current hand is made
+fn get_shown_move_history<'a>(
+    move_history: &'a [MoveHistoryEntry],
+    last_hand_move_history: &'a [MoveHistoryEntry],
+) -> &'a [MoveHistoryEntry] {
+    if move_history.is_empty() {
+        return last_hand_move_history;
+    }
+    return move_history;
+}
+
+// the turns are counted from one, the last turn is used if the turn is not specified
+fn find_move_history_entry(
+    history: &[MoveHistoryEntry],
+    turn: Option<usize>,
+) -> Result<&MoveHistoryEntry, String> {
+    let Some(turn) = turn else {
+        return history
+            .last()
+            .ok_or("No moves are recorded to explain".to_string());
+    };
+    return turn
+        .checked_sub(1)
+        .and_then(|index| history.get(index))
+        .ok_or(format!(
+            "There is no turn {}, {} turns are recorded, send /history to see them",
+            turn,
+            history.len()
+        ));
+}
+
+// e.g. "5. Nine of sou 960/1200, 2 -> 2 shanten"
+fn get_move_history_text(history: &[MoveHistoryEntry], user_settings: &UserSettings) -> String {
+    let mut result = String::new();
+    for (i, entry) in history.iter().enumerate() {
+        let hand = &entry.move_data.game_state.hands[entry.move_data.hand_index];
+        // the shanten of the hand before the tile was drawn
+        let shanten_before = calculate_shanten(
+            &hand.tiles[0..get_closed_hand_size(hand)],
+            &user_settings.score_settings,
+        )
+        .get_calculated_shanten();
+        result += &format!(
+            "{}. {} {}/{}, {} -> {} shanten{}\n",
+            i + 1,
+            get_capitalized(tile_to_string(
+                &entry.move_data.discarded_tile,
+                user_settings.display_settings.terms_display
+            )),
+            entry.score,
+            entry.best_score,
+            shanten_before,
+            entry.shanten_after_discard,
+            if entry.is_best_discard {
+                ""
+            } else {
+                " (not the best)"
+            }
+        );
+    }
+    return result;
+}
+
+// doesn't tell which discards are the best, only how many of them there are
+fn get_hint_text(game_state: &GameState, user_settings: &UserSettings) -> String {
+    let hand = &game_state.hands[0];
+    if hand.tiles[13] == EMPTY_TILE {
+        return "There is no tile to discard".to_string();
+    }
+
+    let full_hand_shanten =
+        calculate_shanten(&get_closed_tiles(hand), &user_settings.score_settings)
+            .get_calculated_shanten();
+    if full_hand_shanten < 0 {
+        return get_shanten_text(full_hand_shanten);
+    }
+    let best_discards = calculate_best_discards(
+        hand,
+        full_hand_shanten,
+        &mut get_visible_tiles(game_state, 0),
+        &get_trainer_win_conditions(game_state, 0),
+        &user_settings.score_settings,
+    );
+    let best_discard_scores = get_best_discard_scores(&best_discards);
+
+    return format!(
+        "{} after the discard\n{} discards don't go back in shanten, {} of them have the best score",
+        get_shanten_text(full_hand_shanten),
+        best_discards.len(),
+        best_discard_scores.tiles.len()
+    );
+}
+
+// a wall of a single suit has only enough tiles for one player
+fn get_player_count(game_settings: &GameSettings) -> u32 {
+    return if game_settings.simulate_opponents && !game_settings.single_suit {
+        4
+    } else {
+        1
+    };
+}
+
+// the same seed deals the same wall only with the same deal settings, so they are a part of the command
+fn get_replay_command(game_state: &GameState) -> Option<String> {
+    let deal_settings = game_state.deal_settings.as_ref()?;
+    let command = match game_state.deal_mode {
+        DealMode::Shuffled => "/seed",
+        DealMode::SingleSuit => "/chinitsu",
+        DealMode::GivenHand => return None,
+    };
+    return Some(format!(
+        "{} {} {}",
+        command,
+        game_state.seed,
+        make_deal_code(deal_settings, game_state.deal_mode)
+    ));
+}
+
+// the wall of a replayed hand is dealt with the given deal settings, the player's settings are not changed
+fn apply_deal_settings(
+    game_settings: &GameSettings,
+    deal_settings: &Option<DealSettings>,
+) -> GameSettings {
+    return match deal_settings {
+        Some(deal_settings) => GameSettings {
+            include_honors: deal_settings.include_honors,
+            red_fives_per_suit: deal_settings.red_fives_per_suit,
+            simulate_opponents: deal_settings.simulate_opponents,
+            ..game_settings.clone()
+        },
+        None => game_settings.clone(),
+    };
+}
+
+fn get_replayed_deal_text(game_state: &GameState, deal_settings_were_given: bool) -> String {
+    let Some(deal_settings) = &game_state.deal_settings else {
+        return String::new();
+    };
+    let deal_settings_text = get_deal_settings_text(deal_settings, game_state.deal_mode);
+    return if deal_settings_were_given {
+        format!(
+            "\nDealt with the settings of the replayed hand: {}, your settings are not changed",
+            deal_settings_text
+        )
+    } else {
+        format!(
+            "\nDealt with your settings: {}, the seed deals the same wall only with the same settings",
+            deal_settings_text
+        )
+    };
+}
+
+fn get_seed_text(game_state: &GameState) -> String {
+    return match get_replay_command(game_state) {
+        Some(command) => format!(
+            "\nSeed: {} (send {} to replay this hand)",
+            game_state.seed, command
+        ),
+        None => String::new(),
+    };
+}
+
+fn get_waits_value_text(
+    game_state: &GameState,
+    wait_tiles: &[Tile],
+    user_settings: &UserSettings,
+) -> String {
+    let waits_value = calculate_waits_value(
+        &get_closed_tiles(&game_state.hands[0]),
+        &game_state.hands[0].melds,
+        wait_tiles,
+        &get_visible_tiles(game_state, 0),
+        &get_trainer_win_conditions(game_state, 0),
+    );
+
+    let mut result = String::new();
+    for wait in &waits_value {
+        let tile_string = tile_to_string(&wait.tile, user_settings.display_settings.terms_display);
+        if wait.value.yaku.is_empty() {
+            result += &format!("{}: no yaku\n", get_capitalized(tile_string));
+            continue;
+        }
+
+        let mut yaku_names: Vec<String> = wait
+            .value
+            .yaku
+            .iter()
+            .map(|yaku_han| get_yaku_name(yaku_han.yaku).to_string())
+            .collect();
+        if wait.value.dora > 0 {
+            yaku_names.push(format!("Dora {}", wait.value.dora));
+        }
+        result += &format!(
+            "{}: {} ({} han {} fu: {})\n",
+            get_capitalized(tile_string),
+            wait.value.points,
+            wait.value.han,
+            wait.value.fu,
+            yaku_names.join(", ")
+        );
+    }
+    result += &format!(
+        "Expected value: {}\n",
+        get_waits_expected_value(&waits_value).round()
+    );
+
+    return result;
+}
+
+// e.g. "234m 111p 999s 55z + 56m: 47m", the waiting part goes after the complete sets
+fn get_wait_shape_text(closed_tiles: &[Tile]) -> String {
+    let Some(wait_shape) = classify_wait_shape(closed_tiles) else {
+        return String::new();
+    };
+
+    let mut result = format!(
+        "Wait shape: {} on {}\n",
+        get_wait_shape_name(wait_shape.shape),
+        get_short_tiles_text(&wait_shape.waits)
+    );
+    for reading in &wait_shape.readings {
+        let mut parts: Vec<String> = reading
+            .sets
+            .iter()
+            .map(|set| get_short_tiles_text(set))
+            .collect();
+        parts.push("+ ".to_string() + &get_short_tiles_text(&reading.waiting_tiles));
+        result += &format!(
+            "{}: {} ({})\n",
+            parts.join(" "),
+            get_short_tiles_text(&reading.waits),
+            get_wait_shape_name(reading.shape)
+        );
+    }
+
+    return result;
+}
+
+// e.g. "123m 456p, pair 11s, protoruns 34m 68p, floating 9s"
+fn get_decomposition_text(decomposition: &HandDecomposition) -> String {
+    let mut parts = Vec::new();
+    if !decomposition.sets.is_empty() {
+        let sets: Vec<String> = decomposition
+            .sets
+            .iter()
+            .map(|set| get_short_tiles_text(set))
+            .collect();
+        parts.push(sets.join(" "));
+    }
+    if let Some(pair) = &decomposition.pair {
+        parts.push(format!("pair {}", get_short_tiles_text(pair)));
+    }
+    if !decomposition.protoruns.is_empty() {
+        let protoruns: Vec<String> = decomposition
+            .protoruns
+            .iter()
+            .map(|protorun| get_short_tiles_text(protorun))
+            .collect();
+        parts.push(format!("protoruns {}", protoruns.join(" ")));
+    }
+    if !decomposition.floating_tiles.is_empty() {
+        parts.push(format!(
+            "floating {}",
+            get_short_tiles_text(&decomposition.floating_tiles)
+        ));
+    }
+    return parts.join(", ");
+}
+
+// shows how the hand is split after the discard, to explain where the ukeire comes from
+fn get_hand_shape_after_discard_text(hand: &Hand, discard: &Tile) -> String {
+    const MAX_SHOWN_DECOMPOSITIONS: usize = 3;
+
+    let mut tiles = get_closed_tiles(hand);
+    let Some(position) = find_tile_position(&tiles, discard) else {
+        return String::new();
+    };
+    tiles.remove(position);
+
+    let decompositions = find_optimal_decompositions(&tiles);
+    let mut result = format!("After discarding {}:\n", get_short_tiles_text(&[*discard]));
+    for decomposition in decompositions.iter().take(MAX_SHOWN_DECOMPOSITIONS) {
+        result += &get_decomposition_text(decomposition);
+        result += "\n";
+    }
+    if decompositions.len() > MAX_SHOWN_DECOMPOSITIONS {
+        result += &format!(
+            "and {} more ways to read the hand\n",
+            decompositions.len() - MAX_SHOWN_DECOMPOSITIONS
+        );
+    }
+    return result;
+}
+
+fn get_move_explanation_text(
+    previous_move: &PreviousMoveData,
+    user_settings: &UserSettings,
+) -> String {
+    assert_ne!(
+        previous_move.game_state.hands[previous_move.hand_index].tiles[13], EMPTY_TILE,
+        "Expected move state hand have 14 tiles before the discard"
+    );
+
+    let mut visible_tiles = get_visible_tiles(&previous_move.game_state, previous_move.hand_index);
+    let best_discards = calculate_best_discards(
+        &previous_move.game_state.hands[previous_move.hand_index],
+        previous_move.full_hand_shanten,
+        &mut visible_tiles,
+        &get_trainer_win_conditions(&previous_move.game_state, previous_move.hand_index),
+        &user_settings.score_settings,
+    );
+
+    if best_discards.is_empty() {
+        return "No appropriate discards. This shouldn't happen. Please report this error to the developers".to_string();
+    }
+
+    let mut result = String::new();
+    for discard_info in &best_discards {
+        let tile_string = tile_to_string(
+            &discard_info.tile,
+            user_settings.display_settings.terms_display,
+        );
+        result += &format!("{}: {}\n", get_capitalized(tile_string), discard_info.score,)
+    }
+    result += &get_hand_shape_after_discard_text(
+        &previous_move.game_state.hands[previous_move.hand_index],
+        &best_discards[0].tile,
+    );
+
+    return result;
+}
+
+// the hand is simulated until the given turn, or until the end of the wall
+fn get_simulated_move_explanation_text(
+    previous_move: &PreviousMoveData,
+    move_turn: usize,
+    until_turn: Option<usize>,
+    user_settings: &UserSettings,
+) -> String {
+    let game_state = &previous_move.game_state;
+    let turns_left = get_turns_left(game_state);
+    let turns = match until_turn {
+        Some(until_turn) if until_turn <= move_turn => {
+            return format!(
+                "The turn to simulate until should be after turn {}, e.g. /explain_deep {} {}",
+                move_turn,
+                move_turn,
+                move_turn + 6
+            );
+        }
+        Some(until_turn) => (until_turn - move_turn).min(turns_left),
+        None => turns_left,
+    };
+
+    let conditions = get_trainer_win_conditions(game_state, previous_move.hand_index);
+    let best_discards = calculate_best_discards(
+        &game_state.hands[previous_move.hand_index],
+        previous_move.full_hand_shanten,
+        &mut get_visible_tiles(game_state, previous_move.hand_index),
+        &conditions,
+        &user_settings.score_settings,
+    );
+    let discards: Vec<Tile> = best_discards
+        .iter()
+        .map(|discard_info| discard_info.tile)
+        .collect();
+
+    let simulation_results = simulate_discards(
+        game_state,
+        previous_move.hand_index,
+        &discards,
+        WIN_RATE_SIMULATION_COUNT,
+        turns,
+        &conditions,
+        &user_settings.score_settings,
+    );
+    if simulation_results.is_empty() {
+        return "No appropriate discards. This shouldn't happen. Please report this error to the developers".to_string();
+    }
+
+    let mut result = format!(
+        "Chances to reach tenpai and to win by turn {} (the wall ends on turn {}, {} simulations):\n",
+        move_turn + turns,
+        move_turn + turns_left,
+        WIN_RATE_SIMULATION_COUNT
+    );
+    for simulation_result in simulation_results {
+        result += &format!(
+            "{}: tenpai {}%, win {}%\n",
+            get_capitalized(tile_to_string(
+                &simulation_result.tile,
+                user_settings.display_settings.terms_display
+            )),
+            (100.0 * simulation_result.tenpai_chance).round(),
+            (100.0 * simulation_result.win_chance).round()
+        );
+    }
+    result += "The simulation keeps only the tiles that improve shanten, \
+        and wins are counted both from own draws and from other players' discards, \
+        only for the waits that have a yaku";
+
+    return result;
+}
+
+fn record_finished_hand(user_state: &mut UserState, seed: u64, turns_to_tenpai: Option<u32>) {
+    if user_state.moves == 0 {
+        return;
+    }
+
+    record_hand(
+        &mut user_state.stats,
+        HandRecord {
+            timestamp: get_current_timestamp(),
+            seed: seed,
+            efficiency: user_state.efficiency_sum / user_state.moves as f32,
+            moves: user_state.moves,
+            turns_to_tenpai: turns_to_tenpai,
+            mistakes_by_shanten: user_state.mistakes_by_shanten,
+            undo_count: user_state.undo_count,
+        },
+    );
+    user_state.stats_unsaved = true;
+}
+
+// e.g. "Last week: 12 hands, efficiency 84% (+3% to all time), tenpai in 10 hands in 9.5 turns"
+fn get_stats_summary_text(
+    period_name: &str,
+    summary: &StatsSummary,
+    all_time_summary: &StatsSummary,
+) -> String {
+    let mut result = format!(
+        "{}: {} hands, efficiency {}%",
+        period_name,
+        summary.hand_count,
+        (100.0 * summary.average_efficiency).floor()
+    );
+    if summary.hand_count != all_time_summary.hand_count {
+        result += &format!(
+            " ({:+}% to all time)",
+            (100.0 * (summary.average_efficiency - all_time_summary.average_efficiency)).round()
+        );
+    }
+    if summary.tenpai_count > 0 {
+        result += &format!(
+            ", tenpai in {} hands in {:.1} turns",
+            summary.tenpai_count, summary.average_turns_to_tenpai
+        );
+    }
+    result += "\n";
+    return result;
+}
+
+fn get_stats_response(user_state: &UserState, static_data: &StaticData) -> Vec<Response> {
+    const CHART_HAND_COUNT: usize = 50;
+    const CHART_AVERAGE_WINDOW: usize = 10;
+
+    let Some(all_time_summary) = summarize_hands_since(&user_state.stats, 0) else {
+        return text_response("No finished hands are recorded yet, send /start to play a hand");
+    };
+
+    let now = get_current_timestamp();
+    let mut text = String::new();
+    let periods = [
+        ("Last day", now.saturating_sub(SECONDS_IN_DAY)),
+        ("Last week", now.saturating_sub(7 * SECONDS_IN_DAY)),
+    ];
+    for (period_name, since_timestamp) in periods {
+        if let Some(summary) = summarize_hands_since(&user_state.stats, since_timestamp) {
+            text += &get_stats_summary_text(period_name, &summary, &all_time_summary);
+        }
+    }
+    text += &get_stats_summary_text("All time", &all_time_summary, &all_time_summary);
+
+    let mistakes: Vec<String> = (0..TRACKED_SHANTEN_LEVELS)
+        .rev()
+        .filter(|&i| all_time_summary.mistakes_by_shanten[i] > 0)
+        .map(|i| {
+            let level_name = if i == TRACKED_SHANTEN_LEVELS - 1 {
+                format!("{}+ shanten", i)
+            } else if i == 0 {
+                "tenpai".to_string()
+            } else {
+                format!("{} shanten", i)
+            };
+            format!(
+                "{}: {}",
+                level_name, all_time_summary.mistakes_by_shanten[i]
+            )
+        })
+        .collect();
+    if !mistakes.is_empty() {
+        text += &format!(
+            "Not the best discards by shanten: {}\n",
+            mistakes.join(", ")
+        );
+    }
+    if all_time_summary.hands_with_undo_count > 0 {
+        text += &format!(
+            "Hands replayed with /undo: {}\n",
+            all_time_summary.hands_with_undo_count
+        );
+    }
+
+    let all_efficiencies: Vec<f32> = user_state
+        .stats
+        .hands
+        .iter()
+        .map(|hand| hand.efficiency)
+        .collect();
+    let average_efficiencies = get_rolling_average(&all_efficiencies, CHART_AVERAGE_WINDOW);
+    let first_shown_hand = all_efficiencies.len().saturating_sub(CHART_HAND_COUNT);
+    text += &format!(
+        "The chart shows the efficiency of the last {} hands and the average of every {} hands",
+        all_efficiencies.len() - first_shown_hand,
+        CHART_AVERAGE_WINDOW
+    );
+
+    return image_response(
+        render_progress_chart(
+            &all_efficiencies[first_shown_hand..],
+            &average_efficiencies[first_shown_hand..],
+            &static_data.render_data,
+        ),
+        text,
+    );
+}
+
+fn get_review_response(user_state: &mut UserState, static_data: &StaticData) -> Vec<Response> {
+    let now = get_current_timestamp();
+    let Some(mistake_index) = find_due_mistake(&user_state.mistake_log, now) else {
+        return match get_next_review_timestamp(&user_state.mistake_log) {
+            Some(next_review_timestamp) => text_response(format!(
+                "No mistakes to review now, the next review is in {} hours",
+                (next_review_timestamp - now).div_ceil(60 * 60)
+            )),
+            None => text_response(
+                "No mistakes to review, discards that lose a lot of efficiency are added here",
+            ),
+        };
+    };
+
+    let mistake = &user_state.mistake_log.mistakes[mistake_index];
+    let game_state = make_review_puzzle(mistake).and_then(|puzzle| {
+        let game_state = make_puzzle_game_state(
+            &puzzle,
+            mistake.player_count,
+            &user_state.settings.game_settings,
+            generate_random_seed(),
+        )?;
+        return Ok((puzzle, game_state));
+    });
+    let (puzzle, game_state) = match game_state {
+        Ok(result) => result,
+        Err(err) => {
+            // a position that can't be restored would block the review forever
+            eprintln!("Failed to restore a mistake position: {}", err);
+            user_state.mistake_log.mistakes.remove(mistake_index);
+            user_state.stats_unsaved = true;
+            return text_response(
+                "Failed to restore the position. Send /review to try the next one",
+            );
+        }
+    };
+
+    let due_count = user_state
+        .mistake_log
+        .mistakes
+        .iter()
+        .filter(|mistake| mistake.next_review_timestamp <= now)
+        .count();
+    let response = game_state_response(
+        &game_state,
+        static_data,
+        format!(
+            "Mistake review ({} positions to review)\nWhat would you discard?",
+            due_count
+        ),
+    );
+    user_state.game_state = Some(game_state);
+    user_state.puzzle = Some(puzzle);
+    user_state.reviewed_mistake = Some(mistake_index);
+    user_state.quiz = None;
+    start_new_move_history(user_state);
+    return response;
+}
+
+// the data loaded once on start and shared between all the users
+pub struct StaticData {
+    pub translations: Translations,
+    pub render_data: ImageRenderData,
+    pub puzzle_packs: Vec<PuzzlePack>,
+}
+
+// the frontend decides how to show the image and the discard options
+#[derive(Clone)]
+pub struct Response {
+    pub text: String,
+    pub image: Option<ImageBuf>,
+    // tiles that can be discarded in the MPSZ notation, sending any of them makes the discard
+    pub discard_options: Vec<String>,
+    pub position_id: u64, // the position the discard options are for
+}
+
+fn make_quiz_hand(tiles: &[Tile]) -> Hand {
+    let mut hand = EMPTY_HAND;
+    hand.tiles[0..tiles.len()].copy_from_slice(tiles);
+    return hand;
+}
+
+fn get_shanten_text(shanten: i8) -> String {
+    return match shanten {
+        -1 => "The hand is complete".to_string(),
+        0 => "The hand is tenpai (0-shanten)".to_string(),
+        _ => format!("The hand is {}-shanten", shanten),
+    };
+}
+
+fn get_shanten_explanation_text(
+    hand_tiles: &[Tile],
+    score_settings: &ScoreCalculationSettings,
+) -> String {
+    const MAX_SHOWN_DECOMPOSITIONS: usize = 3;
+
+    match get_best_hand_form(hand_tiles, score_settings) {
+        HandForm::Chiitoitsu => {
+            let pair_count = make_frequency_table(hand_tiles)
+                .iter()
+                .filter(|count| **count >= 2)
+                .count();
+            return format!(
+                "The hand is the closest to chiitoitsu: it has {} different pairs, shanten = 6 - pairs\n",
+                pair_count
+            );
+        }
+        HandForm::Kokushi => {
+            return "The hand is the closest to kokushi musou: shanten = 13 - different terminals and honors - 1 if one of them is a pair\n".to_string();
+        }
+        HandForm::Standard => {}
+    }
+
+    let mut result =
+        "Shanten = 8 - 2 for every set - 1 for every protorun - 1 for the pair, at most four sets and protoruns are counted\n".to_string();
+    let decompositions = find_optimal_decompositions(hand_tiles);
+    for decomposition in decompositions.iter().take(MAX_SHOWN_DECOMPOSITIONS) {
+        result += &get_decomposition_text(decomposition);
+        result += "\n";
+    }
+    return result;
+}
+
+fn get_quiz_answer_response(user_state: &mut UserState, message_text: &str) -> Vec<Response> {
+    let Some(quiz) = &user_state.quiz else {
+        return text_response("No quiz is in progress");
+    };
+    let score_settings = &user_state.settings.score_settings;
+
+    let text = match quiz {
+        Quiz::Waits { hand_tiles } => {
+            let Ok(answer) = make_tile_sequence_from_string(message_text.trim()) else {
+                return text_response("Type the waits in MPSZ notation, e.g. 147m5z");
+            };
+            let waits = get_quiz_waits(hand_tiles, score_settings);
+            let result = check_wait_quiz_answer(&waits, &answer);
+
+            let mut text = if result.missed.is_empty() && result.extra.is_empty() {
+                "Correct!\n".to_string()
+            } else {
+                "Not quite\n".to_string()
+            };
+            text += &format!("Waits: {}\n", get_short_tiles_text(&waits));
+            if !result.missed.is_empty() {
+                text += &format!("Missed: {}\n", get_short_tiles_text(&result.missed));
+            }
+            if !result.extra.is_empty() {
+                text += &format!("Not waits: {}\n", get_short_tiles_text(&result.extra));
+            }
+            text += &get_wait_shape_text(hand_tiles);
+            text += "Send /wait_quiz for the next hand";
+            text
+        }
+        Quiz::Shanten { hand_tiles } => {
+            let Ok(answer) = message_text.trim().parse::<i8>() else {
+                return text_response("Type the shanten number, e.g. 2 (0 means tenpai)");
+            };
+            let shanten = calculate_shanten(hand_tiles, score_settings).get_calculated_shanten();
+
+            let mut text = if answer == shanten {
+                format!("Correct! {}\n", get_shanten_text(shanten))
+            } else {
+                format!(
+                    "Not quite, you answered {}\n{}\n{}",
+                    answer,
+                    get_shanten_text(shanten),
+                    get_shanten_explanation_text(hand_tiles, score_settings)
+                )
+            };
+            text += "Send /shanten_quiz for the next hand";
+            text
+        }
+    };
+
+    user_state.quiz = None;
+    return text_response(text);
+}
+
+// the puzzle is a single discard, it is graded and explained right away
+fn get_puzzle_answer_response(
+    user_state: &mut UserState,
+    message_text: &str,
+    static_data: &StaticData,
+) -> Vec<Response> {
+    let settings = &user_state.settings;
+    let Some(game_state) = &user_state.game_state else {
+        user_state.puzzle = None;
+        return text_response("No puzzle is in progress, send /puzzle to get a new one");
+    };
+
+    let requested_tile = get_tile_from_input(&message_text.to_lowercase());
+    if requested_tile == EMPTY_TILE {
+        return text_response(INVALID_TILE_MESSAGE);
+    }
+    let hand = &game_state.hands[0];
+    let Some(tile_index_in_hand) = find_tile_position(&hand.tiles, &requested_tile) else {
+        return text_response("Could not find the given tile in the hand");
+    };
+    let discarded_tile = hand.tiles[tile_index_in_hand];
+
+    let full_hand_shanten = calculate_shanten(&get_closed_tiles(hand), &settings.score_settings)
+        .get_calculated_shanten();
+    let best_discards = calculate_best_discards(
+        hand,
+        full_hand_shanten,
+        &mut get_visible_tiles(game_state, 0),
+        &get_trainer_win_conditions(game_state, 0),
+        &settings.score_settings,
+    );
+    let best_discard_scores = get_best_discard_scores(&best_discards);
+
+    let discard_score = get_discard_score(&best_discards, &discarded_tile);
+    let mut summary = format!(
+        "Discarded {} ({}/{})\n",
+        tile_to_string(&discarded_tile, settings.display_settings.terms_display),
+        discard_score,
+        best_discard_scores.score
+    );
+    if best_discard_scores.tiles.contains(&discarded_tile) {
+        summary += "Best discard";
+    } else {
+        summary += &format!(
+            "Better discards: {}",
+            get_capitalized(&get_printable_tiles_set_text(
+                &best_discard_scores.tiles,
+                settings.display_settings.terms_display
+            ))
+        );
+    }
+
+    let previous_move = PreviousMoveData {
+        game_state: game_state.clone(),
+        hand_index: 0,
+        full_hand_shanten,
+        discarded_tile: discarded_tile,
+    };
+
+    let mut explanation = String::new();
+    if let Some(puzzle) = &user_state.puzzle {
+        if let Some(author_explanation) = &puzzle.explanation {
+            explanation += &format!("{}\n\n", author_explanation);
+        }
+    }
+    explanation += &get_move_explanation_text(&previous_move, settings);
+    match user_state.reviewed_mistake {
+        Some(mistake_index) => {
+            let review_result = update_mistake_after_review(
+                &mut user_state.mistake_log,
+                mistake_index,
+                best_discard_scores.tiles.contains(&discarded_tile),
+                get_current_timestamp(),
+            );
+            user_state.stats_unsaved = true;
+            explanation += &match review_result {
+                ReviewResult::Repeat => "This position will be served again\n".to_string(),
+                ReviewResult::NextReviewInDays(days) => {
+                    format!("The next review of this position is in {} days\n", days)
+                }
+                ReviewResult::Mastered => {
+                    "The position is mastered and removed from the review\n".to_string()
+                }
+            };
+            explanation += "Send /review for the next mistake";
+        }
+        None => explanation += "Send /puzzle for the next puzzle",
+    }
+
+    let mut responses = image_response(
+        render_move_explanation(
+            &previous_move,
+            &settings.score_settings,
+            &static_data.render_data,
+        ),
+        summary,
+    );
+    responses.extend(text_response(explanation));
+
+    let mut tiles_after_discard = get_closed_tiles(hand);
+    if let Some(position) = find_tile_position(&tiles_after_discard, &discarded_tile) {
+        tiles_after_discard.remove(position);
+    }
+    let shanten_after_discard =
+        calculate_shanten(&tiles_after_discard, &settings.score_settings).get_calculated_shanten();
+    user_state.move_history.push(MoveHistoryEntry {
+        move_data: previous_move,
+        score: discard_score,
+        best_score: best_discard_scores.score,
+        is_best_discard: best_discard_scores.tiles.contains(&discarded_tile),
+        shanten_after_discard: shanten_after_discard,
+    });
+    user_state.game_state = None;
+    user_state.puzzle = None;
+    user_state.reviewed_mistake = None;
+    return responses;
+}
+
+pub fn text_response(text: impl Into<String>) -> Vec<Response> {
+    return [Response {
+        text: text.into(),
+        image: None,
+        discard_options: Vec::new(),
+        position_id: 0,
+    }]
+    .to_vec();
+}
+
+fn single_image_response(img: ImageBuf, text: String) -> Response {
+    return Response {
+        text: text,
+        image: Some(img),
+        discard_options: Vec::new(),
+        position_id: 0,
+    };
+}
+
+// every tile of the hand in the order they are drawn, empty if it's not the turn to discard
+fn get_discard_options(hand: &Hand) -> Vec<String> {
+    if hand.tiles[13] == EMPTY_TILE {
+        return Vec::new();
+    }
+
+    return get_closed_tiles(hand)
+        .iter()
+        .map(|tile| get_short_tiles_text(&[*tile]))
+        .collect();
+}
+
+fn single_game_state_response(
+    game_state: &GameState,
+    static_data: &StaticData,
+    text: String,
+) -> Response {
+    let mut response = single_image_response(
+        render_game_state(game_state, &static_data.render_data),
+        text,
+    );
+    response.discard_options = get_discard_options(&game_state.hands[0]);
+    return response;
+}
+
+fn game_state_response(
+    game_state: &GameState,
+    static_data: &StaticData,
+    text: String,
+) -> Vec<Response> {
+    return [single_game_state_response(game_state, static_data, text)].to_vec();
+}
+
+fn image_response(img: ImageBuf, text: String) -> Vec<Response> {
+    return [single_image_response(img, text)].to_vec();
+}
+
+pub fn process_command(
+    user_state: &mut UserState,
+    command: TrainerCommand,
+    static_data: &StaticData,
+) -> Vec<Response> {
+    let settings = &mut user_state.settings;
+
+    match command {
+        TrainerCommand::Start => {
+            match generate_normal_dealt_game(
+                get_player_count(&settings.game_settings),
+                &settings.game_settings,
+                generate_random_seed(),
+            ) {
+                Ok(game_state) => user_state.game_state = Some(game_state),
+                Err(err) => {
+                    eprintln!("Failed to generate a new hand: {}", err);
+                    return text_response("Failed to generate a new hand. Try again");
+                }
+            }
+            return start_game(user_state, static_data);
+        }
+        TrainerCommand::StartWithHand { hand, discards } => {
+            match generate_dealt_game_with_hand_and_discards(
+                get_player_count(&settings.game_settings),
+                hand,
+                discards,
+                &settings.game_settings,
+                generate_random_seed(),
+            ) {
+                Ok(game_state) => user_state.game_state = Some(game_state),
+                Err(err) => {
+                    return text_response(format!("Can't generate game with this input: {}", err));
+                }
+            }
+            return start_game(user_state, static_data);
+        }
+        TrainerCommand::Chinitsu(seed, deal_settings) => {
+            let game_settings = GameSettings {
+                single_suit: true,
+                ..apply_deal_settings(&settings.game_settings, &deal_settings)
+            };
+            match generate_normal_dealt_game(
+                get_player_count(&game_settings),
+                &game_settings,
+                seed.unwrap_or_else(generate_random_seed),
+            ) {
+                Ok(game_state) => user_state.game_state = Some(game_state),
+                Err(err) => {
+                    eprintln!("Failed to generate a single suit hand: {}", err);
+                    return text_response("Failed to generate a new hand. Try again");
+                }
+            }
+            let mut responses = start_game(user_state, static_data);
+            if let (Some(_), Some(game_state)) = (seed, &user_state.game_state) {
+                responses[0].text += &get_replayed_deal_text(game_state, deal_settings.is_some());
+            }
+            return responses;
+        }
+        TrainerCommand::ChinitsuWaits => {
+            let quiz_settings = QuizSettings {
+                difficulty: QuizDifficulty::Hard,
+                single_suit: true,
+                include_honors: false,
+            };
+            let hand_tiles = generate_wait_quiz_hand(
+                &quiz_settings,
+                &settings.score_settings,
+                &mut thread_rng(),
+            );
+            let image = render_hand(&make_quiz_hand(&hand_tiles), &static_data.render_data);
+            user_state.quiz = Some(Quiz::Waits {
+                hand_tiles: hand_tiles,
+            });
+            return image_response(
+                image,
+                "What are the waits of this hand? Type all of them, e.g. 147m".to_string(),
+            );
+        }
+        TrainerCommand::Review => {
+            return get_review_response(user_state, static_data);
+        }
+        TrainerCommand::Stats => {
+            return get_stats_response(user_state, static_data);
+        }
+        TrainerCommand::Seed(seed, deal_settings) => {
+            let Some(seed) = seed else {
+                return match &user_state.game_state {
+                    Some(game_state) => match get_replay_command(game_state) {
+                        Some(command) => text_response(format!(
+                            "Seed of the current hand: {}\nSend {} to replay it",
+                            game_state.seed, command
+                        )),
+                        None => text_response(
+                            "The current hand was given, not shuffled, or dealt by an older version, it can't be replayed from the seed",
+                        ),
+                    },
+                    None => {
+                        text_response("Specify the seed of the hand to replay, e.g. /seed 12345")
+                    }
+                };
+            };
+            // single suit hands are replayed with /chinitsu
+            let game_settings = GameSettings {
+                single_suit: false,
+                ..apply_deal_settings(&settings.game_settings, &deal_settings)
+            };
+            match generate_normal_dealt_game(get_player_count(&game_settings), &game_settings, seed)
+            {
+                Ok(game_state) => user_state.game_state = Some(game_state),
+                Err(err) => {
+                    eprintln!("Failed to generate a hand with seed {}: {}", seed, err);
+                    return text_response("Failed to generate a new hand. Try again");
+                }
+            }
+            let mut responses = start_game(user_state, static_data);
+            if let Some(game_state) = &user_state.game_state {
+                responses[0].text += &get_replayed_deal_text(game_state, deal_settings.is_some());
+            }
+            return responses;
+        }
+        TrainerCommand::Puzzle(id) => {
+            let puzzle = match id {
+                Some(id) => find_puzzle(&static_data.puzzle_packs, &id),
+                None => static_data
+                    .puzzle_packs
+                    .iter()
+                    .flat_map(|pack| &pack.puzzles)
+                    .collect::<Vec<&Puzzle>>()
+                    .choose(&mut thread_rng())
+                    .copied(),
+            };
+            let Some(puzzle) = puzzle else {
+                return text_response("No puzzle found");
+            };
+            match make_puzzle_game_state(
+                puzzle,
+                get_player_count(&settings.game_settings),
+                &settings.game_settings,
+                generate_random_seed(),
+            ) {
+                Ok(game_state) => user_state.game_state = Some(game_state),
+                Err(err) => {
+                    eprintln!("Failed to set up puzzle {}: {}", puzzle.id, err);
+                    return text_response("Failed to set up the puzzle. Try another one");
+                }
+            }
+            user_state.puzzle = Some(puzzle.clone());
+            user_state.reviewed_mistake = None;
+            user_state.quiz = None;
+            start_new_move_history(user_state);
+            let Some(game_state) = &user_state.game_state else {
+                return text_response(NO_HAND_IN_PROGRESS_MESSAGE);
+            };
+            return game_state_response(
+                game_state,
+                static_data,
+                format!(
+                    "Puzzle {} from \"{}\"\nWhat would you discard?",
+                    puzzle.id, puzzle.pack_name
+                ),
+            );
+        }
+        TrainerCommand::WaitQuiz => {
+            let hand_tiles = generate_wait_quiz_hand(
+                &settings.quiz_settings,
+                &settings.score_settings,
+                &mut thread_rng(),
+            );
+            let image = render_hand(&make_quiz_hand(&hand_tiles), &static_data.render_data);
+            user_state.quiz = Some(Quiz::Waits {
+                hand_tiles: hand_tiles,
+            });
+            return image_response(
+                image,
+                "What are the waits of this hand? Type all of them, e.g. 147m5z".to_string(),
+            );
+        }
+        TrainerCommand::ShantenQuiz => {
+            let hand_tiles = generate_shanten_quiz_hand(&settings.quiz_settings, &mut thread_rng());
+            let image = render_hand(&make_quiz_hand(&hand_tiles), &static_data.render_data);
+            user_state.quiz = Some(Quiz::Shanten {
+                hand_tiles: hand_tiles,
+            });
+            return image_response(
+                image,
+                "How many tiles away from tenpai is this hand? Type the shanten number".to_string(),
+            );
+        }
+        TrainerCommand::SetQuizDifficulty(difficulty) => {
+            settings.quiz_settings.difficulty = difficulty;
+            user_state.settings_unsaved = true;
+            return text_response(match difficulty {
+                QuizDifficulty::Easy => "Quizzes are now easy",
+                QuizDifficulty::Normal => "Quizzes are now normal",
+                QuizDifficulty::Hard => "Quizzes are now hard",
+            });
+        }
+        TrainerCommand::ToggleQuizSingleSuit => {
+            settings.quiz_settings.single_suit = !settings.quiz_settings.single_suit;
+            user_state.settings_unsaved = true;
+            return text_response(format!(
+                "Quizzes now use {}",
+                if settings.quiz_settings.single_suit {
+                    "only one suit"
+                } else {
+                    "all suits"
+                }
+            ));
+        }
+        TrainerCommand::ToggleQuizHonors => {
+            settings.quiz_settings.include_honors = !settings.quiz_settings.include_honors;
+            user_state.settings_unsaved = true;
+            return text_response(format!(
+                "Honors in quizzes are now toggled {}",
+                if settings.quiz_settings.include_honors {
+                    "on"
+                } else {
+                    "off"
+                }
+            ));
+        }
+        TrainerCommand::ToggleHint => {
+            settings.training_aid_settings.hints_enabled =
+                !settings.training_aid_settings.hints_enabled;
+            user_state.settings_unsaved = true;
+            return text_response(format!(
+                "Hints are now toggled {}",
+                if settings.training_aid_settings.hints_enabled {
+                    "on"
+                } else {
+                    "off"
+                }
+            ));
+        }
+        TrainerCommand::ToggleUndo => {
+            settings.training_aid_settings.undo_enabled =
+                !settings.training_aid_settings.undo_enabled;
+            user_state.settings_unsaved = true;
+            return text_response(format!(
+                "Undo is now toggled {}",
+                if settings.training_aid_settings.undo_enabled {
+                    "on"
+                } else {
+                    "off"
+                }
+            ));
+        }
+        TrainerCommand::Puzzles => {
+            if static_data.puzzle_packs.is_empty() {
+                return text_response("No puzzle packs are loaded");
+            }
+            let mut text = String::new();
+            for pack in &static_data.puzzle_packs {
+                let ids: Vec<&str> = pack
+                    .puzzles
+                    .iter()
+                    .map(|puzzle| puzzle.id.as_str())
+                    .collect();
+                text += &format!("{}: {}\n", pack.name, ids.join(", "));
+            }
+            text += "Send /puzzle for a random puzzle or /puzzle <id> for a specific one";
+            return text_response(text);
+        }
+        TrainerCommand::Table => {
+            let Some(game_state) = &user_state.game_state else {
+                return text_response(NO_HAND_IN_PROGRESS_MESSAGE);
+            };
+            return game_state_response(
+                game_state,
+                static_data,
+                format!("Tiles left: {}", game_state.live_wall.len()),
+            );
+        }
+        TrainerCommand::Explain(turn) => {
+            let history = get_shown_move_history(
+                &user_state.move_history,
+                &user_state.last_hand_move_history,
+            );
+            return match find_move_history_entry(history, turn) {
+                Ok(entry) => image_response(
+                    render_move_explanation(
+                        &entry.move_data,
+                        &settings.score_settings,
+                        &static_data.render_data,
+                    ),
+                    get_move_explanation_text(&entry.move_data, settings)
+                        + "Send /explain_deep <turn> [until turn] to see simulated chances \
+                        to reach tenpai and win",
+                ),
+                Err(err) => text_response(err),
+            };
+        }
+        TrainerCommand::ExplainDeep(turn, until_turn) => {
+            let history = get_shown_move_history(
+                &user_state.move_history,
+                &user_state.last_hand_move_history,
+            );
+            return match find_move_history_entry(history, turn) {
+                Ok(entry) => text_response(get_simulated_move_explanation_text(
+                    &entry.move_data,
+                    turn.unwrap_or(history.len()),
+                    until_turn,
+                    settings,
+                )),
+                Err(err) => text_response(err),
+            };
+        }
+        TrainerCommand::History => {
+            let history = get_shown_move_history(
+                &user_state.move_history,
+                &user_state.last_hand_move_history,
+            );
+            if history.is_empty() {
+                return text_response("No moves are recorded yet");
+            }
+            return text_response(
+                get_move_history_text(history, settings)
+                    + "Send /explain <turn> to see the explanation of a turn",
+            );
+        }
+        TrainerCommand::Hint => {
+            if !settings.training_aid_settings.hints_enabled {
+                return text_response("Hints are turned off, send /toggle_hint to turn them on");
+            }
+            let Some(game_state) = &user_state.game_state else {
+                return text_response(NO_HAND_IN_PROGRESS_MESSAGE);
+            };
+            // the positions of puzzles and reviews are questions, the hint would give away the answer
+            if user_state.puzzle.is_some() {
+                return text_response(
+                    "Hints are only given for a hand in progress, not for puzzles and reviews",
+                );
+            }
+            return text_response(get_hint_text(game_state, settings));
+        }
+        TrainerCommand::Undo => {
+            if !settings.training_aid_settings.undo_enabled {
+                return text_response("Undo is turned off, send /toggle_undo to turn it on");
+            }
+            if user_state.game_state.is_none() || user_state.puzzle.is_some() {
+                return text_response("Only discards of a hand in progress can be taken back");
+            }
+            let Some(entry) = user_state.move_history.pop() else {
+                return text_response("No discards to take back");
+            };
+            // the move is not counted for the hand efficiency and the stats,
+            // but the hand is recorded as replayed after the better discards were shown
+            let efficiency = entry.score as f32 / entry.best_score as f32;
+            user_state.current_score -= entry.score;
+            user_state.best_score -= entry.best_score;
+            user_state.efficiency_sum -= efficiency;
+            user_state.moves -= 1;
+            user_state.undo_count += 1;
+            if !entry.is_best_discard {
+                let level_index = get_shanten_level_index(entry.move_data.full_hand_shanten);
+                user_state.mistakes_by_shanten[level_index] -= 1;
+            }
+            if efficiency < MISTAKE_EFFICIENCY_THRESHOLD
+                && remove_last_logged_mistake(
+                    &mut user_state.mistake_log,
+                    &entry.move_data.game_state,
+                    &entry.move_data.discarded_tile,
+                )
+            {
+                user_state.stats_unsaved = true;
+            }
+            let response = game_state_response(
+                &entry.move_data.game_state,
+                static_data,
+                format!(
+                    "Took back the discard of {}",
+                    tile_to_string(
+                        &entry.move_data.discarded_tile,
+                        settings.display_settings.terms_display
+                    )
+                ),
+            );
+            user_state.game_state = Some(entry.move_data.game_state);
+            return response;
+        }
+        TrainerCommand::Kan(tile) => {
+            let Some(game_state) = user_state.game_state.as_mut() else {
+                return text_response(NO_HAND_IN_PROGRESS_MESSAGE);
+            };
+            if user_state.puzzle.is_some() {
+                return text_response("Puzzles are answered with a discard");
+            }
+            if let Err(err) = declare_closed_kan(game_state, 0, &tile) {
+                return text_response(format!("Can't declare kan: {}", err));
+            }
+            return game_state_response(
+                game_state,
+                static_data,
+                format!(
+                    "Declared kan\nDrew {}\n{} tiles left in the live wall\n",
+                    tile_to_string(
+                        &game_state.hands[0].tiles[13],
+                        settings.display_settings.terms_display
+                    ),
+                    game_state.live_wall.len()
+                ),
+            );
+        }
+        TrainerCommand::Settings => return text_response(SETTINGS_TEXT),
+        TrainerCommand::SetTerms(terms_display) => {
+            settings.display_settings.terms_display = terms_display;
+            user_state.settings_unsaved = true;
+            return match terms_display {
+                TermsDisplayOption::EnglishTerms => {
+                    settings.display_settings.language_key = "ene".to_string();
+                    text_response("Set terminology to English")
+                }
+                TermsDisplayOption::JapaneseTerms => {
+                    settings.display_settings.language_key = "enj".to_string();
+                    text_response("Set terminology to Japanese")
+                }
+            };
+        }
+        TrainerCommand::ToggleKokushi => {
+            settings.score_settings.allow_kokushi = !settings.score_settings.allow_kokushi;
+            user_state.settings_unsaved = true;
+            return text_response(format!(
+                "Kokushi musou is now {}counted for shanten calculation",
+                if settings.score_settings.allow_kokushi {
+                    ""
+                } else {
+                    "not "
+                }
+            ));
+        }
+        TrainerCommand::ToggleChiitoi => {
+            settings.score_settings.allow_chiitoitsu = !settings.score_settings.allow_chiitoitsu;
+            user_state.settings_unsaved = true;
+            return text_response(format!(
+                "Chiitoitsu is now {}counted for shanten calculation",
+                if settings.score_settings.allow_chiitoitsu {
+                    ""
+                } else {
+                    "not "
+                }
+            ));
+        }
+        TrainerCommand::ToggleHonors => {
+            settings.game_settings.include_honors = !settings.game_settings.include_honors;
+            user_state.settings_unsaved = true;
+            return text_response(format!(
+                "Using honors is now toggled {}",
+                if settings.game_settings.include_honors {
+                    "on"
+                } else {
+                    "off"
+                }
+            ));
+        }
+        TrainerCommand::ToggleOpponents => {
+            settings.game_settings.simulate_opponents = !settings.game_settings.simulate_opponents;
+            user_state.settings_unsaved = true;
+            return text_response(format!(
+                "Simulated opponents are now toggled {}",
+                if settings.game_settings.simulate_opponents {
+                    "on"
+                } else {
+                    "off"
+                }
+            ));
+        }
+        TrainerCommand::ToggleSingleSuit => {
+            settings.game_settings.single_suit = !settings.game_settings.single_suit;
+            user_state.settings_unsaved = true;
+            return text_response(format!(
+                "Playing with a single suit wall is now toggled {}",
+                if settings.game_settings.single_suit {
+                    "on"
+                } else {
+                    "off"
+                }
+            ));
+        }
+        TrainerCommand::SetRedFives(count) => {
+            if count > 4 {
+                return text_response("There can't be more than 4 red fives of each suit");
+            }
+            settings.game_settings.red_fives_per_suit = count;
+            user_state.settings_unsaved = true;
+            return text_response(format!(
+                "Number of red fives of each suit is now set to {}",
+                count
+            ));
+        }
+        TrainerCommand::ToggleRedFiveTiebreak => {
+            settings.score_settings.prefer_keeping_red_fives =
+                !settings.score_settings.prefer_keeping_red_fives;
+            user_state.settings_unsaved = true;
+            return text_response(format!(
+                "Keeping red fives is now {}preferred between discards with equal score",
+                if settings.score_settings.prefer_keeping_red_fives {
+                    ""
+                } else {
+                    "not "
+                }
+            ));
+        }
+        TrainerCommand::ToggleValueRanking => {
+            settings.score_settings.discard_ranking = match settings.score_settings.discard_ranking
+            {
+                DiscardRanking::Efficiency => DiscardRanking::EfficiencyAndValue,
+                DiscardRanking::EfficiencyAndValue => DiscardRanking::Efficiency,
+            };
+            user_state.settings_unsaved = true;
+            return text_response(match settings.score_settings.discard_ranking {
+                DiscardRanking::Efficiency => "Discards are now scored only by efficiency",
+                DiscardRanking::EfficiencyAndValue => {
+                    "Discards are now scored by efficiency weighted by the value of the hand"
+                }
+            });
+        }
+        TrainerCommand::SetDepth(lookahead_depth) => {
+            settings.score_settings.lookahead_depth = lookahead_depth;
+            user_state.settings_unsaved = true;
+            return text_response(match lookahead_depth {
+                LookaheadDepth::Ukeire1 => "Discards are now scored by ukeire1",
+                LookaheadDepth::Ukeire2 => "Discards are now scored by ukeire2",
+                LookaheadDepth::Ukeire3 => "Discards are now scored by ukeire3",
+                LookaheadDepth::Exact => {
+                    "Discards are now scored by looking ahead until the hand is complete, \
+                    far from tenpai the lookahead stops earlier to answer in time"
+                }
+            });
+        }
+        TrainerCommand::InfoScore => {
+            return text_response("By default the bot uses ukeire2 as the score, \
+            which is calculated as a sum of multiplications of all ukeire that each potential \
+            improvement can give multiplayed by the number of tiles that can give that improvement.\n\n\
+            In simpler worlds it is a score that takes one step further than simply ukeire.\n\n\
+            When calculating the score the bot takes into account the number of tiles left in \
+            the live wall and the number of tiles discarded by the player.\n\n\
+            With /depth the score can be changed to plain ukeire (1), to ukeire3 that takes \
+            one more step (3), or to looking ahead until the hand is complete (exact).\n\n\
+            With /toggle_value_ranking the score is also multiplied by two for every han the hand \
+            is expected to be worth (riichi, tanyao, yakuhai and dora), and discards that leave \
+            an open hand without a yaku get no score.");
+        }
+        TrainerCommand::Input(message_text) => {
+            return get_input_response(user_state, &message_text, static_data);
+        }
+    }
+}
+
+// the text that is not a command is a discard, or an answer to the running quiz or puzzle
+fn get_input_response(
+    user_state: &mut UserState,
+    message_text: &str,
+    static_data: &StaticData,
+) -> Vec<Response> {
+    if user_state.quiz.is_some() {
+        return get_quiz_answer_response(user_state, message_text);
+    }
+
+    if user_state.puzzle.is_some() {
+        return get_puzzle_answer_response(user_state, message_text, static_data);
+    }
+
+    let settings = &user_state.settings;
+    let Some(game_state) = user_state.game_state.as_mut() else {
+        return text_response(NO_HAND_IN_PROGRESS_MESSAGE);
+    };
+
+    let mut answer: String = String::new();
+    let requested_tile = get_tile_from_input(&message_text.to_lowercase());
+    if requested_tile == EMPTY_TILE {
+        return text_response(INVALID_TILE_MESSAGE);
+    }
+
+    let full_hand_shanten = calculate_shanten(
+        &get_closed_tiles(&game_state.hands[0]),
+        &settings.score_settings,
+    )
+    .get_calculated_shanten();
+    let best_discards = calculate_best_discards(
+        &game_state.hands[0],
+        full_hand_shanten,
+        &mut get_visible_tiles(game_state, 0),
+        &get_trainer_win_conditions(game_state, 0),
+        &settings.score_settings,
+    );
+
+    let best_discard_scores = get_best_discard_scores(&best_discards);
+    let mut discarded_tile = None;
+
+    match find_tile_position(&game_state.hands[0].tiles, &requested_tile) {
+        Some(tile_index_in_hand) => {
+            let state_before_discard = (*game_state).clone();
+            let tile = discard_tile(game_state, 0, tile_index_in_hand);
+            discarded_tile = Some(tile);
+            let current_discard_score = get_discard_score(&best_discards, &tile);
+
+            user_state.best_score += best_discard_scores.score;
+            user_state.current_score += current_discard_score;
+            let efficiency = current_discard_score as f32 / best_discard_scores.score as f32;
+            user_state.efficiency_sum += efficiency;
+            user_state.moves += 1;
+            if !best_discard_scores.tiles.contains(&tile) {
+                user_state.mistakes_by_shanten[get_shanten_level_index(full_hand_shanten)] += 1;
+            }
+            if efficiency < MISTAKE_EFFICIENCY_THRESHOLD {
+                log_mistake(
+                    &mut user_state.mistake_log,
+                    make_mistake_record(
+                        &state_before_discard,
+                        &tile,
+                        efficiency,
+                        get_current_timestamp(),
+                    ),
+                );
+                user_state.stats_unsaved = true;
+            }
+
+            let shanten_calculator = calculate_shanten(
+                &get_closed_tiles(&game_state.hands[0]),
+                &settings.score_settings,
+            );
+            let new_shanten = shanten_calculator.get_calculated_shanten();
+            user_state.move_history.push(MoveHistoryEntry {
+                move_data: PreviousMoveData {
+                    game_state: state_before_discard,
+                    hand_index: 0,
+                    full_hand_shanten,
+                    discarded_tile: tile,
+                },
+                score: current_discard_score,
+                best_score: best_discard_scores.score,
+                is_best_discard: best_discard_scores.tiles.contains(&tile),
+                shanten_after_discard: new_shanten,
+            });
+            if new_shanten > 0 {
+                answer += &format!(
+                    "Discarded {} ({}/{})\n",
+                    tile_to_string(&tile, settings.display_settings.terms_display),
+                    current_discard_score,
+                    best_discard_scores.score
+                );
+                if has_potential_for_furiten(
+                    shanten_calculator.get_best_waits(),
+                    &game_state.discards[0],
+                ) {
+                    answer += "Possible furiten\n";
+                }
+            } else {
+                answer += translate("tenpai_hand", &static_data.translations, settings);
+                answer += "\n";
+                let wait_tiles = filter_tiles_finishing_hand(
+                    &get_closed_tiles(&game_state.hands[0]),
+                    &convert_frequency_table_to_flat_vec(shanten_calculator.get_best_waits()),
+                    &settings.score_settings,
+                );
+                answer += &format!(
+                    "Waits: {} ({} tiles)",
+                    get_printable_tiles_set_text(
+                        &wait_tiles,
+                        settings.display_settings.terms_display
+                    ),
+                    find_potentially_available_tile_count(
+                        &get_visible_tiles(game_state, 0),
+                        &wait_tiles
+                    )
+                );
+                if has_furiten_waits(&wait_tiles, &game_state.discards[0]) {
+                    answer += " furiten";
+                }
+                answer += "\n";
+                answer += &get_wait_shape_text(&get_closed_tiles(&game_state.hands[0]));
+                answer += &get_waits_value_text(game_state, &wait_tiles, settings);
+            }
+        }
+        None => {
+            answer += "Could not find the given tile in the hand\n";
+        }
+    }
+
+    if game_state.hands[0].tiles[13] == EMPTY_TILE {
+        let shanten_calculator = calculate_shanten(
+            &get_closed_tiles(&game_state.hands[0]),
+            &settings.score_settings,
+        );
+        let shanten = shanten_calculator.get_calculated_shanten();
+        match discarded_tile {
+            Some(tile) => {
+                if shanten > full_hand_shanten {
+                    answer += "Went back in shanten\n";
+                } else {
+                    if best_discard_scores.tiles.contains(&tile) {
+                        answer += "Best discard\n";
+                    } else {
+                        answer += &format!(
+                            "Better discards: {}\n",
+                            get_capitalized(&get_printable_tiles_set_text(
+                                &best_discard_scores.tiles,
+                                settings.display_settings.terms_display
+                            ))
+                        );
+                    }
+                }
+
+                if shanten <= 0 {
+                    if user_state.best_score > 0 {
+                        answer += &format!(
+                            "Score: {}/{}\nAverage efficiency {}% for {} turns",
+                            user_state.current_score,
+                            user_state.best_score,
+                            (100.0 * (user_state.efficiency_sum / user_state.moves as f32)).floor(),
+                            user_state.moves
+                        );
+                    } else {
+                        answer += &format!(
+                            "Some error occurred, best possible score was zero, current score: {}",
+                            user_state.current_score
+                        );
+                    }
+                    answer += &get_seed_text(game_state);
+                    let seed = game_state.seed;
+                    user_state.game_state = None;
+                    record_finished_hand(user_state, seed, Some(user_state.moves));
+                    answer += "\nSend /start to start new game";
+                    return text_response(answer);
+                }
+            }
+            None => panic!("We got 13 tiles but nothing discarded, that is broken"),
+        }
+
+        if game_state.hands.len() > 1 {
+            let other_players_discards =
+                play_other_players_turns(game_state, 0, &settings.score_settings);
+            if !other_players_discards.is_empty() {
+                answer += &format!(
+                    "Other players discarded: {}\n",
+                    other_players_discards
+                        .iter()
+                        .map(|tile| tile_to_string(tile, settings.display_settings.terms_display))
+                        .collect::<Vec<&str>>()
+                        .join(", ")
+                );
+            }
+        }
+
+        if game_state.live_wall.is_empty() {
+            answer += "\nEnd of life wall, no more tiles left";
+            answer += &get_seed_text(game_state);
+            let seed = game_state.seed;
+            user_state.game_state = None;
+            record_finished_hand(user_state, seed, None);
+            answer += "\nSend /start to start new game";
+            return text_response(answer);
+        }
+
+        draw_tile_to_hand(game_state, 0);
+        answer += &format!(
+            "Drew {}\n{} tiles left in the live wall\n",
+            tile_to_string(
+                &game_state.hands[0].tiles[13],
+                settings.display_settings.terms_display
+            ),
+            game_state.live_wall.len()
+        );
+    }
+
+    return game_state_response(game_state, static_data, answer);
+}
+
+fn load_translations() -> Translations {
+    let mut translations = HashMap::new();
+
+    {
+        translations.insert(
+            "ene".to_string(),
+            HashMap::from([("tenpai_hand", "The hand is ready now")]),
+        );
+    }
+
+    {
+        translations.insert(
+            "enj".to_string(),
+            HashMap::from([("tenpai_hand", "Tenpai")]),
+        );
+    }
+
+    return translations;
+}
+
+pub fn load_static_data(puzzle_packs_path: &Path) -> StaticData {
+    build_shanten_lookup_tables();
+    return StaticData {
+        translations: load_translations(),
+        render_data: load_static_render_data(),
+        puzzle_packs: load_puzzle_packs(puzzle_packs_path),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_commands_are_parsed() {
+        let Ok(TrainerCommand::StartWithHand { hand, discards }) =
+            parse_command("/start 123456789m1234p-9s1z")
+        else {
+            panic!("The hand should be parsed");
+        };
+        assert_eq!(hand.tiles[0], get_tile_from_input("1m"));
+        assert_eq!(discards.len(), 2);
+
+        assert!(matches!(
+            parse_command("/depth 3"),
+            Ok(TrainerCommand::SetDepth(LookaheadDepth::Ukeire3))
+        ));
+        assert!(matches!(
+            parse_command("/explain 2"),
+            Ok(TrainerCommand::Explain(Some(2)))
+        ));
+        assert!(matches!(
+            parse_command("/explain_deep 2 10"),
+            Ok(TrainerCommand::ExplainDeep(Some(2), Some(10)))
+        ));
+        assert!(parse_command("/seed abc").is_err());
+        assert!(matches!(
+            parse_command("/chinitsu 7"),
+            Ok(TrainerCommand::Chinitsu(Some(7), None))
+        ));
+        assert!(matches!(
+            parse_command("/seed 7 2ho"),
+            Ok(TrainerCommand::Seed(
+                Some(7),
+                Some(DealSettings {
+                    include_honors: true,
+                    red_fives_per_suit: 2,
+                    simulate_opponents: true,
+                })
+            ))
+        ));
+        assert!(parse_command("/seed 7 5h").is_err());
+        assert!(parse_command("/seed 7 1x").is_err());
+        assert!(parse_command("/kan").is_err());
+        assert!(matches!(
+            parse_command("red"),
+            Ok(TrainerCommand::Input(text)) if text == "red"
+        ));
+    }
+
+    #[test]
+    fn test_discard_is_scored() {
+        let static_data = load_static_data(Path::new("resources/puzzles"));
+        let mut user_state = get_default_user_state();
+
+        let responses = process_command(
+            &mut user_state,
+            TrainerCommand::Seed(Some(42), None),
+            &static_data,
+        );
+        assert_eq!(responses.len(), 1);
+        assert!(responses[0].image.is_some());
+        assert_eq!(responses[0].discard_options.len(), 14);
+
+        let drawn_tile = responses[0].discard_options[13].clone();
+        let responses = process_user_message(&mut user_state, &drawn_tile, &static_data);
+        assert!(responses[0].text.starts_with("Discarded"));
+        assert_eq!(user_state.moves, 1);
+        assert_eq!(user_state.move_history.len(), 1);
+        assert!(user_state.best_score >= user_state.current_score);
+    }
+
+    #[test]
+    fn test_only_shuffled_hands_are_offered_to_be_replayed() {
+        let static_data = load_static_data(Path::new("resources/puzzles"));
+        let mut user_state = get_default_user_state();
+
+        let responses = process_user_message(&mut user_state, "/chinitsu 7", &static_data);
+        assert!(responses[0]
+            .text
+            .contains("Only 8 tiles are left to draw in the wall of one suit"));
+        let game_state = user_state.game_state.as_ref().unwrap();
+        assert_eq!(game_state.deal_mode, DealMode::SingleSuit);
+        let chinitsu_tiles = game_state.hands[0].tiles;
+        let responses = process_user_message(&mut user_state, "/seed", &static_data);
+        assert!(responses[0]
+            .text
+            .ends_with("Send /chinitsu 7 0 to replay it"));
+        process_user_message(&mut user_state, "/chinitsu 7 0", &static_data);
+        assert_eq!(
+            user_state.game_state.as_ref().unwrap().hands[0].tiles,
+            chinitsu_tiles
+        );
+
+        // /seed deals a wall of all suits even with the single suit setting
+        user_state.settings.game_settings.single_suit = true;
+        let responses = process_user_message(&mut user_state, "/seed 7", &static_data);
+        assert!(responses[0].text.contains("Dealt with your settings"));
+        let game_state = user_state.game_state.as_ref().unwrap();
+        assert_eq!(game_state.deal_mode, DealMode::Shuffled);
+        assert!(get_seed_text(game_state).contains("send /seed 7 0h to replay"));
+        let shuffled_tiles = game_state.hands[0].tiles;
+
+        // the deal settings of the command are used instead of the player's settings
+        user_state.settings.game_settings.include_honors = false;
+        user_state.settings.game_settings.red_fives_per_suit = 1;
+        user_state.settings.game_settings.simulate_opponents = true;
+        let responses = process_user_message(&mut user_state, "/seed 7 0h", &static_data);
+        assert!(responses[0]
+            .text
+            .contains("Dealt with the settings of the replayed hand: honors on, 0 red fives of each suit, opponents off"));
+        let game_state = user_state.game_state.as_ref().unwrap();
+        assert_eq!(game_state.hands.len(), 1);
+        assert_eq!(game_state.hands[0].tiles, shuffled_tiles);
+        assert!(!user_state.settings.game_settings.include_honors);
+
+        process_user_message(&mut user_state, "/start 123456789m123p1s5z", &static_data);
+        assert_eq!(get_seed_text(user_state.game_state.as_ref().unwrap()), "");
+        let responses = process_user_message(&mut user_state, "/seed", &static_data);
+        assert!(responses[0].text.contains("can't be replayed"));
+    }
+
+    #[test]
+    fn test_history_and_explain_find_the_turn() {
+        let static_data = load_static_data(Path::new("resources/puzzles"));
+        let mut user_state = get_default_user_state();
+
+        let responses = process_command(&mut user_state, TrainerCommand::History, &static_data);
+        assert_eq!(responses[0].text, "No moves are recorded yet");
+
+        // the hand is far from tenpai, so discarding the drawn tile doesn't finish it
+        process_user_message(&mut user_state, "/start 1469m258p369s1234z", &static_data);
+        process_user_message(&mut user_state, "1z", &static_data);
+        for _ in 0..2 {
+            let drawn_tile = user_state.game_state.as_ref().unwrap().hands[0].tiles[13];
+            process_user_message(
+                &mut user_state,
+                &get_short_tiles_text(&[drawn_tile]),
+                &static_data,
+            );
+        }
+
+        let responses = process_command(&mut user_state, TrainerCommand::History, &static_data);
+        let history_lines: Vec<&str> = responses[0].text.lines().collect();
+        assert_eq!(history_lines.len(), 4);
+        assert!(history_lines[0].starts_with("1. East"));
+        assert!(history_lines[2].starts_with("3. "));
+
+        let responses = process_command(
+            &mut user_state,
+            TrainerCommand::Explain(Some(1)),
+            &static_data,
+        );
+        assert!(responses[0].image.is_some());
+        let responses =
+            process_command(&mut user_state, TrainerCommand::Explain(None), &static_data);
+        assert!(responses[0].image.is_some());
+        let responses = process_command(
+            &mut user_state,
+            TrainerCommand::Explain(Some(4)),
+            &static_data,
+        );
+        assert_eq!(
+            responses[0].text,
+            "There is no turn 4, 3 turns are recorded, send /history to see them"
+        );
+        let responses = process_command(
+            &mut user_state,
+            TrainerCommand::Explain(Some(0)),
+            &static_data,
+        );
+        assert!(responses[0].text.starts_with("There is no turn 0"));
+
+        // the previous hand is shown until the first discard of the next one
+        process_user_message(&mut user_state, "/start 1469m258p369s1234z", &static_data);
+        let responses = process_command(&mut user_state, TrainerCommand::History, &static_data);
+        assert_eq!(responses[0].text.lines().count(), 4);
+        process_user_message(&mut user_state, "1z", &static_data);
+        let responses = process_command(&mut user_state, TrainerCommand::History, &static_data);
+        assert_eq!(responses[0].text.lines().count(), 2);
+    }
+
+    #[test]
+    fn test_finished_hand_is_recorded_in_the_stats() {
+        let static_data = load_static_data(Path::new("resources/puzzles"));
+        let mut user_state = get_default_user_state();
+
+        let responses = process_command(&mut user_state, TrainerCommand::Stats, &static_data);
+        assert!(responses[0]
+            .text
+            .starts_with("No finished hands are recorded yet"));
+
+        // discarding the white dragon reaches tenpai and finishes the hand
+        process_user_message(&mut user_state, "/start 123456789m1234p5z", &static_data);
+        let responses = process_user_message(&mut user_state, "5z", &static_data);
+        assert!(responses[0].text.ends_with("Send /start to start new game"));
+        assert!(user_state.game_state.is_none());
+        assert!(user_state.stats_unsaved);
+
+        assert_eq!(user_state.stats.hands.len(), 1);
+        let hand = &user_state.stats.hands[0];
+        assert_eq!(hand.moves, 1);
+        assert_eq!(hand.turns_to_tenpai, Some(1));
+        assert_eq!(hand.efficiency, 1.0);
+        assert_eq!(hand.mistakes_by_shanten, [0; TRACKED_SHANTEN_LEVELS]);
+        assert_eq!(hand.undo_count, 0);
+        assert!(user_state.mistake_log.mistakes.is_empty());
+
+        let responses = process_command(&mut user_state, TrainerCommand::Stats, &static_data);
+        assert!(!responses[0].text.starts_with("No finished hands"));
+
+        // a hand without discards is not recorded
+        process_user_message(&mut user_state, "/start 123456789m1234p5z", &static_data);
+        process_user_message(&mut user_state, "/start 123456789m1234p5z", &static_data);
+        assert_eq!(user_state.stats.hands.len(), 1);
+    }
+
+    #[test]
+    fn test_puzzle_answers_are_graded() {
+        let static_data = load_static_data(Path::new("resources/puzzles"));
+        let mut user_state = get_default_user_state();
+
+        let responses = process_command(
+            &mut user_state,
+            TrainerCommand::Puzzle(Some("basic-1".to_string())),
+            &static_data,
+        );
+        assert!(responses[0]
+            .text
+            .starts_with("Puzzle basic-1 from \"Basic efficiency\""));
+        let responses = process_user_message(&mut user_state, "1z", &static_data);
+        assert!(responses[0].text.ends_with("Best discard"));
+        assert!(responses[1]
+            .text
+            .ends_with("Send /puzzle for the next puzzle"));
+        assert!(user_state.puzzle.is_none());
+        assert!(user_state.game_state.is_none());
+
+        process_command(
+            &mut user_state,
+            TrainerCommand::Puzzle(Some("basic-1".to_string())),
+            &static_data,
+        );
+        let responses = process_user_message(&mut user_state, "2m", &static_data);
+        assert!(responses[0].text.contains("Better discards: "));
+        let responses = process_command(
+            &mut user_state,
+            TrainerCommand::Puzzle(Some("no-such-puzzle".to_string())),
+            &static_data,
+        );
+        assert_eq!(responses[0].text, "No puzzle found");
+
+        // a mistake from a hand is served as a review puzzle right away
+        process_user_message(&mut user_state, "/start 123456789m1234p5z", &static_data);
+        process_user_message(&mut user_state, "1m", &static_data);
+        assert_eq!(user_state.mistake_log.mistakes.len(), 1);
+        process_command(&mut user_state, TrainerCommand::Review, &static_data);
+        assert_eq!(user_state.reviewed_mistake, Some(0));
+        let responses = process_user_message(&mut user_state, "5z", &static_data);
+        assert!(responses[1]
+            .text
+            .contains("The next review of this position is in 1 days"));
+        assert_eq!(user_state.mistake_log.mistakes[0].correct_answers_in_row, 1);
+        let responses = process_command(&mut user_state, TrainerCommand::Review, &static_data);
+        assert!(responses[0].text.starts_with("No mistakes to review now"));
+    }
+
+    #[test]
+    fn test_quiz_answers_are_checked() {
+        let static_data = load_static_data(Path::new("resources/puzzles"));
+        let mut user_state = get_default_user_state();
+        let score_settings = user_state.settings.score_settings.clone();
+
+        process_command(&mut user_state, TrainerCommand::WaitQuiz, &static_data);
+        let Some(Quiz::Waits { hand_tiles }) = user_state.quiz.clone() else {
+            panic!("The wait quiz should be running");
+        };
+        let responses = process_user_message(&mut user_state, "waits", &static_data);
+        assert!(responses[0].text.starts_with("Type the waits"));
+        assert!(user_state.quiz.is_some());
+        let waits = get_quiz_waits(&hand_tiles, &score_settings);
+        let responses =
+            process_user_message(&mut user_state, &get_short_tiles_text(&waits), &static_data);
+        assert!(responses[0].text.starts_with("Correct!"));
+        assert!(user_state.quiz.is_none());
+
+        user_state.quiz = Some(Quiz::Waits {
+            hand_tiles: make_tile_sequence_from_string("123456789m123p1s").unwrap(),
+        });
+        let responses = process_user_message(&mut user_state, "2s", &static_data);
+        assert!(responses[0]
+            .text
+            .starts_with("Not quite\nWaits: 1s\nMissed: 1s\nNot waits: 2s\n"));
+
+        process_command(&mut user_state, TrainerCommand::ShantenQuiz, &static_data);
+        let Some(Quiz::Shanten { hand_tiles }) = user_state.quiz.clone() else {
+            panic!("The shanten quiz should be running");
+        };
+        let shanten = calculate_shanten(&hand_tiles, &score_settings).get_calculated_shanten();
+        let responses =
+            process_user_message(&mut user_state, &(shanten + 1).to_string(), &static_data);
+        assert!(responses[0]
+            .text
+            .starts_with(&format!("Not quite, you answered {}\n", shanten + 1)));
+        assert!(user_state.quiz.is_none());
+    }
+
+    #[test]
+    fn test_buttons_of_earlier_positions_are_rejected() {
+        let static_data = load_static_data(Path::new("resources/puzzles"));
+        let mut user_state = get_default_user_state();
+        let press = |user_state: &mut UserState, response: &Response, tile_text: &str| {
+            let button_data = make_discard_button_data(response.position_id, tile_text);
+            return process_discard_button(user_state, &button_data, &static_data);
+        };
+
+        let start_responses =
+            process_user_message(&mut user_state, "/start 1469m258p369s1234z", &static_data);
+        let discard_responses = press(&mut user_state, &start_responses[0], "1z");
+        assert_eq!(user_state.moves, 1);
+        assert_ne!(
+            discard_responses[0].position_id,
+            start_responses[0].position_id
+        );
+
+        // the hand still has 2z that was pressed from the first image
+        let responses = press(&mut user_state, &start_responses[0], "2z");
+        assert!(responses[0]
+            .text
+            .starts_with("This button is from an earlier position"));
+        assert_eq!(user_state.moves, 1);
+
+        // a typed discard makes the buttons of the image before it stale too
+        process_user_message(&mut user_state, "2z", &static_data);
+        let responses = press(&mut user_state, &discard_responses[0], "3z");
+        assert!(responses[0]
+            .text
+            .starts_with("This button is from an earlier position"));
+        assert_eq!(user_state.moves, 2);
+
+        // the buttons of the hand are not taken as the answer to a quiz
+        let last_position = user_state.position_id;
+        process_user_message(&mut user_state, "/wait_quiz", &static_data);
+        let responses = process_discard_button(
+            &mut user_state,
+            &make_discard_button_data(last_position, "3z"),
+            &static_data,
+        );
+        assert!(responses[0]
+            .text
+            .starts_with("This button is from an earlier position"));
+        assert!(user_state.quiz.is_some());
+    }
+
+    #[test]
+    fn test_hint_counts_the_best_discards() {
+        let static_data = load_static_data(Path::new("resources/puzzles"));
+        let mut user_state = get_default_user_state();
+
+        let responses = process_user_message(&mut user_state, "/hint", &static_data);
+        assert_eq!(responses[0].text, NO_HAND_IN_PROGRESS_MESSAGE);
+
+        process_user_message(&mut user_state, "/start 123456789m123p1s5z", &static_data);
+        // the dora indicator would make one of the two tanki waits better if it was 1s or 5z
+        user_state.game_state.as_mut().unwrap().dora_indicators[0] = get_tile_from_input("9p");
+        let responses = process_user_message(&mut user_state, "/hint", &static_data);
+        assert_eq!(
+            responses[0].text,
+            "The hand is tenpai (0-shanten) after the discard\n\
+            2 discards don't go back in shanten, 2 of them have the best score"
+        );
+
+        process_user_message(&mut user_state, "/puzzle basic-1", &static_data);
+        let responses = process_user_message(&mut user_state, "/hint", &static_data);
+        assert!(responses[0]
+            .text
+            .starts_with("Hints are only given for a hand in progress"));
+
+        user_state.settings.training_aid_settings.hints_enabled = false;
+        let responses = process_user_message(&mut user_state, "/hint", &static_data);
+        assert!(responses[0].text.starts_with("Hints are turned off"));
+    }
+
+    #[test]
+    fn test_explain_deep_simulates_until_the_given_turn() {
+        let static_data = load_static_data(Path::new("resources/puzzles"));
+        let mut user_state = get_default_user_state();
+        process_user_message(&mut user_state, "/start 123456789m123p1s5z", &static_data);
+        process_user_message(&mut user_state, "5z", &static_data);
+
+        let responses = process_user_message(&mut user_state, "/explain_deep 1 3", &static_data);
+        assert!(responses[0]
+            .text
+            .starts_with("Chances to reach tenpai and to win by turn 3 "));
+        let responses = process_user_message(&mut user_state, "/explain_deep 1 1", &static_data);
+        assert!(responses[0]
+            .text
+            .starts_with("The turn to simulate until should be after turn 1"));
+        let responses = process_user_message(&mut user_state, "/explain_deep 2", &static_data);
+        assert!(responses[0].text.starts_with("There is no turn 2"));
+    }
+
+    #[test]
+    fn test_undo_takes_the_discard_out_of_the_stats() {
+        let static_data = load_static_data(Path::new("resources/puzzles"));
+        let mut user_state = get_default_user_state();
+        process_user_message(&mut user_state, "/start 123456789m123p1s5z", &static_data);
+        let hand_before_discard = user_state.game_state.as_ref().unwrap().hands[0].tiles;
+
+        // breaking a set goes back from tenpai
+        process_user_message(&mut user_state, "1m", &static_data);
+        assert_eq!(user_state.moves, 1);
+        assert_eq!(user_state.efficiency_sum, 0.0);
+        assert_eq!(user_state.mistakes_by_shanten[0], 1);
+        assert_eq!(user_state.mistake_log.mistakes.len(), 1);
+
+        let responses = process_user_message(&mut user_state, "/undo", &static_data);
+        assert!(responses[0].text.starts_with("Took back the discard of"));
+        assert_eq!(user_state.moves, 0);
+        assert_eq!(user_state.current_score, 0);
+        assert_eq!(user_state.best_score, 0);
+        assert_eq!(user_state.efficiency_sum, 0.0);
+        assert_eq!(user_state.mistakes_by_shanten, [0; TRACKED_SHANTEN_LEVELS]);
+        assert!(user_state.mistake_log.mistakes.is_empty());
+        assert_eq!(user_state.undo_count, 1);
+        assert!(user_state.move_history.is_empty());
+        assert_eq!(
+            user_state.game_state.as_ref().unwrap().hands[0].tiles,
+            hand_before_discard
+        );
+
+        process_user_message(&mut user_state, "5z", &static_data);
+        assert_eq!(user_state.moves, 1);
+        assert_eq!(user_state.efficiency_sum, 1.0);
+        assert_eq!(user_state.mistakes_by_shanten, [0; TRACKED_SHANTEN_LEVELS]);
+        assert!(user_state.mistake_log.mistakes.is_empty());
+
+        // the undo count is recorded with the hand and starts over with the next one
+        record_finished_hand(&mut user_state, 0, Some(0));
+        assert_eq!(user_state.stats.hands[0].undo_count, 1);
+        process_user_message(&mut user_state, "/start", &static_data);
+        assert_eq!(user_state.undo_count, 0);
+    }
+}
@@ -1,14 +1,35 @@
 use crate::game_logic::*;
+use crate::shanten_lookup::calculate_standard_shanten_with_lookup;
 use serde::{Deserialize, Serialize};
 use std::cmp::max;
+use std::collections::HashMap;
+use std::sync::Mutex;
 
 #[cfg(test)]
 use crate::input_output;
 
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum DiscardRanking {
+    Efficiency,         // lookahead score only
+    EfficiencyAndValue, // lookahead score weighted by the estimated value of the hand
+}
+
+// how many steps ahead the discards are evaluated
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum LookaheadDepth {
+    Ukeire1,
+    Ukeire2,
+    Ukeire3,
+    Exact, // all the way until the hand is complete, within the node budget
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 pub struct ScoreCalculationSettings {
     pub allow_kokushi: bool,
     pub allow_chiitoitsu: bool,
+    pub prefer_keeping_red_fives: bool,
+    pub discard_ranking: DiscardRanking,
+    pub lookahead_depth: LookaheadDepth,
 }
 
 fn set_max(element: &mut u8, value: u8) {
@@ -72,8 +93,7 @@ impl ShantenCalculator {
 
         if i >= self.hand_table.len() {
             // We've checked everything. See if this shanten is better than the current best.
-            let current_shanten =
-                (8 - (self.complete_sets * 2) - self.partial_sets - self.pair) as i8;
+            let current_shanten = 8 - (self.complete_sets * 2) - self.partial_sets - self.pair;
             if current_shanten < self.best_shanten {
                 self.best_shanten = current_shanten;
                 self.best_waits = EMPTY_FREQUENCY_TABLE;
@@ -101,7 +121,7 @@ impl ShantenCalculator {
 
             // Edge or Side wait protorun
             if i < 30 && self.hand_table[i + 1] != 0 {
-                let left_edge_wait = i % 10 == 0;
+                let left_edge_wait = i.is_multiple_of(10);
                 let right_edge_wait = i % 10 == 7;
 
                 self.partial_sets += 1;
@@ -197,6 +217,19 @@ impl ShantenCalculator {
         self.remove_completed_sets(0);
     }
 
+    fn calculate_shanten_standard_with_lookup(&mut self) {
+        let (shanten, waits) =
+            calculate_standard_shanten_with_lookup(&self.hand_table, self.complete_sets);
+        if shanten < self.best_shanten {
+            self.best_shanten = shanten;
+            self.best_waits = waits;
+        } else if shanten == self.best_shanten {
+            for (best_wait, wait) in self.best_waits.iter_mut().zip(waits) {
+                set_max(best_wait, wait);
+            }
+        }
+    }
+
     fn calculate_shanten_chiitoitsu(&mut self) {
         let mut pair_count = 0;
         let mut unique_tile_count = 0;
@@ -276,6 +309,14 @@ impl ShantenCalculator {
         return shanten;
     }
 
+    fn calculate_shanten_standard_with_method(&mut self, use_lookup_tables: bool) {
+        if use_lookup_tables {
+            self.calculate_shanten_standard_with_lookup();
+        } else {
+            self.calculate_shanten_standard();
+        }
+    }
+
     pub fn get_calculated_shanten(&self) -> i8 {
         return self.best_shanten;
     }
@@ -285,17 +326,42 @@ impl ShantenCalculator {
     }
 }
 
+// the tiles are the closed part of the hand, every meld makes it three tiles shorter
 pub fn calculate_shanten(tiles: &[Tile], settings: &ScoreCalculationSettings) -> ShantenCalculator {
+    return calculate_shanten_with_method(tiles, settings, true);
+}
+
+// the recursive calculation is kept as a reference for the lookup tables in the tests and the benchmark
+pub fn calculate_shanten_recursive(
+    tiles: &[Tile],
+    settings: &ScoreCalculationSettings,
+) -> ShantenCalculator {
+    return calculate_shanten_with_method(tiles, settings, false);
+}
+
+fn calculate_shanten_with_method(
+    tiles: &[Tile],
+    settings: &ScoreCalculationSettings,
+    use_lookup_tables: bool,
+) -> ShantenCalculator {
+    let meld_count = ((14 - tiles.len()) / 3) as i8;
+
     let mut calculator = ShantenCalculator {
-        hand_table: make_frequency_table(&tiles),
+        hand_table: make_frequency_table(tiles),
         waits_table: EMPTY_FREQUENCY_TABLE,
-        complete_sets: 0,
+        complete_sets: meld_count,
         pair: 0,
         partial_sets: 0,
         best_shanten: MAX_SHANTEN,
         best_waits: EMPTY_FREQUENCY_TABLE,
     };
 
+    // chiitoitsu and kokushi can be collected only with a fully closed hand
+    if meld_count > 0 {
+        calculator.calculate_shanten_standard_with_method(use_lookup_tables);
+        return calculator;
+    }
+
     if settings.allow_chiitoitsu {
         calculator.calculate_shanten_chiitoitsu();
 
@@ -313,20 +379,28 @@ pub fn calculate_shanten(tiles: &[Tile], settings: &ScoreCalculationSettings) ->
         }
     }
 
-    calculator.calculate_shanten_standard();
+    calculator.calculate_shanten_standard_with_method(use_lookup_tables);
 
     return calculator;
 }
 
 pub fn get_visible_tiles(game: &GameState, visible_hand_index: usize) -> TileFrequencyTable {
-    let mut result = game.total_discards_table.clone();
+    let mut result = game.total_discards_table;
 
     for tile in game.hands[visible_hand_index].tiles {
         result[get_tile_index(&tile)] += 1;
     }
 
-    for i in 0..game.opened_dora_indicators {
-        result[get_tile_index(&game.dora_indicators[i as usize])] += 1;
+    for hand in &game.hands {
+        for meld in &hand.melds {
+            for tile in &meld.tiles {
+                result[get_tile_index(tile)] += 1;
+            }
+        }
+    }
+
+    for indicator in get_opened_dora_indicators(game) {
+        result[get_tile_index(indicator)] += 1;
     }
 
     return result;
@@ -357,21 +431,22 @@ pub fn filter_tiles_improving_shanten(
     settings: &ScoreCalculationSettings,
 ) -> Vec<Tile> {
     assert!(
-        hand_tiles.len() == 13,
-        "filter_tiles_improving_shanten is expected to be called on 13 tiles"
+        hand_tiles.len() % 3 == 1,
+        "filter_tiles_improving_shanten is expected to be called on 13 tiles minus melds"
     );
 
     let mut extended_hand = [hand_tiles.to_vec(), [EMPTY_TILE].to_vec()].concat();
+    let last_index = extended_hand.len() - 1;
 
     let mut result = Vec::new();
 
-    for i in 0..tiles.len() {
-        extended_hand[13] = tiles[i];
+    for tile in tiles {
+        extended_hand[last_index] = *tile;
 
-        let calculator = calculate_shanten(&extended_hand, &settings);
+        let calculator = calculate_shanten(&extended_hand, settings);
 
         if calculator.get_calculated_shanten() < current_shanten {
-            result.push(tiles[i]);
+            result.push(*tile);
         }
     }
 
@@ -384,21 +459,22 @@ pub fn filter_tiles_finishing_hand(
     settings: &ScoreCalculationSettings,
 ) -> Vec<Tile> {
     assert!(
-        hand_tiles.len() == 13,
-        "filter_tiles_improving_shanten is expected to be called on 13 tiles"
+        hand_tiles.len() % 3 == 1,
+        "filter_tiles_improving_shanten is expected to be called on 13 tiles minus melds"
     );
 
     let mut extended_hand = [hand_tiles.to_vec(), [EMPTY_TILE].to_vec()].concat();
+    let last_index = extended_hand.len() - 1;
 
     let mut result = Vec::new();
 
-    for i in 0..tiles.len() {
-        extended_hand[13] = tiles[i];
+    for tile in tiles {
+        extended_hand[last_index] = *tile;
 
-        let calculator = calculate_shanten(&extended_hand, &settings);
+        let calculator = calculate_shanten(&extended_hand, settings);
 
         if calculator.get_calculated_shanten() < 0 {
-            result.push(tiles[i]);
+            result.push(*tile);
         }
     }
 
@@ -411,90 +487,299 @@ pub struct WeightedDiscard {
     pub score: u32,
 }
 
-fn sort_weighted_discards(weighted_discards: &mut [WeightedDiscard]) {
+pub fn sort_weighted_discards(
+    weighted_discards: &mut [WeightedDiscard],
+    settings: &ScoreCalculationSettings,
+) {
     weighted_discards.sort_by(|a: &WeightedDiscard, b: &WeightedDiscard| {
         if a.score > b.score {
             std::cmp::Ordering::Less
         } else if a.score < b.score {
             std::cmp::Ordering::Greater
+        } else if settings.prefer_keeping_red_fives && a.tile.is_red != b.tile.is_red {
+            // when everything else is equal, keeping a red five is better
+            if a.tile.is_red {
+                std::cmp::Ordering::Greater
+            } else {
+                std::cmp::Ordering::Less
+            }
         } else {
             std::cmp::Ordering::Equal
         }
     });
 }
 
-pub fn calculate_best_discards_ukeire1(
+// key is the hand, the visible tiles and the remaining depth,
+// the value is the score and the number of hands evaluated to calculate it without the cache
+type LookaheadScoreCache = HashMap<(TileFrequencyTable, TileFrequencyTable, u8), (u32, u32)>;
+// key is the hand, the value is its shanten and the tiles that improve it (if they were needed)
+type HandShapeCache = HashMap<TileFrequencyTable, (i8, Option<Vec<Tile>>)>;
+
+// deeper lookahead is only calculated while fewer hands than this were evaluated in the call,
+// far from tenpai the exact lookahead would otherwise take tens of seconds
+// cached scores count the hands they were calculated from, so the depth doesn't depend on the cache
+const LOOKAHEAD_NODE_BUDGET: u32 = 100_000;
+// a cache is cleared when it grows over this, to keep the memory bounded
+const MAX_LOOKAHEAD_CACHE_ENTRIES: usize = 2_000_000;
+
+struct LookaheadCache {
+    // the hand shapes depend on which special hands count
+    allow_kokushi: bool,
+    allow_chiitoitsu: bool,
+    scores: LookaheadScoreCache,
+    hand_shapes: HandShapeCache,
+    evaluated_nodes: u32, // in the current call
+}
+
+// caches are kept between calls, so scoring a discard after a hint reuses the work of the hint
+// every call takes a cache out of the pool, so parallel calls don't wait for each other
+static LOOKAHEAD_CACHE_POOL: Mutex<Vec<LookaheadCache>> = Mutex::new(Vec::new());
+
+fn take_lookahead_cache(settings: &ScoreCalculationSettings) -> LookaheadCache {
+    let mut pool = LOOKAHEAD_CACHE_POOL.lock().unwrap();
+    let found_index = pool.iter().position(|cache| {
+        cache.allow_kokushi == settings.allow_kokushi
+            && cache.allow_chiitoitsu == settings.allow_chiitoitsu
+    });
+    return match found_index {
+        Some(index) => pool.swap_remove(index),
+        None => make_empty_lookahead_cache(settings),
+    };
+}
+
+fn make_empty_lookahead_cache(settings: &ScoreCalculationSettings) -> LookaheadCache {
+    return LookaheadCache {
+        allow_kokushi: settings.allow_kokushi,
+        allow_chiitoitsu: settings.allow_chiitoitsu,
+        scores: HashMap::new(),
+        hand_shapes: HashMap::new(),
+        evaluated_nodes: 0,
+    };
+}
+
+fn return_lookahead_cache(mut cache: LookaheadCache) {
+    if cache.scores.len() + cache.hand_shapes.len() > MAX_LOOKAHEAD_CACHE_ENTRIES {
+        cache.scores.clear();
+        cache.hand_shapes.clear();
+    }
+    LOOKAHEAD_CACHE_POOL.lock().unwrap().push(cache);
+}
+
+// returns the tiles improving the hand only if the hand has the expected shanten
+fn get_tiles_improving_hand_with_shanten(
     hand_tiles: &[Tile],
-    minimal_shanten: i8,
-    visible_tiles: &TileFrequencyTable,
+    expected_shanten: i8,
     settings: &ScoreCalculationSettings,
-) -> Vec<WeightedDiscard> {
-    assert!(
-        hand_tiles[13] != EMPTY_TILE,
-        "calculate_best_discards_ukeire1 expected hand with 14 tiles"
+    cache: &mut LookaheadCache,
+) -> Option<Vec<Tile>> {
+    let hand_table = make_frequency_table(hand_tiles);
+    if let Some((shanten, tiles_improving_shanten)) = cache.hand_shapes.get(&hand_table) {
+        if *shanten != expected_shanten {
+            return None;
+        }
+        if tiles_improving_shanten.is_some() {
+            return tiles_improving_shanten.clone();
+        }
+    }
+
+    let calculator = calculate_shanten(hand_tiles, settings);
+    let shanten = calculator.get_calculated_shanten();
+    if shanten != expected_shanten {
+        cache.hand_shapes.insert(hand_table, (shanten, None));
+        return None;
+    }
+
+    let tiles_improving_shanten = filter_tiles_improving_shanten(
+        hand_tiles,
+        &convert_frequency_table_to_flat_vec(&calculator.best_waits),
+        shanten,
+        settings,
     );
+    cache
+        .hand_shapes
+        .insert(hand_table, (shanten, Some(tiles_improving_shanten.clone())));
+    return Some(tiles_improving_shanten);
+}
 
-    let mut possible_discards = Vec::with_capacity(14);
-    let mut previous_tile = EMPTY_TILE;
-    let mut full_hand = hand_tiles.to_vec();
-    full_hand.sort();
-    let full_hand = full_hand;
+// depth 1 is the number of tiles improving the hand (ukeire1)
+// every next level sums the best scores of the level below for every improving tile that can be drawn,
+// multiplied by the number of these tiles that are still available
+// returns None if the node budget ran out before the score was calculated
+fn evaluate_lookahead(
+    hand_tiles: &mut Vec<Tile>,
+    shanten: i8,
+    tiles_improving_shanten: &[Tile],
+    depth: u8,
+    visible_tiles: &mut TileFrequencyTable,
+    settings: &ScoreCalculationSettings,
+    cache: &mut LookaheadCache,
+) -> Option<u32> {
+    if depth <= 1 {
+        return Some(
+            find_potentially_available_tile_count(visible_tiles, tiles_improving_shanten) as u32,
+        );
+    }
 
-    for i in 0..full_hand.len() {
-        if full_hand[i] == previous_tile {
+    let cache_key = (make_frequency_table(hand_tiles), *visible_tiles, depth);
+    if let Some((score, node_count)) = cache.scores.get(&cache_key) {
+        cache.evaluated_nodes += node_count;
+        if cache.evaluated_nodes > LOOKAHEAD_NODE_BUDGET {
+            return None;
+        }
+        return Some(*score);
+    }
+
+    let evaluated_nodes_before = cache.evaluated_nodes;
+    let mut score: u32 = 0;
+    for tile in tiles_improving_shanten {
+        let tile_index = get_tile_index(tile);
+        let available_tiles = 4 - visible_tiles[tile_index];
+        if available_tiles == 0 {
             continue;
         }
-        let mut reduced_tiles = full_hand.clone();
-        reduced_tiles.remove(i);
+        hand_tiles.push(*tile);
+        visible_tiles[tile_index] += 1;
+
+        let mut best_score = 0;
+        let mut previous_tile = EMPTY_TILE;
+        let mut full_hand = hand_tiles.clone();
+        full_hand.sort();
+        for i in 0..full_hand.len() {
+            if is_same_tile_ignoring_red(&full_hand[i], &previous_tile) {
+                continue;
+            }
+            previous_tile = full_hand[i];
 
-        let calculator = calculate_shanten(&reduced_tiles, &settings);
+            cache.evaluated_nodes += 1;
+            if cache.evaluated_nodes > LOOKAHEAD_NODE_BUDGET {
+                visible_tiles[tile_index] -= 1;
+                hand_tiles.pop();
+                return None;
+            }
 
-        if calculator.get_calculated_shanten() == minimal_shanten {
-            let tiles_improving_shanten = filter_tiles_improving_shanten(
-                &reduced_tiles,
-                &convert_frequency_table_to_flat_vec(&calculator.best_waits),
-                minimal_shanten,
-                &settings,
+            let mut reduced_tiles = full_hand.clone();
+            reduced_tiles.remove(i);
+            let Some(new_tiles_improving_shanten) =
+                get_tiles_improving_hand_with_shanten(&reduced_tiles, shanten - 1, settings, cache)
+            else {
+                continue;
+            };
+
+            let next_score = evaluate_lookahead(
+                &mut reduced_tiles,
+                shanten - 1,
+                &new_tiles_improving_shanten,
+                depth - 1,
+                visible_tiles,
+                settings,
+                cache,
             );
-            let available_tiles =
-                find_potentially_available_tile_count(&visible_tiles, &tiles_improving_shanten);
-            if available_tiles > 0 {
-                possible_discards.push(WeightedDiscard {
-                    tile: full_hand[i],
-                    tiles_improving_shanten: tiles_improving_shanten,
-                    score: available_tiles as u32,
-                });
-            }
+            let Some(next_score) = next_score else {
+                visible_tiles[tile_index] -= 1;
+                hand_tiles.pop();
+                return None;
+            };
+            best_score = max(best_score, next_score);
         }
+        score += best_score * (available_tiles as u32);
 
-        previous_tile = full_hand[i];
+        visible_tiles[tile_index] -= 1;
+        hand_tiles.pop();
     }
 
-    sort_weighted_discards(&mut possible_discards);
+    let node_count = cache.evaluated_nodes - evaluated_nodes_before;
+    cache.scores.insert(cache_key, (score, node_count));
+    return Some(score);
+}
 
-    return possible_discards;
+pub fn get_lookahead_depth_value(lookahead_depth: LookaheadDepth, shanten: i8) -> u8 {
+    let max_useful_depth = (max(shanten, 0) + 1) as u8;
+    return match lookahead_depth {
+        LookaheadDepth::Ukeire1 => 1,
+        LookaheadDepth::Ukeire2 => 2,
+        LookaheadDepth::Ukeire3 => 3,
+        LookaheadDepth::Exact => max_useful_depth,
+    }
+    .min(max_useful_depth);
 }
 
-pub fn calculate_best_discards_ukeire2(
+// the depth is limited by the number of steps needed to complete the hand,
+// and lowered if the node budget runs out before the requested depth is calculated
+// the same position always gets the same depth, whatever was calculated before
+pub fn calculate_best_discards_with_depth(
     hand_tiles: &[Tile],
     minimal_shanten: i8,
     visible_tiles: &mut TileFrequencyTable,
+    lookahead_depth: LookaheadDepth,
     settings: &ScoreCalculationSettings,
 ) -> Vec<WeightedDiscard> {
     assert!(
-        hand_tiles[13] != EMPTY_TILE,
-        "calculate_best_discards_ukeire2 expected hand with 14 tiles"
+        hand_tiles.len() % 3 == 2 && !hand_tiles.contains(&EMPTY_TILE),
+        "calculate_best_discards_with_depth expected hand with 14 tiles minus melds"
     );
 
-    if minimal_shanten <= 0 {
-        return calculate_best_discards_ukeire1(
-            &hand_tiles,
+    let mut cache = take_lookahead_cache(settings);
+    let mut possible_discards = calculate_best_discards_with_cache(
+        hand_tiles,
+        minimal_shanten,
+        visible_tiles,
+        lookahead_depth,
+        settings,
+        &mut cache,
+    );
+    return_lookahead_cache(cache);
+
+    sort_weighted_discards(&mut possible_discards, settings);
+
+    return possible_discards;
+}
+
+fn calculate_best_discards_with_cache(
+    hand_tiles: &[Tile],
+    minimal_shanten: i8,
+    visible_tiles: &mut TileFrequencyTable,
+    lookahead_depth: LookaheadDepth,
+    settings: &ScoreCalculationSettings,
+    cache: &mut LookaheadCache,
+) -> Vec<WeightedDiscard> {
+    let max_depth = get_lookahead_depth_value(lookahead_depth, minimal_shanten);
+    cache.evaluated_nodes = 0;
+
+    // the levels are calculated from the shallowest, so a result is ready when the budget runs out
+    let mut possible_discards = calculate_discards_at_depth(
+        hand_tiles,
+        minimal_shanten,
+        visible_tiles,
+        1,
+        settings,
+        cache,
+    )
+    .expect("ukeire1 doesn't use the node budget");
+    for depth in 2..=max_depth {
+        match calculate_discards_at_depth(
+            hand_tiles,
             minimal_shanten,
-            &visible_tiles,
-            &settings,
-        );
+            visible_tiles,
+            depth,
+            settings,
+            cache,
+        ) {
+            Some(discards) => possible_discards = discards,
+            None => break,
+        }
     }
 
+    return possible_discards;
+}
+
+fn calculate_discards_at_depth(
+    hand_tiles: &[Tile],
+    minimal_shanten: i8,
+    visible_tiles: &mut TileFrequencyTable,
+    depth: u8,
+    settings: &ScoreCalculationSettings,
+    cache: &mut LookaheadCache,
+) -> Option<Vec<WeightedDiscard>> {
     let mut possible_discards = Vec::with_capacity(14);
     let mut previous_tile = EMPTY_TILE;
     let mut full_hand = hand_tiles.to_vec();
@@ -505,53 +790,67 @@ pub fn calculate_best_discards_ukeire2(
         if full_hand[i] == previous_tile {
             continue;
         }
+        previous_tile = full_hand[i];
+
         let mut reduced_tiles = full_hand.clone();
         reduced_tiles.remove(i);
 
-        let calculator = calculate_shanten(&reduced_tiles, &settings);
-
-        if calculator.get_calculated_shanten() == minimal_shanten {
-            let tiles_improving_shanten = filter_tiles_improving_shanten(
-                &reduced_tiles,
-                &convert_frequency_table_to_flat_vec(&calculator.best_waits),
-                minimal_shanten,
-                &settings,
-            );
-            let mut score: u32 = 0;
-            for tile in &tiles_improving_shanten {
-                let tile_index = get_tile_index(tile);
-                let available_tiles = 4 - visible_tiles[tile_index];
-                if available_tiles == 0 {
-                    continue;
-                }
-                reduced_tiles.push(*tile);
-                visible_tiles[tile_index] += 1;
-                let weighted_discards = calculate_best_discards_ukeire1(
-                    &reduced_tiles,
-                    minimal_shanten - 1,
-                    visible_tiles,
-                    settings,
-                );
-                if !weighted_discards.is_empty() {
-                    score += weighted_discards[0].score * (available_tiles as u32);
-                }
-                visible_tiles[tile_index] -= 1;
-                reduced_tiles.pop();
-            }
+        let Some(tiles_improving_shanten) =
+            get_tiles_improving_hand_with_shanten(&reduced_tiles, minimal_shanten, settings, cache)
+        else {
+            continue;
+        };
 
+        let score = evaluate_lookahead(
+            &mut reduced_tiles,
+            minimal_shanten,
+            &tiles_improving_shanten,
+            depth,
+            visible_tiles,
+            settings,
+            cache,
+        )?;
+        // with plain ukeire there is no point in discards that can't improve the hand
+        if depth > 1 || score > 0 {
             possible_discards.push(WeightedDiscard {
                 tile: full_hand[i],
                 tiles_improving_shanten: tiles_improving_shanten,
                 score: score,
             });
         }
-
-        previous_tile = full_hand[i];
     }
 
-    sort_weighted_discards(&mut possible_discards);
+    return Some(possible_discards);
+}
 
-    return possible_discards;
+pub fn calculate_best_discards_ukeire1(
+    hand_tiles: &[Tile],
+    minimal_shanten: i8,
+    visible_tiles: &TileFrequencyTable,
+    settings: &ScoreCalculationSettings,
+) -> Vec<WeightedDiscard> {
+    return calculate_best_discards_with_depth(
+        hand_tiles,
+        minimal_shanten,
+        &mut visible_tiles.clone(),
+        LookaheadDepth::Ukeire1,
+        settings,
+    );
+}
+
+pub fn calculate_best_discards_ukeire2(
+    hand_tiles: &[Tile],
+    minimal_shanten: i8,
+    visible_tiles: &mut TileFrequencyTable,
+    settings: &ScoreCalculationSettings,
+) -> Vec<WeightedDiscard> {
+    return calculate_best_discards_with_depth(
+        hand_tiles,
+        minimal_shanten,
+        visible_tiles,
+        LookaheadDepth::Ukeire2,
+        settings,
+    );
 }
 
 #[derive(Clone)]
@@ -560,7 +859,8 @@ pub struct DiscardScores {
     pub score: u32,
 }
 
-pub fn get_best_discard_scores(best_discards: &Vec<WeightedDiscard>) -> DiscardScores {
+// all discards with the best score, red fives are only sorted after the others with the same score
+pub fn get_best_discard_scores(best_discards: &[WeightedDiscard]) -> DiscardScores {
     if !best_discards.is_empty() {
         let mut result = DiscardScores {
             tiles: Vec::new(),
@@ -594,10 +894,10 @@ pub fn get_discard_score(best_discards: &Vec<WeightedDiscard>, tile: &Tile) -> u
     return 0;
 }
 
-pub fn has_furiten_waits(waits: &Vec<Tile>, discards: &Vec<Tile>) -> bool {
+pub fn has_furiten_waits(waits: &[Tile], discards: &[Tile]) -> bool {
     let discards_table = make_frequency_table(discards);
     for tile in waits {
-        if discards_table[get_tile_index(&tile)] > 0 {
+        if discards_table[get_tile_index(tile)] > 0 {
             return true;
         }
     }
@@ -607,7 +907,7 @@ pub fn has_furiten_waits(waits: &Vec<Tile>, discards: &Vec<Tile>) -> bool {
 
 pub fn has_potential_for_furiten(waits_table: &TileFrequencyTable, discards: &Vec<Tile>) -> bool {
     for tile in discards {
-        if waits_table[get_tile_index(&tile)] > 1 {
+        if waits_table[get_tile_index(tile)] > 1 {
             return true;
         }
     }
@@ -617,21 +917,25 @@ pub fn has_potential_for_furiten(waits_table: &TileFrequencyTable, discards: &Ve
 
 fn get_tile_from_index(index: usize) -> Tile {
     return match index {
-        i if i < (10 as usize) => Tile {
+        i if i < 10_usize => Tile {
             suit: Suit::Man,
             value: (index + 1) as u8,
+            is_red: false,
         },
-        i if i < (20 as usize) => Tile {
+        i if i < 20_usize => Tile {
             suit: Suit::Pin,
             value: (index + 1 - 10) as u8,
+            is_red: false,
         },
-        i if i < (30 as usize) => Tile {
+        i if i < 30_usize => Tile {
             suit: Suit::Sou,
             value: (index + 1 - 20) as u8,
+            is_red: false,
         },
         _ => Tile {
             suit: Suit::Special,
             value: (index + 1 - 30) as u8,
+            is_red: false,
         },
     };
 }
@@ -639,8 +943,8 @@ fn get_tile_from_index(index: usize) -> Tile {
 pub fn convert_frequency_table_to_flat_vec(frequency_table: &TileFrequencyTable) -> Vec<Tile> {
     let mut result = Vec::new();
 
-    for i in 0..frequency_table.len() {
-        if frequency_table[i] > 0 {
+    for (i, count) in frequency_table.iter().enumerate() {
+        if *count > 0 {
             let tile = get_tile_from_index(i);
             result.push(tile);
         }
@@ -649,7 +953,470 @@ pub fn convert_frequency_table_to_flat_vec(frequency_table: &TileFrequencyTable)
     return result;
 }
 
-#[derive(Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum WaitShape {
+    Ryanmen,
+    Kanchan,
+    Penchan,
+    Shanpon,
+    Tanki,
+    Nobetan,            // two tanki waits on a four tile sequence, e.g. 1234 waits on 1 and 4
+    Entotsu,            // ryanmen and shanpon together, e.g. 23444 with another pair
+    Sanmenchan,         // three-sided ryanmen, e.g. 23456 waits on 1, 4 and 7
+    MultiSidedChinitsu, // three or more waits in a hand of a single suit
+    Complex,            // any other combination of the basic shapes
+}
+
+pub fn get_wait_shape_name(shape: WaitShape) -> &'static str {
+    return match shape {
+        WaitShape::Ryanmen => "ryanmen",
+        WaitShape::Kanchan => "kanchan",
+        WaitShape::Penchan => "penchan",
+        WaitShape::Shanpon => "shanpon",
+        WaitShape::Tanki => "tanki",
+        WaitShape::Nobetan => "nobetan",
+        WaitShape::Entotsu => "entotsu",
+        WaitShape::Sanmenchan => "sanmenchan",
+        WaitShape::MultiSidedChinitsu => "multi-sided chinitsu",
+        WaitShape::Complex => "complex wait",
+    };
+}
+
+// one way to read a tenpai hand, the shape is always one of the basic ones
+#[derive(Debug, Clone, PartialEq)]
+pub struct WaitReading {
+    pub sets: Vec<Vec<Tile>>,
+    pub waiting_tiles: Vec<Tile>, // a single tile, a pair with a protorun, or two pairs
+    pub shape: WaitShape,
+    pub waits: Vec<Tile>,
+}
+
+pub struct WaitShapeDescription {
+    pub shape: WaitShape,
+    pub waits: Vec<Tile>,
+    pub readings: Vec<WaitReading>,
+}
+
+// removes complete sets from the table, the tiles that are not in any set are left over
+fn find_sets_with_leftovers(
+    table: &mut TileFrequencyTable,
+    start_index: usize,
+    sets: &mut Vec<Vec<usize>>,
+    leftovers: &mut Vec<usize>,
+    result: &mut Vec<(Vec<Vec<usize>>, Vec<usize>)>,
+) {
+    let Some(i) = (start_index..table.len()).find(|i| table[*i] > 0) else {
+        if leftovers.len() == 1 || leftovers.len() == 4 {
+            result.push((sets.clone(), leftovers.clone()));
+        }
+        return;
+    };
+
+    // Triplet
+    if table[i] >= 3 {
+        table[i] -= 3;
+        sets.push([i; 3].to_vec());
+        find_sets_with_leftovers(table, i, sets, leftovers, result);
+        sets.pop();
+        table[i] += 3;
+    }
+
+    // Sequence
+    if i < 30 && i % 10 <= 6 && table[i + 1] > 0 && table[i + 2] > 0 {
+        table[i] -= 1;
+        table[i + 1] -= 1;
+        table[i + 2] -= 1;
+        sets.push([i, i + 1, i + 2].to_vec());
+        find_sets_with_leftovers(table, i, sets, leftovers, result);
+        sets.pop();
+        table[i] += 1;
+        table[i + 1] += 1;
+        table[i + 2] += 1;
+    }
+
+    // Leftover tile
+    if leftovers.len() < 4 {
+        table[i] -= 1;
+        leftovers.push(i);
+        find_sets_with_leftovers(table, i, sets, leftovers, result);
+        leftovers.pop();
+        table[i] += 1;
+    }
+}
+
+// returns the shape and the waits of two tiles left after a pair is taken out of the waiting part
+fn get_protorun_waits(first: usize, second: usize) -> Option<(WaitShape, Vec<usize>)> {
+    if first == second {
+        return Some((WaitShape::Shanpon, [first].to_vec()));
+    }
+    if first >= 30 || first / 10 != second / 10 {
+        return None;
+    }
+    if second == first + 1 {
+        return Some(match first % 10 {
+            0 => (WaitShape::Penchan, [first + 2].to_vec()),
+            7 => (WaitShape::Penchan, [first - 1].to_vec()),
+            _ => (WaitShape::Ryanmen, [first - 1, first + 2].to_vec()),
+        });
+    }
+    if second == first + 2 {
+        return Some((WaitShape::Kanchan, [first + 1].to_vec()));
+    }
+    return None;
+}
+
+// takes the tiles with the given indexes out of the pool, to keep the red fives in the readings
+fn take_tiles_from_pool(pool: &mut Vec<Tile>, indexes: &[usize]) -> Vec<Tile> {
+    let mut result = Vec::with_capacity(indexes.len());
+    for index in indexes {
+        if let Some(position) = pool.iter().position(|tile| get_tile_index(tile) == *index) {
+            result.push(pool.remove(position));
+        }
+    }
+    return result;
+}
+
+fn make_wait_reading(
+    closed_tiles: &[Tile],
+    sets: &[Vec<usize>],
+    waiting_indexes: &[usize],
+    shape: WaitShape,
+    wait_indexes: &[usize],
+) -> WaitReading {
+    let mut pool = closed_tiles.to_vec();
+    return WaitReading {
+        sets: sets
+            .iter()
+            .map(|set| take_tiles_from_pool(&mut pool, set))
+            .collect(),
+        waiting_tiles: take_tiles_from_pool(&mut pool, waiting_indexes),
+        shape: shape,
+        waits: wait_indexes
+            .iter()
+            .map(|index| get_tile_from_index(*index))
+            .collect(),
+    };
+}
+
+// the same split can be found in different order of removing the tiles,
+// and with two pairs the same shanpon is found twice
+fn add_unique_wait_reading(readings: &mut Vec<WaitReading>, reading: WaitReading) {
+    let is_duplicate = readings.iter().any(|existing| {
+        existing.shape == reading.shape
+            && existing.sets == reading.sets
+            && existing.waits == reading.waits
+    });
+    if !is_duplicate {
+        readings.push(reading);
+    }
+}
+
+fn find_wait_readings(closed_tiles: &[Tile]) -> Vec<WaitReading> {
+    let mut splits = Vec::new();
+    find_sets_with_leftovers(
+        &mut make_frequency_table(closed_tiles),
+        0,
+        &mut Vec::new(),
+        &mut Vec::new(),
+        &mut splits,
+    );
+
+    let mut result: Vec<WaitReading> = Vec::new();
+    for (sets, leftovers) in splits {
+        if leftovers.len() == 1 {
+            add_unique_wait_reading(
+                &mut result,
+                make_wait_reading(
+                    closed_tiles,
+                    &sets,
+                    &leftovers,
+                    WaitShape::Tanki,
+                    &leftovers,
+                ),
+            );
+            continue;
+        }
+
+        // the leftovers are sorted, so a pair can only be two neighboring tiles
+        for pair_position in 0..3 {
+            if leftovers[pair_position] != leftovers[pair_position + 1] {
+                continue;
+            }
+            let pair_index = leftovers[pair_position];
+            let mut protorun = leftovers.clone();
+            protorun.drain(pair_position..pair_position + 2);
+
+            let Some((shape, mut waits)) = get_protorun_waits(protorun[0], protorun[1]) else {
+                continue;
+            };
+            if shape == WaitShape::Shanpon {
+                waits.push(pair_index);
+                waits.sort();
+            }
+
+            let waiting_indexes = [[pair_index; 2].to_vec(), protorun].concat();
+            add_unique_wait_reading(
+                &mut result,
+                make_wait_reading(closed_tiles, &sets, &waiting_indexes, shape, &waits),
+            );
+        }
+    }
+
+    return result;
+}
+
+fn are_same_suit_with_step(indexes: &[usize], step: usize) -> bool {
+    return indexes
+        .windows(2)
+        .all(|pair| pair[0] < 30 && pair[0] / 10 == pair[1] / 10 && pair[1] == pair[0] + step);
+}
+
+// names the wait of a tenpai hand (13 tiles minus melds), only standard hands are recognized
+pub fn classify_wait_shape(closed_tiles: &[Tile]) -> Option<WaitShapeDescription> {
+    let readings = find_wait_readings(closed_tiles);
+    if readings.is_empty() {
+        return None;
+    }
+
+    let mut wait_indexes: Vec<usize> = readings
+        .iter()
+        .flat_map(|reading| reading.waits.iter().map(get_tile_index))
+        .collect();
+    wait_indexes.sort();
+    wait_indexes.dedup();
+
+    let mut basic_shapes: Vec<WaitShape> = Vec::new();
+    for reading in &readings {
+        if !basic_shapes.contains(&reading.shape) {
+            basic_shapes.push(reading.shape);
+        }
+    }
+    let has_only_shapes = |shapes: &[WaitShape]| {
+        return basic_shapes.iter().all(|shape| shapes.contains(shape))
+            && shapes.iter().all(|shape| basic_shapes.contains(shape));
+    };
+    let is_single_suit = closed_tiles
+        .iter()
+        .all(|tile| tile.suit == closed_tiles[0].suit)
+        && closed_tiles[0].suit != Suit::Special;
+
+    let shape = match wait_indexes.len() {
+        1 if basic_shapes.len() == 1 => basic_shapes[0],
+        2 if has_only_shapes(&[WaitShape::Ryanmen]) => WaitShape::Ryanmen,
+        2 if has_only_shapes(&[WaitShape::Shanpon]) => WaitShape::Shanpon,
+        2 if has_only_shapes(&[WaitShape::Tanki]) && are_same_suit_with_step(&wait_indexes, 3) => {
+            WaitShape::Nobetan
+        }
+        3 if has_only_shapes(&[WaitShape::Ryanmen])
+            && are_same_suit_with_step(&wait_indexes, 3) =>
+        {
+            WaitShape::Sanmenchan
+        }
+        3 if has_only_shapes(&[WaitShape::Ryanmen, WaitShape::Shanpon]) => WaitShape::Entotsu,
+        count if count >= 3 && is_single_suit => WaitShape::MultiSidedChinitsu,
+        _ => WaitShape::Complex,
+    };
+
+    return Some(WaitShapeDescription {
+        shape: shape,
+        waits: wait_indexes
+            .iter()
+            .map(|index| get_tile_from_index(*index))
+            .collect(),
+        readings: readings,
+    });
+}
+
+// one of the best ways to split a hand into groups, shanten = 8 - 2 * sets - protoruns - pair
+// where the sets include melds
+#[derive(Debug, Clone, PartialEq)]
+pub struct HandDecomposition {
+    pub sets: Vec<Vec<Tile>>,
+    pub pair: Option<Vec<Tile>>,
+    pub protoruns: Vec<Vec<Tile>>,
+    pub floating_tiles: Vec<Tile>,
+}
+
+// the same tiles as HandDecomposition but with tile indexes
+#[derive(Clone, PartialEq)]
+struct IndexDecomposition {
+    sets: Vec<Vec<usize>>,
+    pair: Option<usize>,
+    protoruns: Vec<Vec<usize>>,
+    floating_tiles: Vec<usize>,
+}
+
+struct DecompositionSearch {
+    hand_table: TileFrequencyTable,
+    meld_count: i8,
+    current: IndexDecomposition,
+    best_shanten: i8,
+    best_decompositions: Vec<IndexDecomposition>,
+}
+
+impl DecompositionSearch {
+    fn get_current_shanten(&self) -> i8 {
+        let complete_sets = self.meld_count + self.current.sets.len() as i8;
+        return 8
+            - complete_sets * 2
+            - self.current.protoruns.len() as i8
+            - self.current.pair.is_some() as i8;
+    }
+
+    fn add_protorun(&mut self, i: usize, protorun: Vec<usize>) {
+        for index in &protorun {
+            self.hand_table[*index] -= 1;
+        }
+        self.current.protoruns.push(protorun);
+        self.remove_potential_sets(i);
+        if let Some(protorun) = self.current.protoruns.pop() {
+            for index in &protorun {
+                self.hand_table[*index] += 1;
+            }
+        }
+    }
+
+    // the same order of checks as in ShantenCalculator, but every decomposition is kept
+    fn remove_potential_sets(&mut self, mut i: usize) {
+        while i < self.hand_table.len() && self.hand_table[i] == 0 {
+            i += 1;
+        }
+
+        if i >= self.hand_table.len() {
+            let shanten = self.get_current_shanten();
+            if shanten > self.best_shanten {
+                return;
+            }
+            if shanten < self.best_shanten {
+                self.best_shanten = shanten;
+                self.best_decompositions.clear();
+            }
+            let decomposition = IndexDecomposition {
+                floating_tiles: convert_frequency_table_to_indexes(&self.hand_table),
+                ..self.current.clone()
+            };
+            if !self.best_decompositions.contains(&decomposition) {
+                self.best_decompositions.push(decomposition);
+            }
+            return;
+        }
+
+        let set_count = self.meld_count as usize + self.current.sets.len();
+        if set_count + self.current.protoruns.len() < 4 {
+            if self.hand_table[i] == 2 {
+                self.add_protorun(i, [i, i].to_vec());
+            }
+            if i < 30 && self.hand_table[i + 1] != 0 {
+                self.add_protorun(i, [i, i + 1].to_vec());
+            }
+            if i < 30 && i % 10 <= 7 && self.hand_table[i + 2] != 0 {
+                self.add_protorun(i, [i, i + 2].to_vec());
+            }
+        }
+
+        // the tiles that are skipped stay in the table as floating tiles
+        self.remove_potential_sets(i + 1);
+    }
+
+    fn remove_completed_sets(&mut self, mut i: usize) {
+        while i < self.hand_table.len() && self.hand_table[i] == 0 {
+            i += 1;
+        }
+
+        if i >= self.hand_table.len() {
+            self.remove_potential_sets(0);
+            return;
+        }
+
+        let mut possible_sets = Vec::new();
+        if self.hand_table[i] >= 3 {
+            possible_sets.push([i; 3].to_vec());
+        }
+        if i < 30 && self.hand_table[i + 1] != 0 && self.hand_table[i + 2] != 0 {
+            possible_sets.push([i, i + 1, i + 2].to_vec());
+        }
+        for set in possible_sets {
+            for index in &set {
+                self.hand_table[*index] -= 1;
+            }
+            self.current.sets.push(set);
+            self.remove_completed_sets(i);
+            if let Some(set) = self.current.sets.pop() {
+                for index in &set {
+                    self.hand_table[*index] += 1;
+                }
+            }
+        }
+
+        self.remove_completed_sets(i + 1);
+    }
+
+    fn find_decompositions(&mut self) {
+        for i in 0..self.hand_table.len() {
+            if self.hand_table[i] >= 2 {
+                self.hand_table[i] -= 2;
+                self.current.pair = Some(i);
+                self.remove_completed_sets(0);
+                self.current.pair = None;
+                self.hand_table[i] += 2;
+            }
+        }
+
+        self.remove_completed_sets(0);
+    }
+}
+
+fn convert_frequency_table_to_indexes(frequency_table: &TileFrequencyTable) -> Vec<usize> {
+    let mut result = Vec::new();
+    for (index, count) in frequency_table.iter().enumerate() {
+        result.extend([index].repeat(*count as usize));
+    }
+    return result;
+}
+
+// lists every split of the closed tiles of a standard hand that gives the best shanten
+// chiitoitsu and kokushi are not considered, the tiles keep their red fives
+pub fn find_optimal_decompositions(tiles: &[Tile]) -> Vec<HandDecomposition> {
+    let mut search = DecompositionSearch {
+        hand_table: make_frequency_table(tiles),
+        meld_count: ((14 - tiles.len()) / 3) as i8,
+        current: IndexDecomposition {
+            sets: Vec::new(),
+            pair: None,
+            protoruns: Vec::new(),
+            floating_tiles: Vec::new(),
+        },
+        best_shanten: MAX_SHANTEN + 1,
+        best_decompositions: Vec::new(),
+    };
+    search.find_decompositions();
+
+    return search
+        .best_decompositions
+        .iter()
+        .map(|decomposition| {
+            let mut pool = tiles.to_vec();
+            return HandDecomposition {
+                sets: decomposition
+                    .sets
+                    .iter()
+                    .map(|set| take_tiles_from_pool(&mut pool, set))
+                    .collect(),
+                pair: decomposition
+                    .pair
+                    .map(|pair_index| take_tiles_from_pool(&mut pool, &[pair_index; 2])),
+                protoruns: decomposition
+                    .protoruns
+                    .iter()
+                    .map(|protorun| take_tiles_from_pool(&mut pool, protorun))
+                    .collect(),
+                floating_tiles: take_tiles_from_pool(&mut pool, &decomposition.floating_tiles),
+            };
+        })
+        .collect();
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct PreviousMoveData {
     pub game_state: GameState,
     pub hand_index: usize,
@@ -660,6 +1427,7 @@ pub struct PreviousMoveData {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::user_settings::DEFAULT_SCORE_SETTINGS;
 
     #[test]
     fn test_calculator_defaults() {
@@ -685,13 +1453,7 @@ mod tests {
     #[test]
     fn test_example_hand_one_shanten() {
         let hand = input_output::make_hand_from_string("123456789m1334p").unwrap();
-        let calculator = calculate_shanten(
-            &hand.tiles[0..13],
-            &ScoreCalculationSettings {
-                allow_kokushi: true,
-                allow_chiitoitsu: true,
-            },
-        );
+        let calculator = calculate_shanten(&hand.tiles[0..13], &DEFAULT_SCORE_SETTINGS);
         assert_eq!(calculator.get_calculated_shanten(), 1);
         assert_eq!(
             convert_frequency_table_to_flat_vec(&calculator.best_waits),
@@ -702,17 +1464,277 @@ mod tests {
     #[test]
     fn test_example_hand_two_shanten() {
         let hand = input_output::make_hand_from_string("122456789m1369p").unwrap();
-        let calculator = calculate_shanten(
-            &hand.tiles[0..13],
-            &ScoreCalculationSettings {
-                allow_kokushi: true,
-                allow_chiitoitsu: true,
-            },
-        );
+        let calculator = calculate_shanten(&hand.tiles[0..13], &DEFAULT_SCORE_SETTINGS);
         assert_eq!(calculator.get_calculated_shanten(), 2);
         assert_eq!(
             convert_frequency_table_to_flat_vec(&calculator.best_waits),
             input_output::make_tile_sequence_from_string("1234m2456789p").unwrap()
         );
     }
+
+    #[test]
+    fn test_red_five_counts_as_normal_five_for_shanten() {
+        let settings = DEFAULT_SCORE_SETTINGS;
+        let hand = input_output::make_hand_from_string("123406789m1334p").unwrap();
+        assert!(hand.tiles[4].is_red);
+        let calculator = calculate_shanten(&hand.tiles[0..13], &settings);
+        assert_eq!(calculator.get_calculated_shanten(), 1);
+        assert_eq!(
+            convert_frequency_table_to_flat_vec(&calculator.best_waits),
+            input_output::make_tile_sequence_from_string("123456p").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_red_five_is_kept_between_equal_discards() {
+        let hand = input_output::make_hand_from_string("123m456p789s5550s1z").unwrap();
+        let mut visible_tiles = make_frequency_table(&hand.tiles);
+        let mut settings = DEFAULT_SCORE_SETTINGS;
+        let shanten = calculate_shanten(&hand.tiles, &settings).get_calculated_shanten();
+        let best_discards =
+            calculate_best_discards_ukeire2(&hand.tiles, shanten, &mut visible_tiles, &settings);
+
+        let red_five = input_output::make_tile_sequence_from_string("0s").unwrap()[0];
+        assert_eq!(
+            get_discard_score(&best_discards, &red_five),
+            best_discards[0].score
+        );
+        // the red five is still a correct discard, it is only listed last
+        assert_eq!(
+            get_best_discard_scores(&best_discards).tiles,
+            input_output::make_tile_sequence_from_string("50s").unwrap()
+        );
+
+        settings.prefer_keeping_red_fives = false;
+        let best_discards =
+            calculate_best_discards_ukeire2(&hand.tiles, shanten, &mut visible_tiles, &settings);
+        let mut best_tiles = get_best_discard_scores(&best_discards).tiles;
+        best_tiles.sort();
+        assert_eq!(
+            best_tiles,
+            input_output::make_tile_sequence_from_string("50s").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_shanten_with_open_meld() {
+        let settings = DEFAULT_SCORE_SETTINGS;
+        let hand = input_output::make_hand_from_string("23m456p789s11z+789m").unwrap();
+        let closed_tiles = get_closed_tiles(&hand);
+        assert_eq!(closed_tiles.len(), 10);
+
+        let calculator = calculate_shanten(&closed_tiles, &settings);
+        assert_eq!(calculator.get_calculated_shanten(), 0);
+        assert_eq!(
+            filter_tiles_finishing_hand(
+                &closed_tiles,
+                &convert_frequency_table_to_flat_vec(calculator.get_best_waits()),
+                &settings
+            ),
+            input_output::make_tile_sequence_from_string("14m").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_lookahead_depth_is_limited_by_shanten() {
+        let mut settings = DEFAULT_SCORE_SETTINGS;
+        let hand = input_output::make_hand_from_string("122456789m1369p1z").unwrap();
+        let shanten = calculate_shanten(&hand.tiles, &settings).get_calculated_shanten();
+        assert_eq!(shanten, 2);
+        assert_eq!(get_lookahead_depth_value(LookaheadDepth::Exact, shanten), 3);
+        assert_eq!(get_lookahead_depth_value(LookaheadDepth::Ukeire3, 0), 1);
+
+        let get_scores = |settings: &ScoreCalculationSettings| -> Vec<(Tile, u32)> {
+            let mut visible_tiles = make_frequency_table(&hand.tiles);
+            return calculate_best_discards_with_depth(
+                &hand.tiles,
+                shanten,
+                &mut visible_tiles,
+                settings.lookahead_depth,
+                settings,
+            )
+            .iter()
+            .map(|discard| (discard.tile, discard.score))
+            .collect();
+        };
+
+        let ukeire2_scores = get_scores(&settings);
+        let mut visible_tiles = make_frequency_table(&hand.tiles);
+        let ukeire1_scores: Vec<(Tile, u32)> =
+            calculate_best_discards_ukeire1(&hand.tiles, shanten, &visible_tiles, &settings)
+                .iter()
+                .map(|discard| (discard.tile, discard.score))
+                .collect();
+        assert!(ukeire1_scores[0].1 < ukeire2_scores[0].1);
+        assert_eq!(
+            ukeire2_scores,
+            calculate_best_discards_ukeire2(&hand.tiles, shanten, &mut visible_tiles, &settings)
+                .iter()
+                .map(|discard| (discard.tile, discard.score))
+                .collect::<Vec<(Tile, u32)>>()
+        );
+
+        settings.lookahead_depth = LookaheadDepth::Ukeire3;
+        let ukeire3_scores = get_scores(&settings);
+        assert!(!ukeire3_scores.is_empty());
+        assert!(ukeire3_scores.windows(2).all(|pair| pair[0].1 >= pair[1].1));
+        assert!(ukeire3_scores[0].1 > ukeire2_scores[0].1);
+
+        settings.lookahead_depth = LookaheadDepth::Exact;
+        assert_eq!(get_scores(&settings), ukeire3_scores);
+    }
+
+    #[test]
+    fn test_exact_lookahead_is_limited_by_node_budget() {
+        let mut settings = ScoreCalculationSettings {
+            lookahead_depth: LookaheadDepth::Exact,
+            ..DEFAULT_SCORE_SETTINGS
+        };
+        let hand = input_output::make_hand_from_string("1479m2588p369s147z").unwrap();
+        let shanten = calculate_shanten(&hand.tiles, &settings).get_calculated_shanten();
+        assert_eq!(shanten, 5);
+
+        let get_scores = |settings: &ScoreCalculationSettings| -> Vec<(Tile, u32)> {
+            let mut visible_tiles = make_frequency_table(&hand.tiles);
+            return calculate_best_discards_with_depth(
+                &hand.tiles,
+                shanten,
+                &mut visible_tiles,
+                settings.lookahead_depth,
+                settings,
+            )
+            .iter()
+            .map(|discard| (discard.tile, discard.score))
+            .collect();
+        };
+
+        // six steps to complete the hand are too many, a shallower depth is used
+        let exact_scores = get_scores(&settings);
+        assert!(!exact_scores.is_empty());
+        // the second call uses the cache of the first one
+        assert_eq!(get_scores(&settings), exact_scores);
+
+        // a cache filled by a neighbouring position gives the same result as an empty one
+        let get_scores_with_cache = |hand_tiles: &[Tile], cache: &mut LookaheadCache| {
+            let shanten = calculate_shanten(hand_tiles, &settings).get_calculated_shanten();
+            let mut visible_tiles = make_frequency_table(hand_tiles);
+            let mut discards = calculate_best_discards_with_cache(
+                hand_tiles,
+                shanten,
+                &mut visible_tiles,
+                LookaheadDepth::Exact,
+                &settings,
+                cache,
+            );
+            sort_weighted_discards(&mut discards, &settings);
+            return discards
+                .iter()
+                .map(|discard| (discard.tile, discard.score))
+                .collect::<Vec<(Tile, u32)>>();
+        };
+        // 7z is discarded and 3p is drawn
+        let next_hand_tiles =
+            input_output::make_tile_sequence_from_string("1479m23588p369s14z").unwrap();
+        let cold_scores =
+            get_scores_with_cache(&next_hand_tiles, &mut make_empty_lookahead_cache(&settings));
+        let mut warm_cache = make_empty_lookahead_cache(&settings);
+        get_scores_with_cache(&hand.tiles, &mut warm_cache);
+        assert!(!warm_cache.scores.is_empty());
+        assert_eq!(
+            get_scores_with_cache(&next_hand_tiles, &mut warm_cache),
+            cold_scores
+        );
+
+        settings.lookahead_depth = LookaheadDepth::Ukeire2;
+        let ukeire2_scores = get_scores(&settings);
+        settings.lookahead_depth = LookaheadDepth::Ukeire3;
+        let ukeire3_scores = get_scores(&settings);
+        assert!(exact_scores == ukeire2_scores || exact_scores == ukeire3_scores);
+    }
+
+    #[test]
+    fn test_wait_shapes_are_classified() {
+        let expected_shapes = [
+            ("123456789m34p11s", WaitShape::Ryanmen, "25p"),
+            ("123456789m35p11s", WaitShape::Kanchan, "4p"),
+            ("123456789m12p11s", WaitShape::Penchan, "3p"),
+            ("123456789m11p11s", WaitShape::Shanpon, "1p1s"),
+            ("123456789m111p1s", WaitShape::Tanki, "1s"),
+            ("123456789m1234p", WaitShape::Nobetan, "14p"),
+            ("123456m23444p11s", WaitShape::Entotsu, "14p1s"),
+            ("123456m23456p11s", WaitShape::Sanmenchan, "147p"),
+            (
+                "1112345678999m",
+                WaitShape::MultiSidedChinitsu,
+                "123456789m",
+            ),
+            ("123456789m1113p", WaitShape::Complex, "23p"),
+        ];
+
+        for (hand_string, shape, waits) in expected_shapes {
+            let tiles = input_output::make_tile_sequence_from_string(hand_string).unwrap();
+            let wait_shape = classify_wait_shape(&tiles).unwrap();
+            assert_eq!(wait_shape.shape, shape, "wrong shape of {}", hand_string);
+            assert_eq!(
+                input_output::get_short_tiles_text(&wait_shape.waits),
+                waits,
+                "wrong waits of {}",
+                hand_string
+            );
+        }
+
+        let not_tenpai_tiles =
+            input_output::make_tile_sequence_from_string("123456789m1357p").unwrap();
+        assert!(classify_wait_shape(&not_tenpai_tiles).is_none());
+    }
+
+    #[test]
+    fn test_wait_reading_keeps_red_fives() {
+        let tiles = input_output::make_tile_sequence_from_string("123456789m40p11s").unwrap();
+        let wait_shape = classify_wait_shape(&tiles).unwrap();
+        assert_eq!(wait_shape.shape, WaitShape::Ryanmen);
+        assert_eq!(wait_shape.readings.len(), 1);
+        assert_eq!(
+            input_output::get_short_tiles_text(&wait_shape.readings[0].waiting_tiles),
+            "11s40p"
+        );
+    }
+
+    #[test]
+    fn test_optimal_decompositions_are_listed() {
+        let tiles = input_output::make_tile_sequence_from_string("123456789m34p1s5z").unwrap();
+        let decompositions = find_optimal_decompositions(&tiles);
+        assert_eq!(decompositions.len(), 1);
+        assert_eq!(decompositions[0].sets.len(), 3);
+        assert_eq!(decompositions[0].pair, None);
+        assert_eq!(decompositions[0].protoruns.len(), 1);
+        assert_eq!(
+            input_output::get_short_tiles_text(&decompositions[0].protoruns[0]),
+            "34p"
+        );
+        assert_eq!(
+            input_output::get_short_tiles_text(&decompositions[0].floating_tiles),
+            "1s5z"
+        );
+
+        // tenpai both as a pair with a ryanmen and as a tanki
+        let tiles = input_output::make_tile_sequence_from_string("123456789m2234p").unwrap();
+        let decompositions = find_optimal_decompositions(&tiles);
+        assert_eq!(decompositions.len(), 2);
+        for decomposition in &decompositions {
+            let tile_count = decomposition.sets.len() * 3
+                + decomposition.pair.as_ref().map_or(0, |pair| pair.len())
+                + decomposition.protoruns.len() * 2
+                + decomposition.floating_tiles.len();
+            assert_eq!(tile_count, 13);
+        }
+
+        // melds are counted as sets but not listed
+        let tiles = input_output::make_tile_sequence_from_string("2234p").unwrap();
+        let decompositions = find_optimal_decompositions(&tiles);
+        assert_eq!(decompositions.len(), 2);
+        assert!(decompositions
+            .iter()
+            .any(|decomposition| decomposition.sets.is_empty() && decomposition.pair.is_some()));
+    }
 }
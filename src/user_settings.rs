@@ -2,28 +2,58 @@ use serde::{Deserialize, Serialize};
 
 use crate::game_logic::GameSettings;
 use crate::input_output::*;
-use crate::ukeire_calculator::ScoreCalculationSettings;
+use crate::quizzes::{QuizDifficulty, QuizSettings};
+use crate::ukeire_calculator::{DiscardRanking, LookaheadDepth, ScoreCalculationSettings};
 
 #[derive(Clone, Serialize, Deserialize)]
 pub struct UserSettings {
     pub display_settings: DisplaySettings,
     pub score_settings: ScoreCalculationSettings,
     pub game_settings: GameSettings,
+    pub quiz_settings: QuizSettings,
+    pub training_aid_settings: TrainingAidSettings,
 }
 
+// the aids can be turned off to play the hands without any help
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TrainingAidSettings {
+    pub hints_enabled: bool,
+    pub undo_enabled: bool,
+}
+
+// the settings new users start with, the tests and benchmarks use them as well
+pub const DEFAULT_SCORE_SETTINGS: ScoreCalculationSettings = ScoreCalculationSettings {
+    allow_kokushi: true,
+    allow_chiitoitsu: true,
+    prefer_keeping_red_fives: true,
+    discard_ranking: DiscardRanking::Efficiency,
+    lookahead_depth: LookaheadDepth::Ukeire2,
+};
+
+pub const DEFAULT_GAME_SETTINGS: GameSettings = GameSettings {
+    deal_first_tile: true,
+    include_honors: true,
+    red_fives_per_suit: 0,
+    simulate_opponents: false,
+    single_suit: false,
+};
+
 pub fn get_default_settings() -> UserSettings {
     UserSettings {
         display_settings: DisplaySettings {
             terms_display: TermsDisplayOption::EnglishTerms,
             language_key: "ene".to_string(),
         },
-        score_settings: ScoreCalculationSettings {
-            allow_kokushi: true,
-            allow_chiitoitsu: true,
-        },
-        game_settings: GameSettings {
-            deal_first_tile: true,
+        score_settings: DEFAULT_SCORE_SETTINGS,
+        game_settings: DEFAULT_GAME_SETTINGS,
+        quiz_settings: QuizSettings {
+            difficulty: QuizDifficulty::Normal,
+            single_suit: false,
             include_honors: true,
         },
+        training_aid_settings: TrainingAidSettings {
+            hints_enabled: true,
+            undo_enabled: true,
+        },
     }
 }
@@ -1,15 +1,30 @@
 use crate::game_logic::*;
 use crate::json_file_updater::*;
+use crate::mistake_review::*;
+use crate::puzzles::Puzzle;
+use crate::quizzes::Quiz;
 use crate::ukeire_calculator::*;
 use crate::user_settings::*;
 use crate::user_state_updaters;
+use crate::user_stats::*;
 use dashmap::DashMap;
+use std::fmt;
 use std::path::Path;
-use teloxide::types::ChatId;
 
 use crate::user_state_updaters::update_user_states_to_the_latest_version;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
+// the id of the chat with the player, the frontends that have only one player use a constant one
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ChatId(pub i64);
+
+impl fmt::Display for ChatId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(f, "{}", self.0);
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct UserStates {
     // json format version, needed to update if the format changes
@@ -19,6 +34,15 @@ pub struct UserStates {
     pub states: DashMap<ChatId, UserState>,
 }
 
+#[derive(Clone, Serialize, Deserialize)]
+pub struct MoveHistoryEntry {
+    pub move_data: PreviousMoveData,
+    pub score: u32,
+    pub best_score: u32,
+    pub is_best_discard: bool,
+    pub shanten_after_discard: i8,
+}
+
 #[derive(Clone)]
 pub struct UserState {
     pub game_state: Option<GameState>,
@@ -26,9 +50,126 @@ pub struct UserState {
     pub best_score: u32,
     pub efficiency_sum: f32,
     pub moves: u32,
-    pub previous_move: Option<PreviousMoveData>,
+    pub mistakes_by_shanten: [u32; TRACKED_SHANTEN_LEVELS], // for the current hand
+    pub undo_count: u32,                                    // for the current hand
+    // changes every time new discard buttons are shown, the older buttons are not accepted
+    pub position_id: u64,
+    pub move_history: Vec<MoveHistoryEntry>, // moves of the current hand, or of the last finished one
+    pub last_hand_move_history: Vec<MoveHistoryEntry>,
+    pub puzzle: Option<Puzzle>, // the puzzle the current hand is from
+    pub quiz: Option<Quiz>,
+    pub reviewed_mistake: Option<usize>, // index in the mistake log if the puzzle is a review
     pub settings: UserSettings,
     pub settings_unsaved: bool,
+    pub stats: UserStats,
+    pub mistake_log: MistakeLog,
+    pub stats_unsaved: bool,   // stats or the mistake log
+    pub session_unsaved: bool, // the running hand, the puzzle, or the quiz
+}
+
+// the running hand with its scores, so it can be continued after a restart
+#[derive(Serialize)]
+struct SerializedSession<'a> {
+    game_state: &'a Option<GameState>,
+    current_score: u32,
+    best_score: u32,
+    efficiency_sum: f32,
+    moves: u32,
+    mistakes_by_shanten: [u32; TRACKED_SHANTEN_LEVELS],
+    undo_count: u32,
+    position_id: u64,
+    move_history: &'a Vec<MoveHistoryEntry>,
+    last_hand_move_history: &'a Vec<MoveHistoryEntry>,
+    puzzle: &'a Option<Puzzle>,
+    quiz: &'a Option<Quiz>,
+    reviewed_mistake: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct DeserializedSession {
+    game_state: Option<GameState>,
+    current_score: u32,
+    best_score: u32,
+    efficiency_sum: f32,
+    moves: u32,
+    mistakes_by_shanten: [u32; TRACKED_SHANTEN_LEVELS],
+    // the sessions in the database are not updated by the json updaters
+    #[serde(default)]
+    undo_count: u32,
+    #[serde(default)]
+    position_id: u64,
+    move_history: Vec<MoveHistoryEntry>,
+    last_hand_move_history: Vec<MoveHistoryEntry>,
+    puzzle: Option<Puzzle>,
+    quiz: Option<Quiz>,
+    reviewed_mistake: Option<usize>,
+}
+
+// the settings are stored at the root of the state
+#[derive(Serialize)]
+struct SerializedUserState<'a> {
+    #[serde(flatten)]
+    settings: &'a UserSettings,
+    stats: &'a UserStats,
+    mistake_log: &'a MistakeLog,
+    session: SerializedSession<'a>,
+}
+
+#[derive(Deserialize)]
+struct DeserializedUserState {
+    #[serde(flatten)]
+    settings: UserSettings,
+    stats: UserStats,
+    mistake_log: MistakeLog,
+    session: Option<DeserializedSession>,
+}
+
+fn make_serialized_session(user_state: &UserState) -> SerializedSession<'_> {
+    return SerializedSession {
+        game_state: &user_state.game_state,
+        current_score: user_state.current_score,
+        best_score: user_state.best_score,
+        efficiency_sum: user_state.efficiency_sum,
+        moves: user_state.moves,
+        mistakes_by_shanten: user_state.mistakes_by_shanten,
+        undo_count: user_state.undo_count,
+        position_id: user_state.position_id,
+        move_history: &user_state.move_history,
+        last_hand_move_history: &user_state.last_hand_move_history,
+        puzzle: &user_state.puzzle,
+        quiz: &user_state.quiz,
+        reviewed_mistake: user_state.reviewed_mistake,
+    };
+}
+
+fn apply_deserialized_session(user_state: &mut UserState, session: DeserializedSession) {
+    user_state.game_state = session.game_state;
+    user_state.current_score = session.current_score;
+    user_state.best_score = session.best_score;
+    user_state.efficiency_sum = session.efficiency_sum;
+    user_state.moves = session.moves;
+    user_state.mistakes_by_shanten = session.mistakes_by_shanten;
+    user_state.undo_count = session.undo_count;
+    user_state.position_id = session.position_id;
+    user_state.move_history = session.move_history;
+    user_state.last_hand_move_history = session.last_hand_move_history;
+    user_state.puzzle = session.puzzle;
+    user_state.quiz = session.quiz;
+    user_state.reviewed_mistake = session.reviewed_mistake;
+}
+
+// used by the storages that keep the session separately from the settings
+pub fn get_session_json(user_state: &UserState) -> Result<String, serde_json::Error> {
+    return serde_json::to_string(&make_serialized_session(user_state));
+}
+
+pub fn restore_session_from_json(
+    user_state: &mut UserState,
+    session_json: &str,
+) -> Result<(), serde_json::Error> {
+    let session: DeserializedSession = serde_json::from_str(session_json)?;
+    apply_deserialized_session(user_state, session);
+    return Ok(());
 }
 
 impl Serialize for UserState {
@@ -36,7 +177,13 @@ impl Serialize for UserState {
     where
         S: Serializer,
     {
-        self.settings.serialize(serializer)
+        SerializedUserState {
+            settings: &self.settings,
+            stats: &self.stats,
+            mistake_log: &self.mistake_log,
+            session: make_serialized_session(self),
+        }
+        .serialize(serializer)
     }
 }
 
@@ -45,8 +192,14 @@ impl<'de> Deserialize<'de> for UserState {
     where
         D: Deserializer<'de>,
     {
+        let deserialized_state = DeserializedUserState::deserialize(deserializer)?;
         let mut user_state = get_default_user_state();
-        user_state.settings = UserSettings::deserialize(deserializer)?;
+        user_state.settings = deserialized_state.settings;
+        user_state.stats = deserialized_state.stats;
+        user_state.mistake_log = deserialized_state.mistake_log;
+        if let Some(session) = deserialized_state.session {
+            apply_deserialized_session(&mut user_state, session);
+        }
         return Ok(user_state);
     }
 }
@@ -58,9 +211,20 @@ pub fn get_default_user_state() -> UserState {
         best_score: 0,
         efficiency_sum: 0.0,
         moves: 0,
-        previous_move: None,
+        mistakes_by_shanten: [0; TRACKED_SHANTEN_LEVELS],
+        undo_count: 0,
+        position_id: 0,
+        move_history: Vec::new(),
+        last_hand_move_history: Vec::new(),
+        puzzle: None,
+        quiz: None,
+        reviewed_mistake: None,
         settings: get_default_settings(),
         settings_unsaved: false,
+        stats: UserStats::default(),
+        mistake_log: MistakeLog::default(),
+        stats_unsaved: false,
+        session_unsaved: false,
     }
 }
 
@@ -83,14 +247,14 @@ pub fn read_user_states_from_file(user_states_file_path: &Path) -> UserStates {
                 panic!("Failed to serialize the default UserStates: {}", err);
             }
         };
-        let result = std::fs::write(&user_states_file_path, data);
+        let result = std::fs::write(user_states_file_path, data);
         if let Err(err) = result {
             panic!("Failed to write the default UserStates: {}", err);
         }
     }
 
     // read the user states file from the disk
-    let data = std::fs::read_to_string(&user_states_file_path);
+    let data = std::fs::read_to_string(user_states_file_path);
     let data = match data {
         Ok(data) => data,
         Err(err) => {
@@ -140,7 +304,7 @@ pub fn read_user_states_from_file(user_states_file_path: &Path) -> UserStates {
                 panic!("Failed to serialize the updated user states: {}", err);
             }
         };
-        let result = std::fs::write(&user_states_file_path, data);
+        let result = std::fs::write(user_states_file_path, data);
         if let Err(err) = result {
             panic!("Failed to write the updated user states: {}", err);
         }
@@ -158,7 +322,7 @@ pub fn save_user_states_to_file(user_states: &UserStates, user_states_file_path:
             return;
         }
     };
-    let result = std::fs::write(&user_states_file_path, data);
+    let result = std::fs::write(user_states_file_path, data);
     if let Err(err) = result {
         eprintln!("Failed to write the user states: {}", err);
     }
@@ -166,8 +330,17 @@ pub fn save_user_states_to_file(user_states: &UserStates, user_states_file_path:
 
 pub fn save_single_user_state(file_path: &Path, chat_id: ChatId, user_state: &UserState) {
     // this is quite terrible, but we need to do that in order to not lock states of other users
-    // this should ideally be replaced by  sqlite database
+    // the sqlite storage should be used instead when the number of users grows
     let user_states = read_user_states_from_file(file_path);
     user_states.states.insert(chat_id, user_state.clone());
     save_user_states_to_file(&user_states, file_path);
 }
+
+// the file is read and written once for all the given states
+pub fn save_several_user_states(file_path: &Path, changed_states: &[(ChatId, UserState)]) {
+    let user_states = read_user_states_from_file(file_path);
+    for (chat_id, user_state) in changed_states {
+        user_states.states.insert(*chat_id, user_state.clone());
+    }
+    save_user_states_to_file(&user_states, file_path);
+}
@@ -0,0 +1,829 @@
+use crate::json_file_updater::{JsonFileUpdaterError, UpdateResult};
+use crate::mistake_review::*;
+use crate::user_settings::UserSettings;
+use crate::user_state::*;
+use crate::user_state_updaters::{update_user_states_to_the_latest_version, LATEST_SAVE_VERSION};
+use crate::user_stats::*;
+use rusqlite::{params, Connection, OptionalExtension, Transaction};
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+// the states are read once on start, and the state of a user is written after it is changed
+pub trait UserStateStorage: Send + Sync {
+    fn load_user_states(&self) -> UserStates;
+    fn save_user_state(&self, chat_id: ChatId, user_state: &UserState);
+    // used for the periodic saves of the running hands, so the storage is written once for all of them
+    fn save_user_states(&self, user_states: &[(ChatId, UserState)]);
+}
+
+// all the states are stored in one json file that is rewritten on every save
+pub struct JsonUserStateStorage {
+    file_path: PathBuf,
+}
+
+impl JsonUserStateStorage {
+    pub fn new(file_path: &Path) -> Self {
+        Self {
+            file_path: file_path.to_path_buf(),
+        }
+    }
+}
+
+impl UserStateStorage for JsonUserStateStorage {
+    fn load_user_states(&self) -> UserStates {
+        return read_user_states_from_file(&self.file_path);
+    }
+
+    fn save_user_state(&self, chat_id: ChatId, user_state: &UserState) {
+        save_single_user_state(&self.file_path, chat_id, user_state);
+    }
+
+    fn save_user_states(&self, user_states: &[(ChatId, UserState)]) {
+        save_several_user_states(&self.file_path, user_states);
+    }
+}
+
+// the settings are stored as json and updated with the same updaters as the json file,
+// the hands and the mistakes have their own tables and need schema changes to be updated
+// version 2 added the sessions table
+// version 3 added the undo count to the finished hands
+const SCHEMA_VERSION: i64 = 3;
+static SETTINGS_VERSION_KEY: &str = "settings_version";
+static SCHEMA_VERSION_KEY: &str = "schema_version";
+static JSON_IMPORTED_KEY: &str = "json_imported";
+
+pub struct SqliteUserStateStorage {
+    connection: Mutex<Connection>,
+}
+
+impl SqliteUserStateStorage {
+    // the states from the json file are imported once when it is given and exists
+    pub fn open(database_path: &Path, json_file_path: Option<&Path>) -> Self {
+        let connection = match Connection::open(database_path) {
+            Ok(connection) => connection,
+            Err(err) => panic!(
+                "Failed to open the user states database '{}': {}",
+                database_path.display(),
+                err
+            ),
+        };
+        return Self::from_connection(connection, json_file_path);
+    }
+
+    pub fn from_connection(connection: Connection, json_file_path: Option<&Path>) -> Self {
+        if let Err(err) = create_tables(&connection) {
+            panic!("Failed to create the user states tables: {}", err);
+        }
+        let storage = Self {
+            connection: Mutex::new(connection),
+        };
+
+        if let Some(json_file_path) = json_file_path {
+            if let Err(err) = storage.import_json_file(json_file_path) {
+                panic!(
+                    "Failed to import the user states from the json file: {}",
+                    err
+                );
+            }
+        }
+        return storage;
+    }
+
+    fn import_json_file(&self, json_file_path: &Path) -> rusqlite::Result<()> {
+        let mut connection = self.lock_connection();
+        if read_metadata(&connection, JSON_IMPORTED_KEY)?.is_some() || !json_file_path.exists() {
+            return Ok(());
+        }
+
+        let user_states = read_user_states_from_file(json_file_path);
+        let transaction = connection.transaction()?;
+        for entry in user_states.states.iter() {
+            write_user_state(&transaction, *entry.key(), entry.value())?;
+        }
+        write_metadata(&transaction, JSON_IMPORTED_KEY, "1")?;
+        transaction.commit()?;
+        println!(
+            "Imported {} user states from '{}'",
+            user_states.states.len(),
+            json_file_path.display()
+        );
+        return Ok(());
+    }
+
+    fn lock_connection(&self) -> std::sync::MutexGuard<'_, Connection> {
+        // the connection stays usable even if another thread panicked while holding it
+        return self
+            .connection
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+    }
+
+    fn read_user_states(&self) -> rusqlite::Result<UserStates> {
+        let mut connection = self.lock_connection();
+
+        let settings_version = read_metadata(&connection, SETTINGS_VERSION_KEY)?
+            .unwrap_or(LATEST_SAVE_VERSION.to_string());
+        let mut settings_json = json!({ "version": settings_version, "states": {} });
+        {
+            let mut statement =
+                connection.prepare("SELECT chat_id, settings FROM user_settings")?;
+            let rows = statement.query_map([], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+            })?;
+            for row in rows {
+                let (chat_id, settings) = row?;
+                settings_json["states"][chat_id.to_string()] = match serde_json::from_str(&settings)
+                {
+                    Ok(settings) => settings,
+                    Err(err) => panic!("Failed to parse the settings of user {}: {}", chat_id, err),
+                };
+            }
+        }
+
+        let update_result = update_user_states_to_the_latest_version(&mut settings_json);
+        if let UpdateResult::Error(JsonFileUpdaterError::UnknownVersion {
+            version,
+            latest_version,
+        }) = &update_result
+        {
+            panic!(
+                "Unknown version of the user settings in the database: {}. The latest version is {}",
+                version, latest_version
+            );
+        }
+
+        let user_states = get_default_user_states();
+        let Some(states) = settings_json["states"].as_object() else {
+            return Ok(user_states);
+        };
+        for (chat_id, settings) in states {
+            let Ok(chat_id) = chat_id.parse::<i64>() else {
+                eprintln!("Skipped the settings with an invalid chat id '{}'", chat_id);
+                continue;
+            };
+            let mut user_state = get_default_user_state();
+            user_state.settings = match UserSettings::deserialize(settings) {
+                Ok(settings) => settings,
+                Err(err) => panic!(
+                    "Failed to deserialize the settings of user {}: {}",
+                    chat_id, err
+                ),
+            };
+            user_state.stats = read_stats(&connection, chat_id)?;
+            user_state.mistake_log = read_mistake_log(&connection, chat_id)?;
+            read_session(&connection, chat_id, &mut user_state)?;
+            user_states.states.insert(ChatId(chat_id), user_state);
+        }
+
+        if update_result == UpdateResult::Updated {
+            println!("The user settings in the database have been updated to the latest version");
+            let transaction = connection.transaction()?;
+            for entry in user_states.states.iter() {
+                write_user_settings(&transaction, *entry.key(), &entry.value().settings)?;
+            }
+            write_metadata(&transaction, SETTINGS_VERSION_KEY, LATEST_SAVE_VERSION)?;
+            transaction.commit()?;
+        }
+
+        return Ok(user_states);
+    }
+
+    fn write_user_state(&self, chat_id: ChatId, user_state: &UserState) -> rusqlite::Result<()> {
+        let mut connection = self.lock_connection();
+        let transaction = connection.transaction()?;
+        write_user_state(&transaction, chat_id, user_state)?;
+        return transaction.commit();
+    }
+
+    fn write_user_states(&self, user_states: &[(ChatId, UserState)]) -> rusqlite::Result<()> {
+        let mut connection = self.lock_connection();
+        let transaction = connection.transaction()?;
+        for (chat_id, user_state) in user_states {
+            write_user_state(&transaction, *chat_id, user_state)?;
+        }
+        return transaction.commit();
+    }
+}
+
+impl UserStateStorage for SqliteUserStateStorage {
+    fn load_user_states(&self) -> UserStates {
+        return match self.read_user_states() {
+            Ok(user_states) => user_states,
+            Err(err) => panic!("Failed to read the user states from the database: {}", err),
+        };
+    }
+
+    fn save_user_state(&self, chat_id: ChatId, user_state: &UserState) {
+        if let Err(err) = self.write_user_state(chat_id, user_state) {
+            eprintln!("Failed to write the state of user {}: {}", chat_id, err);
+        }
+    }
+
+    fn save_user_states(&self, user_states: &[(ChatId, UserState)]) {
+        if let Err(err) = self.write_user_states(user_states) {
+            eprintln!(
+                "Failed to write the states of {} users: {}",
+                user_states.len(),
+                err
+            );
+        }
+    }
+}
+
+fn create_tables(connection: &Connection) -> rusqlite::Result<()> {
+    connection.execute_batch(
+        "CREATE TABLE IF NOT EXISTS metadata (
+            key TEXT PRIMARY KEY,
+            value TEXT NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS user_settings (
+            chat_id INTEGER PRIMARY KEY,
+            settings TEXT NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS finished_hands (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            chat_id INTEGER NOT NULL,
+            timestamp INTEGER NOT NULL,
+            seed TEXT NOT NULL,
+            efficiency REAL NOT NULL,
+            moves INTEGER NOT NULL,
+            turns_to_tenpai INTEGER,
+            mistakes_by_shanten TEXT NOT NULL,
+            undo_count INTEGER NOT NULL DEFAULT 0
+        );
+        CREATE INDEX IF NOT EXISTS finished_hands_chat_id ON finished_hands (chat_id);
+        CREATE TABLE IF NOT EXISTS mistakes (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            chat_id INTEGER NOT NULL,
+            hand TEXT NOT NULL,
+            discards TEXT NOT NULL,
+            dora_indicators TEXT NOT NULL,
+            player_count INTEGER NOT NULL,
+            discarded_tile TEXT NOT NULL,
+            efficiency REAL NOT NULL,
+            timestamp INTEGER NOT NULL,
+            correct_answers_in_row INTEGER NOT NULL,
+            next_review_timestamp INTEGER NOT NULL
+        );
+        CREATE INDEX IF NOT EXISTS mistakes_chat_id ON mistakes (chat_id);
+        CREATE TABLE IF NOT EXISTS sessions (
+            chat_id INTEGER PRIMARY KEY,
+            session TEXT NOT NULL
+        );",
+    )?;
+
+    // a new database starts with the latest versions
+    connection.execute(
+        "INSERT OR IGNORE INTO metadata (key, value) VALUES (?1, ?2)",
+        params![SETTINGS_VERSION_KEY, LATEST_SAVE_VERSION],
+    )?;
+    // new tables are created above for older databases, new columns are added here
+    let schema_version = read_metadata(connection, SCHEMA_VERSION_KEY)?
+        .and_then(|version| version.parse::<i64>().ok())
+        .unwrap_or(SCHEMA_VERSION);
+    if schema_version < 3 {
+        connection.execute(
+            "ALTER TABLE finished_hands ADD COLUMN undo_count INTEGER NOT NULL DEFAULT 0",
+            [],
+        )?;
+    }
+    connection.execute(
+        "INSERT OR REPLACE INTO metadata (key, value) VALUES (?1, ?2)",
+        params![SCHEMA_VERSION_KEY, SCHEMA_VERSION.to_string()],
+    )?;
+    return Ok(());
+}
+
+fn read_metadata(connection: &Connection, key: &str) -> rusqlite::Result<Option<String>> {
+    return connection
+        .query_row(
+            "SELECT value FROM metadata WHERE key = ?1",
+            params![key],
+            |row| row.get(0),
+        )
+        .optional();
+}
+
+fn write_metadata(transaction: &Transaction, key: &str, value: &str) -> rusqlite::Result<()> {
+    transaction.execute(
+        "INSERT OR REPLACE INTO metadata (key, value) VALUES (?1, ?2)",
+        params![key, value],
+    )?;
+    return Ok(());
+}
+
+fn to_sql_error(err: serde_json::Error) -> rusqlite::Error {
+    return rusqlite::Error::ToSqlConversionFailure(Box::new(err));
+}
+
+fn from_sql_error(column: usize, err: serde_json::Error) -> rusqlite::Error {
+    return rusqlite::Error::FromSqlConversionFailure(
+        column,
+        rusqlite::types::Type::Text,
+        Box::new(err),
+    );
+}
+
+fn read_stats(connection: &Connection, chat_id: i64) -> rusqlite::Result<UserStats> {
+    let mut statement = connection.prepare(
+        "SELECT timestamp, seed, efficiency, moves, turns_to_tenpai, mistakes_by_shanten,
+            undo_count
+        FROM finished_hands WHERE chat_id = ?1 ORDER BY id",
+    )?;
+    let hands = statement.query_map(params![chat_id], |row| {
+        // u64 doesn't fit into the integer type of sqlite
+        let seed: String = row.get(1)?;
+        let mistakes_by_shanten: String = row.get(5)?;
+        Ok(HandRecord {
+            timestamp: row.get(0)?,
+            seed: seed.parse().unwrap_or_default(),
+            efficiency: row.get(2)?,
+            moves: row.get(3)?,
+            turns_to_tenpai: row.get(4)?,
+            mistakes_by_shanten: serde_json::from_str(&mistakes_by_shanten)
+                .map_err(|err| from_sql_error(5, err))?,
+            undo_count: row.get(6)?,
+        })
+    })?;
+    return Ok(UserStats {
+        hands: hands.collect::<rusqlite::Result<Vec<HandRecord>>>()?,
+    });
+}
+
+fn read_mistake_log(connection: &Connection, chat_id: i64) -> rusqlite::Result<MistakeLog> {
+    let mut statement = connection.prepare(
+        "SELECT hand, discards, dora_indicators, player_count, discarded_tile, efficiency,
+            timestamp, correct_answers_in_row, next_review_timestamp
+        FROM mistakes WHERE chat_id = ?1 ORDER BY id",
+    )?;
+    let mistakes = statement.query_map(params![chat_id], |row| {
+        Ok(MistakeRecord {
+            hand: row.get(0)?,
+            discards: row.get(1)?,
+            dora_indicators: row.get(2)?,
+            player_count: row.get(3)?,
+            discarded_tile: row.get(4)?,
+            efficiency: row.get(5)?,
+            timestamp: row.get(6)?,
+            correct_answers_in_row: row.get(7)?,
+            next_review_timestamp: row.get(8)?,
+        })
+    })?;
+    return Ok(MistakeLog {
+        mistakes: mistakes.collect::<rusqlite::Result<Vec<MistakeRecord>>>()?,
+    });
+}
+
+// a session that can't be restored is dropped, the user can start a new hand
+fn read_session(
+    connection: &Connection,
+    chat_id: i64,
+    user_state: &mut UserState,
+) -> rusqlite::Result<()> {
+    let session: Option<String> = connection
+        .query_row(
+            "SELECT session FROM sessions WHERE chat_id = ?1",
+            params![chat_id],
+            |row| row.get(0),
+        )
+        .optional()?;
+    if let Some(session) = session {
+        if let Err(err) = restore_session_from_json(user_state, &session) {
+            eprintln!("Failed to restore the session of user {}: {}", chat_id, err);
+        }
+    }
+    return Ok(());
+}
+
+fn write_user_settings(
+    transaction: &Transaction,
+    chat_id: ChatId,
+    settings: &UserSettings,
+) -> rusqlite::Result<()> {
+    let settings = serde_json::to_string(settings).map_err(to_sql_error)?;
+    transaction.execute(
+        "INSERT OR REPLACE INTO user_settings (chat_id, settings) VALUES (?1, ?2)",
+        params![chat_id.0, settings],
+    )?;
+    return Ok(());
+}
+
+// the history is written incrementally, only the hands and the mistakes changed since
+// the last save are written
+fn write_user_state(
+    transaction: &Transaction,
+    chat_id: ChatId,
+    user_state: &UserState,
+) -> rusqlite::Result<()> {
+    write_user_settings(transaction, chat_id, &user_state.settings)?;
+    transaction.execute(
+        "INSERT OR REPLACE INTO sessions (chat_id, session) VALUES (?1, ?2)",
+        params![
+            chat_id.0,
+            get_session_json(user_state).map_err(to_sql_error)?
+        ],
+    )?;
+    write_finished_hands(transaction, chat_id, &user_state.stats.hands)?;
+    write_mistakes(transaction, chat_id, &user_state.mistake_log.mistakes)?;
+    return Ok(());
+}
+
+// the hands are only appended and the oldest are trimmed, so the stored hands are
+// the beginning of the recorded ones up to the last stored hand
+fn write_finished_hands(
+    transaction: &Transaction,
+    chat_id: ChatId,
+    hands: &[HandRecord],
+) -> rusqlite::Result<()> {
+    let stored_count: usize = transaction.query_row(
+        "SELECT COUNT(*) FROM finished_hands WHERE chat_id = ?1",
+        params![chat_id.0],
+        |row| row.get(0),
+    )?;
+    let last_stored_hand: Option<(u64, String, u32)> = transaction
+        .query_row(
+            "SELECT timestamp, seed, moves FROM finished_hands WHERE chat_id = ?1
+            ORDER BY id DESC LIMIT 1",
+            params![chat_id.0],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .optional()?;
+
+    let first_new_hand = match last_stored_hand {
+        Some((timestamp, seed, moves)) => {
+            let last_stored_index = hands.iter().rposition(|hand| {
+                hand.timestamp == timestamp && hand.seed.to_string() == seed && hand.moves == moves
+            });
+            match last_stored_index {
+                Some(index) => {
+                    // the hands trimmed from the beginning of the stats since the last save
+                    transaction.execute(
+                        "DELETE FROM finished_hands WHERE id IN
+                            (SELECT id FROM finished_hands WHERE chat_id = ?1 ORDER BY id LIMIT ?2)",
+                        params![chat_id.0, stored_count.saturating_sub(index + 1)],
+                    )?;
+                    index + 1
+                }
+                None => {
+                    // the stored hands are not in the stats anymore, all of them are replaced
+                    transaction.execute(
+                        "DELETE FROM finished_hands WHERE chat_id = ?1",
+                        params![chat_id.0],
+                    )?;
+                    0
+                }
+            }
+        }
+        None => 0,
+    };
+
+    let mut insert_hand = transaction.prepare(
+        "INSERT INTO finished_hands
+            (chat_id, timestamp, seed, efficiency, moves, turns_to_tenpai, mistakes_by_shanten,
+            undo_count)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+    )?;
+    for hand in &hands[first_new_hand..] {
+        insert_hand.execute(params![
+            chat_id.0,
+            hand.timestamp,
+            hand.seed.to_string(),
+            hand.efficiency,
+            hand.moves,
+            hand.turns_to_tenpai,
+            serde_json::to_string(&hand.mistakes_by_shanten).map_err(to_sql_error)?,
+            hand.undo_count,
+        ])?;
+    }
+    return Ok(());
+}
+
+type StoredMistakeKey = (u64, String, String); // timestamp, hand, discarded tile
+type StoredMistakeReview = (i64, u32, u64); // row id, correct answers in row, next review
+
+// the mistakes are changed by the reviews and removed from any place in the log,
+// a mistake is found among the stored ones by its time and its position,
+// the same mistakes are matched in the order they were stored
+fn write_mistakes(
+    transaction: &Transaction,
+    chat_id: ChatId,
+    mistakes: &[MistakeRecord],
+) -> rusqlite::Result<()> {
+    let mut stored_mistakes: HashMap<StoredMistakeKey, Vec<StoredMistakeReview>> = HashMap::new();
+    {
+        let mut statement = transaction.prepare(
+            "SELECT id, timestamp, hand, discarded_tile, correct_answers_in_row,
+                next_review_timestamp
+            FROM mistakes WHERE chat_id = ?1 ORDER BY id DESC",
+        )?;
+        let rows = statement.query_map(params![chat_id.0], |row| {
+            Ok((
+                (row.get(1)?, row.get(2)?, row.get(3)?),
+                (row.get(0)?, row.get(4)?, row.get(5)?),
+            ))
+        })?;
+        for row in rows {
+            let (key, value) = row?;
+            stored_mistakes.entry(key).or_default().push(value);
+        }
+    }
+
+    let mut insert_mistake = transaction.prepare(
+        "INSERT INTO mistakes
+            (chat_id, hand, discards, dora_indicators, player_count, discarded_tile, efficiency,
+            timestamp, correct_answers_in_row, next_review_timestamp)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+    )?;
+    let mut update_mistake = transaction.prepare(
+        "UPDATE mistakes SET correct_answers_in_row = ?2, next_review_timestamp = ?3
+        WHERE id = ?1",
+    )?;
+    for mistake in mistakes {
+        let key = (
+            mistake.timestamp,
+            mistake.hand.clone(),
+            mistake.discarded_tile.clone(),
+        );
+        match stored_mistakes.get_mut(&key).and_then(Vec::pop) {
+            Some((id, correct_answers_in_row, next_review_timestamp)) => {
+                if correct_answers_in_row != mistake.correct_answers_in_row
+                    || next_review_timestamp != mistake.next_review_timestamp
+                {
+                    update_mistake.execute(params![
+                        id,
+                        mistake.correct_answers_in_row,
+                        mistake.next_review_timestamp,
+                    ])?;
+                }
+            }
+            None => {
+                insert_mistake.execute(params![
+                    chat_id.0,
+                    mistake.hand,
+                    mistake.discards,
+                    mistake.dora_indicators,
+                    mistake.player_count,
+                    mistake.discarded_tile,
+                    mistake.efficiency,
+                    mistake.timestamp,
+                    mistake.correct_answers_in_row,
+                    mistake.next_review_timestamp,
+                ])?;
+            }
+        }
+    }
+
+    // mastered, undone, or trimmed from the log since the last save
+    let mut delete_mistake = transaction.prepare("DELETE FROM mistakes WHERE id = ?1")?;
+    for (id, _, _) in stored_mistakes.values().flatten() {
+        delete_mistake.execute(params![id])?;
+    }
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_logic::generate_normal_dealt_game;
+    use crate::user_settings::get_default_settings;
+
+    fn make_test_user_state() -> UserState {
+        let mut user_state = get_default_user_state();
+        user_state.settings.game_settings.red_fives_per_suit = 2;
+        record_hand(
+            &mut user_state.stats,
+            HandRecord {
+                timestamp: 1000,
+                seed: u64::MAX,
+                efficiency: 0.75,
+                moves: 12,
+                turns_to_tenpai: Some(10),
+                mistakes_by_shanten: [0, 1, 2, 0, 0, 0, 0],
+                undo_count: 1,
+            },
+        );
+        log_mistake(
+            &mut user_state.mistake_log,
+            MistakeRecord {
+                hand: "123456789m1234p5z".to_string(),
+                discards: "9s".to_string(),
+                dora_indicators: "1m".to_string(),
+                player_count: 4,
+                discarded_tile: "1p".to_string(),
+                efficiency: 0.5,
+                timestamp: 1000,
+                correct_answers_in_row: 1,
+                next_review_timestamp: 2000,
+            },
+        );
+        return user_state;
+    }
+
+    fn assert_test_user_state_is_loaded(user_states: &UserStates, chat_id: ChatId) {
+        let user_state = user_states.states.get(&chat_id).unwrap();
+        assert_eq!(user_state.settings.game_settings.red_fives_per_suit, 2);
+        assert_eq!(user_state.stats.hands.len(), 1);
+        assert_eq!(user_state.stats.hands[0].seed, u64::MAX);
+        assert_eq!(user_state.stats.hands[0].turns_to_tenpai, Some(10));
+        assert_eq!(
+            user_state.stats.hands[0].mistakes_by_shanten,
+            [0, 1, 2, 0, 0, 0, 0]
+        );
+        assert_eq!(user_state.stats.hands[0].undo_count, 1);
+        assert_eq!(user_state.mistake_log.mistakes.len(), 1);
+        assert_eq!(
+            user_state.mistake_log.mistakes[0].next_review_timestamp,
+            2000
+        );
+    }
+
+    #[test]
+    fn test_sqlite_storage_saves_and_loads_user_state() {
+        let storage =
+            SqliteUserStateStorage::from_connection(Connection::open_in_memory().unwrap(), None);
+        let chat_id = ChatId(-42);
+
+        let mut user_state = make_test_user_state();
+        storage.save_user_state(chat_id, &user_state);
+        assert_test_user_state_is_loaded(&storage.load_user_states(), chat_id);
+
+        // the history is replaced and not appended on the next save
+        user_state.mistake_log.mistakes.clear();
+        storage.save_user_state(chat_id, &user_state);
+        let user_states = storage.load_user_states();
+        assert_eq!(user_states.states.len(), 1);
+        let loaded_state = user_states.states.get(&chat_id).unwrap();
+        assert_eq!(loaded_state.stats.hands.len(), 1);
+        assert!(loaded_state.mistake_log.mistakes.is_empty());
+    }
+
+    #[test]
+    fn test_only_changed_history_is_written() {
+        let storage =
+            SqliteUserStateStorage::from_connection(Connection::open_in_memory().unwrap(), None);
+        let chat_id = ChatId(9);
+        let get_row_ids = |table: &str| -> Vec<i64> {
+            let connection = storage.lock_connection();
+            let mut statement = connection
+                .prepare(&format!("SELECT id FROM {} ORDER BY id", table))
+                .unwrap();
+            let ids = statement.query_map([], |row| row.get(0)).unwrap();
+            return ids.map(Result::unwrap).collect();
+        };
+
+        let mut user_state = make_test_user_state();
+        let mut second_mistake = user_state.mistake_log.mistakes[0].clone();
+        second_mistake.timestamp = 1500;
+        log_mistake(&mut user_state.mistake_log, second_mistake);
+        storage.save_user_state(chat_id, &user_state);
+        let hand_ids = get_row_ids("finished_hands");
+        let mistake_ids = get_row_ids("mistakes");
+
+        let mut second_hand = user_state.stats.hands[0].clone();
+        second_hand.timestamp = 2000;
+        record_hand(&mut user_state.stats, second_hand);
+        update_mistake_after_review(&mut user_state.mistake_log, 1, true, 3000);
+        storage.save_user_state(chat_id, &user_state);
+        assert_eq!(get_row_ids("finished_hands")[0], hand_ids[0]);
+        assert_eq!(get_row_ids("finished_hands").len(), 2);
+        assert_eq!(get_row_ids("mistakes"), mistake_ids);
+
+        // the oldest hand is trimmed and the first mistake is mastered
+        user_state.stats.hands.remove(0);
+        user_state.mistake_log.mistakes.remove(0);
+        storage.save_user_state(chat_id, &user_state);
+        assert_eq!(get_row_ids("finished_hands").len(), 1);
+        assert_eq!(get_row_ids("mistakes"), mistake_ids[1..]);
+
+        let user_states = storage.load_user_states();
+        let loaded_state = user_states.states.get(&chat_id).unwrap();
+        assert_eq!(loaded_state.stats.hands.len(), 1);
+        assert_eq!(loaded_state.stats.hands[0].timestamp, 2000);
+        assert_eq!(loaded_state.mistake_log.mistakes.len(), 1);
+        assert_eq!(loaded_state.mistake_log.mistakes[0].timestamp, 1500);
+        assert_eq!(
+            loaded_state.mistake_log.mistakes[0].correct_answers_in_row,
+            2
+        );
+    }
+
+    #[test]
+    fn test_finished_hands_table_of_schema_version_2_is_upgraded() {
+        let connection = Connection::open_in_memory().unwrap();
+        connection
+            .execute_batch(
+                "CREATE TABLE metadata (key TEXT PRIMARY KEY, value TEXT NOT NULL);
+                INSERT INTO metadata (key, value) VALUES ('schema_version', '2');
+                CREATE TABLE finished_hands (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    chat_id INTEGER NOT NULL,
+                    timestamp INTEGER NOT NULL,
+                    seed TEXT NOT NULL,
+                    efficiency REAL NOT NULL,
+                    moves INTEGER NOT NULL,
+                    turns_to_tenpai INTEGER,
+                    mistakes_by_shanten TEXT NOT NULL
+                );",
+            )
+            .unwrap();
+
+        let storage = SqliteUserStateStorage::from_connection(connection, None);
+        let chat_id = ChatId(5);
+        storage.save_user_state(chat_id, &make_test_user_state());
+        assert_test_user_state_is_loaded(&storage.load_user_states(), chat_id);
+    }
+
+    #[test]
+    fn test_running_hand_is_restored() {
+        let mut game_settings = get_default_settings().game_settings;
+        game_settings.red_fives_per_suit = 3;
+        let mut user_state = get_default_user_state();
+        user_state.game_state = Some(generate_normal_dealt_game(2, &game_settings, 11).unwrap());
+        user_state.current_score = 30;
+        user_state.best_score = 40;
+        user_state.moves = 2;
+        let chat_id = ChatId(3);
+
+        let assert_running_hand_is_restored = |user_states: &UserStates| {
+            let loaded_state = user_states.states.get(&chat_id).unwrap();
+            let expected_game = user_state.game_state.as_ref().unwrap();
+            let loaded_game = loaded_state.game_state.as_ref().unwrap();
+            assert_eq!(loaded_game.hands[0].tiles, expected_game.hands[0].tiles);
+            assert_eq!(loaded_game.live_wall, expected_game.live_wall);
+            assert_eq!(loaded_game.dead_wall, expected_game.dead_wall);
+            assert_eq!(loaded_game.seed, 11);
+            assert_eq!(loaded_state.current_score, 30);
+            assert_eq!(loaded_state.best_score, 40);
+            assert_eq!(loaded_state.moves, 2);
+        };
+
+        let storage =
+            SqliteUserStateStorage::from_connection(Connection::open_in_memory().unwrap(), None);
+        storage.save_user_state(chat_id, &user_state);
+        assert_running_hand_is_restored(&storage.load_user_states());
+
+        let user_states = get_default_user_states();
+        user_states.states.insert(chat_id, user_state.clone());
+        let json = serde_json::to_string(&user_states).unwrap();
+        assert_running_hand_is_restored(&serde_json::from_str(&json).unwrap());
+    }
+
+    #[test]
+    fn test_json_file_is_imported_to_sqlite_once() {
+        let json_file_path = std::env::temp_dir().join(format!(
+            "riichi_trainer_storage_test_{}.json",
+            std::process::id()
+        ));
+        let chat_id = ChatId(7);
+        let json_storage = JsonUserStateStorage::new(&json_file_path);
+        json_storage.load_user_states();
+        json_storage.save_user_state(chat_id, &make_test_user_state());
+
+        let database_path = json_file_path.with_extension("sqlite");
+        {
+            let storage = SqliteUserStateStorage::open(&database_path, Some(&json_file_path));
+            assert_test_user_state_is_loaded(&storage.load_user_states(), chat_id);
+            storage.save_user_state(chat_id, &get_default_user_state());
+        }
+        {
+            // the changes made after the import are not overwritten by the json file
+            let storage = SqliteUserStateStorage::open(&database_path, Some(&json_file_path));
+            let user_states = storage.load_user_states();
+            let user_state = user_states.states.get(&chat_id).unwrap();
+            assert!(user_state.stats.hands.is_empty());
+        }
+
+        std::fs::remove_file(&json_file_path).unwrap();
+        std::fs::remove_file(&database_path).unwrap();
+    }
+
+    #[test]
+    fn test_several_states_are_saved_at_once() {
+        let json_file_path = std::env::temp_dir().join(format!(
+            "riichi_trainer_batch_test_{}.json",
+            std::process::id()
+        ));
+        let json_storage = JsonUserStateStorage::new(&json_file_path);
+        json_storage.load_user_states();
+        let sqlite_storage =
+            SqliteUserStateStorage::from_connection(Connection::open_in_memory().unwrap(), None);
+
+        let storages: [&dyn UserStateStorage; 2] = [&json_storage, &sqlite_storage];
+        for storage in storages {
+            storage.save_user_state(ChatId(1), &get_default_user_state());
+            storage.save_user_states(&[
+                (ChatId(1), make_test_user_state()),
+                (ChatId(2), make_test_user_state()),
+            ]);
+            let user_states = storage.load_user_states();
+            assert_eq!(user_states.states.len(), 2);
+            assert_test_user_state_is_loaded(&user_states, ChatId(1));
+            assert_test_user_state_is_loaded(&user_states, ChatId(2));
+        }
+
+        std::fs::remove_file(&json_file_path).unwrap();
+    }
+}
@@ -2,10 +2,11 @@
 // Distributed under the MIT License (license terms are at http://opensource.org/licenses/MIT).
 
 use crate::json_file_updater::{JsonFileUpdater, UpdateResult};
+use serde_json::json;
 use serde_json::Value as JsonValue;
 
 static VERSION_FIELD_NAME: &str = "version";
-pub static LATEST_SAVE_VERSION: &str = "0.2.0";
+pub static LATEST_SAVE_VERSION: &str = "0.14.0";
 
 pub fn update_user_states_to_the_latest_version(user_states_json: &mut JsonValue) -> UpdateResult {
     let version = user_states_json[VERSION_FIELD_NAME].as_str();
@@ -23,6 +24,18 @@ fn register_json_updaters() -> JsonFileUpdater {
     let mut json_file_updater = JsonFileUpdater::new(VERSION_FIELD_NAME);
 
     json_file_updater.add_update_function("0.2.0", v0_2_0_added_states_field);
+    json_file_updater.add_update_function("0.3.0", v0_3_0_added_red_fives_settings);
+    json_file_updater.add_update_function("0.4.0", v0_4_0_added_simulate_opponents_setting);
+    json_file_updater.add_update_function("0.5.0", v0_5_0_added_discard_ranking_setting);
+    json_file_updater.add_update_function("0.6.0", v0_6_0_added_lookahead_depth_setting);
+    json_file_updater.add_update_function("0.7.0", v0_7_0_added_quiz_settings);
+    json_file_updater.add_update_function("0.8.0", v0_8_0_added_single_suit_setting);
+    json_file_updater.add_update_function("0.9.0", v0_9_0_added_stats);
+    json_file_updater.add_update_function("0.10.0", v0_10_0_added_mistake_log);
+    json_file_updater.add_update_function("0.11.0", v0_11_0_added_training_aid_settings);
+    json_file_updater.add_update_function("0.12.0", v0_12_0_added_session);
+    json_file_updater.add_update_function("0.13.0", v0_13_0_added_undo_count);
+    json_file_updater.add_update_function("0.14.0", v0_14_0_added_deal_mode);
     // add update functions above this line
     // don't forget to update LATEST_SAVE_VERSION at the beginning of the file
 
@@ -34,3 +47,143 @@ fn v0_2_0_added_states_field(user_states_json: &mut JsonValue) {
     let states = user_states_json.take();
     user_states_json["states"] = states;
 }
+
+fn v0_3_0_added_red_fives_settings(user_states_json: &mut JsonValue) {
+    let Some(states) = user_states_json["states"].as_object_mut() else {
+        return;
+    };
+
+    for state in states.values_mut() {
+        state["game_settings"]["red_fives_per_suit"] = json!(0);
+        state["score_settings"]["prefer_keeping_red_fives"] = json!(true);
+    }
+}
+
+fn v0_4_0_added_simulate_opponents_setting(user_states_json: &mut JsonValue) {
+    let Some(states) = user_states_json["states"].as_object_mut() else {
+        return;
+    };
+
+    for state in states.values_mut() {
+        // the opponents are only played against on request
+        state["game_settings"]["simulate_opponents"] = json!(false);
+    }
+}
+
+fn v0_5_0_added_discard_ranking_setting(user_states_json: &mut JsonValue) {
+    let Some(states) = user_states_json["states"].as_object_mut() else {
+        return;
+    };
+
+    for state in states.values_mut() {
+        state["score_settings"]["discard_ranking"] = json!("Efficiency");
+    }
+}
+
+fn v0_6_0_added_lookahead_depth_setting(user_states_json: &mut JsonValue) {
+    let Some(states) = user_states_json["states"].as_object_mut() else {
+        return;
+    };
+
+    for state in states.values_mut() {
+        state["score_settings"]["lookahead_depth"] = json!("Ukeire2");
+    }
+}
+
+fn v0_7_0_added_quiz_settings(user_states_json: &mut JsonValue) {
+    let Some(states) = user_states_json["states"].as_object_mut() else {
+        return;
+    };
+
+    for state in states.values_mut() {
+        state["quiz_settings"] = json!({
+            "difficulty": "Normal",
+            "single_suit": false,
+            "include_honors": true,
+        });
+    }
+}
+
+fn v0_8_0_added_single_suit_setting(user_states_json: &mut JsonValue) {
+    let Some(states) = user_states_json["states"].as_object_mut() else {
+        return;
+    };
+
+    for state in states.values_mut() {
+        state["game_settings"]["single_suit"] = json!(false);
+    }
+}
+
+fn v0_9_0_added_stats(user_states_json: &mut JsonValue) {
+    let Some(states) = user_states_json["states"].as_object_mut() else {
+        return;
+    };
+
+    for state in states.values_mut() {
+        state["stats"] = json!({ "hands": [] });
+    }
+}
+
+fn v0_10_0_added_mistake_log(user_states_json: &mut JsonValue) {
+    let Some(states) = user_states_json["states"].as_object_mut() else {
+        return;
+    };
+
+    for state in states.values_mut() {
+        state["mistake_log"] = json!({ "mistakes": [] });
+    }
+}
+
+fn v0_11_0_added_training_aid_settings(user_states_json: &mut JsonValue) {
+    let Some(states) = user_states_json["states"].as_object_mut() else {
+        return;
+    };
+
+    for state in states.values_mut() {
+        state["training_aid_settings"] = json!({
+            "hints_enabled": true,
+            "undo_enabled": true,
+        });
+    }
+}
+
+fn v0_12_0_added_session(user_states_json: &mut JsonValue) {
+    let Some(states) = user_states_json["states"].as_object_mut() else {
+        return;
+    };
+
+    for state in states.values_mut() {
+        state["session"] = JsonValue::Null;
+    }
+}
+
+fn v0_13_0_added_undo_count(user_states_json: &mut JsonValue) {
+    let Some(states) = user_states_json["states"].as_object_mut() else {
+        return;
+    };
+
+    for state in states.values_mut() {
+        // the settings from the database come without the stats and the session
+        if let Some(hands) = state["stats"]["hands"].as_array_mut() {
+            for hand in hands {
+                hand["undo_count"] = json!(0);
+            }
+        }
+        if state["session"].is_object() {
+            state["session"]["undo_count"] = json!(0);
+        }
+    }
+}
+
+fn v0_14_0_added_deal_mode(user_states_json: &mut JsonValue) {
+    let Some(states) = user_states_json["states"].as_object_mut() else {
+        return;
+    };
+
+    for state in states.values_mut() {
+        // it's unknown how the running hands were dealt, so they are not offered to be replayed
+        if state["session"]["game_state"].is_object() {
+            state["session"]["game_state"]["deal_mode"] = json!("GivenHand");
+        }
+    }
+}
@@ -0,0 +1,181 @@
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// mistakes made at higher shanten are counted at the last level
+pub const TRACKED_SHANTEN_LEVELS: usize = 7;
+// the oldest hands are removed to keep the saved states small
+const MAX_RECORDED_HANDS: usize = 1000;
+pub const SECONDS_IN_DAY: u64 = 24 * 60 * 60;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct HandRecord {
+    pub timestamp: u64, // seconds since the unix epoch when the hand was finished
+    pub seed: u64,
+    pub efficiency: f32, // from 0 to 1, average for all the moves of the hand
+    pub moves: u32,
+    pub turns_to_tenpai: Option<u32>, // None if the live wall ended before tenpai
+    // discards that were not the best, by the shanten of the hand before the discard
+    pub mistakes_by_shanten: [u32; TRACKED_SHANTEN_LEVELS],
+    // discards taken back with /undo, the hand was replayed after seeing the better discards
+    pub undo_count: u32,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct UserStats {
+    pub hands: Vec<HandRecord>,
+}
+
+pub struct StatsSummary {
+    pub hand_count: u32,
+    pub average_efficiency: f32,
+    pub tenpai_count: u32,
+    pub average_turns_to_tenpai: f32,
+    pub mistakes_by_shanten: [u32; TRACKED_SHANTEN_LEVELS],
+    pub hands_with_undo_count: u32,
+}
+
+pub fn get_current_timestamp() -> u64 {
+    return match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(duration) => duration.as_secs(),
+        Err(err) => {
+            eprintln!("System time is before the unix epoch: {}", err);
+            0
+        }
+    };
+}
+
+pub fn get_shanten_level_index(shanten: i8) -> usize {
+    return (shanten.max(0) as usize).min(TRACKED_SHANTEN_LEVELS - 1);
+}
+
+pub fn record_hand(stats: &mut UserStats, record: HandRecord) {
+    stats.hands.push(record);
+    if stats.hands.len() > MAX_RECORDED_HANDS {
+        let extra_hands = stats.hands.len() - MAX_RECORDED_HANDS;
+        stats.hands.drain(0..extra_hands);
+    }
+}
+
+// returns None if no hands were finished since the given time
+pub fn summarize_hands_since(stats: &UserStats, since_timestamp: u64) -> Option<StatsSummary> {
+    let mut summary = StatsSummary {
+        hand_count: 0,
+        average_efficiency: 0.0,
+        tenpai_count: 0,
+        average_turns_to_tenpai: 0.0,
+        mistakes_by_shanten: [0; TRACKED_SHANTEN_LEVELS],
+        hands_with_undo_count: 0,
+    };
+
+    let mut turns_to_tenpai_sum = 0;
+    for hand in stats
+        .hands
+        .iter()
+        .filter(|hand| hand.timestamp >= since_timestamp)
+    {
+        summary.hand_count += 1;
+        summary.average_efficiency += hand.efficiency;
+        if let Some(turns) = hand.turns_to_tenpai {
+            summary.tenpai_count += 1;
+            turns_to_tenpai_sum += turns;
+        }
+        for i in 0..TRACKED_SHANTEN_LEVELS {
+            summary.mistakes_by_shanten[i] += hand.mistakes_by_shanten[i];
+        }
+        if hand.undo_count > 0 {
+            summary.hands_with_undo_count += 1;
+        }
+    }
+
+    if summary.hand_count == 0 {
+        return None;
+    }
+
+    summary.average_efficiency /= summary.hand_count as f32;
+    if summary.tenpai_count > 0 {
+        summary.average_turns_to_tenpai = turns_to_tenpai_sum as f32 / summary.tenpai_count as f32;
+    }
+    return Some(summary);
+}
+
+// every value is averaged with up to window_size - 1 values before it
+pub fn get_rolling_average(values: &[f32], window_size: usize) -> Vec<f32> {
+    let mut result = Vec::with_capacity(values.len());
+    let mut sum = 0.0;
+    for i in 0..values.len() {
+        sum += values[i];
+        if i >= window_size {
+            sum -= values[i - window_size];
+        }
+        result.push(sum / (i + 1).min(window_size) as f32);
+    }
+    return result;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_test_record(
+        timestamp: u64,
+        efficiency: f32,
+        turns_to_tenpai: Option<u32>,
+    ) -> HandRecord {
+        let mut mistakes_by_shanten = [0; TRACKED_SHANTEN_LEVELS];
+        mistakes_by_shanten[1] = 1;
+        return HandRecord {
+            timestamp: timestamp,
+            seed: 0,
+            efficiency: efficiency,
+            moves: 10,
+            turns_to_tenpai: turns_to_tenpai,
+            mistakes_by_shanten: mistakes_by_shanten,
+            undo_count: 0,
+        };
+    }
+
+    #[test]
+    fn test_hands_are_summarized_for_the_period() {
+        let mut stats = UserStats::default();
+        record_hand(&mut stats, make_test_record(100, 0.5, None));
+        record_hand(&mut stats, make_test_record(200, 0.75, Some(8)));
+        record_hand(&mut stats, make_test_record(300, 1.0, Some(12)));
+        stats.hands[1].undo_count = 2;
+
+        let all_time = summarize_hands_since(&stats, 0).unwrap();
+        assert_eq!(all_time.hand_count, 3);
+        assert_eq!(all_time.average_efficiency, 0.75);
+        assert_eq!(all_time.tenpai_count, 2);
+        assert_eq!(all_time.average_turns_to_tenpai, 10.0);
+        assert_eq!(all_time.mistakes_by_shanten[1], 3);
+        assert_eq!(all_time.hands_with_undo_count, 1);
+
+        let recent = summarize_hands_since(&stats, 200).unwrap();
+        assert_eq!(recent.hand_count, 2);
+        assert_eq!(recent.average_efficiency, 0.875);
+        assert_eq!(recent.hands_with_undo_count, 1);
+
+        assert!(summarize_hands_since(&stats, 301).is_none());
+    }
+
+    #[test]
+    fn test_only_the_latest_hands_are_kept() {
+        let mut stats = UserStats::default();
+        for i in 0..(MAX_RECORDED_HANDS + 5) {
+            record_hand(&mut stats, make_test_record(i as u64, 1.0, None));
+        }
+
+        assert_eq!(stats.hands.len(), MAX_RECORDED_HANDS);
+        assert_eq!(stats.hands[0].timestamp, 5);
+    }
+
+    #[test]
+    fn test_rolling_average() {
+        assert_eq!(
+            get_rolling_average(&[1.0, 0.0, 0.5, 1.0], 2),
+            [1.0, 0.5, 0.25, 0.75]
+        );
+        assert_eq!(get_shanten_level_index(-1), 0);
+        assert_eq!(get_shanten_level_index(13), TRACKED_SHANTEN_LEVELS - 1);
+    }
+}
@@ -0,0 +1,306 @@
+use crate::game_logic::*;
+use crate::rand::prelude::SliceRandom;
+use crate::ukeire_calculator::*;
+use crate::yaku_calculator::*;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+
+#[cfg(test)]
+use crate::input_output;
+
+pub struct DiscardSimulationResult {
+    pub tile: Tile,
+    pub tenpai_chance: f32,
+    pub win_chance: f32,
+}
+
+struct SimulatedHand {
+    tiles: Vec<Tile>,
+    shanten: i8,
+    waits_table: TileFrequencyTable,
+    // the waits that complete the hand with a yaku, a hand without a yaku can't be won
+    winning_waits_table: TileFrequencyTable,
+    discards_table: TileFrequencyTable,
+}
+
+// number of tiles the player can still draw if everyone takes their turns
+pub fn get_turns_left(game: &GameState) -> usize {
+    return game.live_wall.len() / game.hands.len();
+}
+
+// all the tiles the given player can't see: the live wall, the closed tiles of other players and
+// the part of the dead wall that is not revealed
+fn get_unseen_tiles(game: &GameState, hand_index: usize) -> Vec<Tile> {
+    let mut result = game.live_wall.clone();
+
+    for (i, hand) in game.hands.iter().enumerate() {
+        if i != hand_index {
+            result.extend(get_closed_tiles(hand));
+        }
+    }
+
+    // every kan takes a replacement tile and opens an indicator
+    let kan_count = game.opened_dora_indicators as usize - 1;
+    let opened_indicators = get_opened_dora_indicators(game).len();
+    result.extend_from_slice(&game.dead_wall[kan_count..4]);
+    result.extend_from_slice(&game.dead_wall[4 + opened_indicators..]);
+
+    return result;
+}
+
+fn update_waits(
+    hand: &mut SimulatedHand,
+    melds: &[Meld],
+    conditions: &WinConditions,
+    settings: &ScoreCalculationSettings,
+) {
+    hand.waits_table = EMPTY_FREQUENCY_TABLE;
+    hand.winning_waits_table = EMPTY_FREQUENCY_TABLE;
+    if hand.shanten != 0 {
+        return;
+    }
+
+    let calculator = calculate_shanten(&hand.tiles, settings);
+    let waits = filter_tiles_finishing_hand(
+        &hand.tiles,
+        &convert_frequency_table_to_flat_vec(calculator.get_best_waits()),
+        settings,
+    );
+    hand.waits_table = make_frequency_table(&waits);
+    for wait in &waits {
+        let has_yaku = calculate_hand_value(&hand.tiles, melds, wait, conditions)
+            .is_ok_and(|value| !value.yaku.is_empty());
+        if has_yaku {
+            hand.winning_waits_table[get_tile_index(wait)] += 1;
+        }
+    }
+}
+
+fn is_furiten(hand: &SimulatedHand) -> bool {
+    return hand
+        .waits_table
+        .iter()
+        .zip(hand.discards_table)
+        .any(|(wait, discard)| *wait > 0 && discard > 0);
+}
+
+// the drawn tile is kept only when it improves shanten, in this case the discard is chosen by ukeire1
+// otherwise the drawn tile is discarded right away
+fn play_simulated_draw(
+    hand: &mut SimulatedHand,
+    drawn_tile: Tile,
+    visible_tiles: &TileFrequencyTable,
+    settings: &ScoreCalculationSettings,
+) {
+    hand.tiles.push(drawn_tile);
+    let mut discarded_tile = drawn_tile;
+
+    if hand.shanten > 0 {
+        let new_shanten = calculate_shanten(&hand.tiles, settings).get_calculated_shanten();
+        if new_shanten < hand.shanten {
+            let best_discards =
+                calculate_best_discards_ukeire1(&hand.tiles, new_shanten, visible_tiles, settings);
+            if let Some(best_discard) = best_discards.first() {
+                discarded_tile = best_discard.tile;
+                hand.shanten = new_shanten;
+            }
+        }
+    }
+
+    if let Some(position) = hand.tiles.iter().position(|tile| *tile == discarded_tile) {
+        hand.tiles.remove(position);
+    }
+    hand.discards_table[get_tile_index(&discarded_tile)] += 1;
+}
+
+// returns the turns when the hand reached tenpai and when it won, if it happened
+#[allow(clippy::too_many_arguments)]
+fn simulate_hand(
+    mut hand: SimulatedHand,
+    melds: &[Meld],
+    wall: &[Tile],
+    player_count: usize,
+    turns: usize,
+    visible_tiles: &TileFrequencyTable,
+    conditions: &WinConditions,
+    settings: &ScoreCalculationSettings,
+) -> (Option<usize>, Option<usize>) {
+    let mut tenpai_turn = if hand.shanten == 0 { Some(0) } else { None };
+    update_waits(&mut hand, melds, conditions, settings);
+
+    let mut wall_tiles = wall.iter();
+    for turn in 1..=turns {
+        let Some(drawn_tile) = wall_tiles.next() else {
+            break;
+        };
+
+        if hand.winning_waits_table[get_tile_index(drawn_tile)] > 0 {
+            return (tenpai_turn, Some(turn));
+        }
+
+        play_simulated_draw(&mut hand, *drawn_tile, visible_tiles, settings);
+        if tenpai_turn.is_none() && hand.shanten == 0 {
+            tenpai_turn = Some(turn);
+            update_waits(&mut hand, melds, conditions, settings);
+        }
+
+        // other players discard tiles they draw, any of them can be called for ron
+        for _ in 1..player_count {
+            let Some(discarded_tile) = wall_tiles.next() else {
+                break;
+            };
+            if hand.winning_waits_table[get_tile_index(discarded_tile)] > 0 && !is_furiten(&hand) {
+                return (tenpai_turn, Some(turn));
+            }
+        }
+    }
+
+    return (tenpai_turn, None);
+}
+
+// plays out the hand after each of the discards many times, the unseen tiles are shuffled every time
+// the game state is expected to be right before the discard (14 tiles in the hand)
+// wins are counted only for the waits that have a yaku with the given conditions
+pub fn simulate_discards(
+    game: &GameState,
+    hand_index: usize,
+    discards: &[Tile],
+    simulation_count: u32,
+    turns: usize,
+    conditions: &WinConditions,
+    settings: &ScoreCalculationSettings,
+) -> Vec<DiscardSimulationResult> {
+    let hand_tiles = get_closed_tiles(&game.hands[hand_index]);
+    assert!(
+        hand_tiles.len() % 3 == 2,
+        "simulate_discards expected hand with 14 tiles minus melds"
+    );
+
+    let visible_tiles = get_visible_tiles(game, hand_index);
+    let unseen_tiles = get_unseen_tiles(game, hand_index);
+    let player_count = game.hands.len();
+    let melds = &game.hands[hand_index].melds;
+
+    let mut result = Vec::with_capacity(discards.len());
+    for discard in discards {
+        let mut tiles = hand_tiles.clone();
+        let Some(position) = find_tile_position(&tiles, discard) else {
+            eprintln!("Can't simulate discard of a tile that is not in the hand");
+            continue;
+        };
+        let discarded_tile = tiles.remove(position);
+        let shanten = calculate_shanten(&tiles, settings).get_calculated_shanten();
+        let mut discards_table = make_frequency_table(&game.discards[hand_index]);
+        discards_table[get_tile_index(&discarded_tile)] += 1;
+
+        // every discard is simulated with the same walls to make the results comparable
+        let mut wall = unseen_tiles.clone();
+        let mut rng = ChaCha8Rng::seed_from_u64(game.seed);
+        let mut tenpai_count = 0;
+        let mut win_count = 0;
+        for _ in 0..simulation_count {
+            wall.shuffle(&mut rng);
+            let hand = SimulatedHand {
+                tiles: tiles.clone(),
+                shanten: shanten,
+                waits_table: EMPTY_FREQUENCY_TABLE,
+                winning_waits_table: EMPTY_FREQUENCY_TABLE,
+                discards_table: discards_table,
+            };
+            let (tenpai_turn, win_turn) = simulate_hand(
+                hand,
+                melds,
+                &wall,
+                player_count,
+                turns,
+                &visible_tiles,
+                conditions,
+                settings,
+            );
+            if tenpai_turn.is_some() {
+                tenpai_count += 1;
+            }
+            if win_turn.is_some() {
+                win_count += 1;
+            }
+        }
+
+        result.push(DiscardSimulationResult {
+            tile: discarded_tile,
+            tenpai_chance: tenpai_count as f32 / simulation_count as f32,
+            win_chance: win_count as f32 / simulation_count as f32,
+        });
+    }
+
+    return result;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::user_settings::{DEFAULT_GAME_SETTINGS, DEFAULT_SCORE_SETTINGS};
+
+    const GAME_SETTINGS: GameSettings = GameSettings {
+        deal_first_tile: false,
+        simulate_opponents: true,
+        ..DEFAULT_GAME_SETTINGS
+    };
+
+    #[test]
+    fn test_keeping_tenpai_is_better_than_breaking_it() {
+        let settings = DEFAULT_SCORE_SETTINGS;
+        let game_settings = GAME_SETTINGS;
+        let hand = input_output::make_hand_from_string("123456789m2345p9s").unwrap();
+        let game =
+            generate_dealt_game_with_hand_and_discards(4, hand, Vec::new(), &game_settings, 0)
+                .unwrap();
+        let discards = input_output::make_tile_sequence_from_string("9s1m").unwrap();
+
+        let conditions = get_trainer_win_conditions(&game, 0);
+
+        let results = simulate_discards(
+            &game,
+            0,
+            &discards,
+            30,
+            get_turns_left(&game),
+            &conditions,
+            &settings,
+        );
+
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].tenpai_chance, 1.0);
+        assert!(results[0].win_chance > results[1].win_chance);
+
+        let short_results = simulate_discards(&game, 0, &discards, 20, 3, &conditions, &settings);
+        assert!(short_results[1].tenpai_chance < 1.0);
+        let repeated_results =
+            simulate_discards(&game, 0, &discards, 20, 3, &conditions, &settings);
+        assert_eq!(
+            short_results[1].tenpai_chance,
+            repeated_results[1].tenpai_chance
+        );
+    }
+
+    #[test]
+    fn test_hand_without_yaku_is_not_won() {
+        // the open hand has no tanyao because of 123s and no other yaku with 2s or 5s
+        let hand = input_output::make_hand_from_string("234m678p3455s1z+123s").unwrap();
+        let game =
+            generate_dealt_game_with_hand_and_discards(4, hand, Vec::new(), &GAME_SETTINGS, 0)
+                .unwrap();
+        let discards = input_output::make_tile_sequence_from_string("1z").unwrap();
+
+        let results = simulate_discards(
+            &game,
+            0,
+            &discards,
+            30,
+            get_turns_left(&game),
+            &get_trainer_win_conditions(&game, 0),
+            &DEFAULT_SCORE_SETTINGS,
+        );
+        assert_eq!(results[0].tenpai_chance, 1.0);
+        assert_eq!(results[0].win_chance, 0.0);
+    }
+}
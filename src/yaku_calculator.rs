@@ -0,0 +1,1303 @@
+use crate::game_logic::*;
+use crate::ukeire_calculator::*;
+
+#[cfg(test)]
+use crate::input_output;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Yaku {
+    Riichi,
+    MenzenTsumo,
+    Tanyao,
+    Pinfu,
+    Iipeikou,
+    Haku,
+    Hatsu,
+    Chun,
+    SeatWind,
+    RoundWind,
+    Chiitoitsu,
+    Sanshoku,
+    Ittsu,
+    Chanta,
+    Toitoi,
+    Sanankou,
+    Sankantsu,
+    SanshokuDoukou,
+    Shousangen,
+    Honroutou,
+    Honitsu,
+    Junchan,
+    Ryanpeikou,
+    Chinitsu,
+    // yakuman
+    Kokushi,
+    Suuankou,
+    Daisangen,
+    Shousuushii,
+    Daisuushii,
+    Tsuuiisou,
+    Ryuuiisou,
+    Chinroutou,
+    ChuurenPoutou,
+    Suukantsu,
+}
+
+pub fn get_yaku_name(yaku: Yaku) -> &'static str {
+    return match yaku {
+        Yaku::Riichi => "Riichi",
+        Yaku::MenzenTsumo => "Menzen tsumo",
+        Yaku::Tanyao => "Tanyao",
+        Yaku::Pinfu => "Pinfu",
+        Yaku::Iipeikou => "Iipeikou",
+        Yaku::Haku => "Haku",
+        Yaku::Hatsu => "Hatsu",
+        Yaku::Chun => "Chun",
+        Yaku::SeatWind => "Seat wind",
+        Yaku::RoundWind => "Round wind",
+        Yaku::Chiitoitsu => "Chiitoitsu",
+        Yaku::Sanshoku => "Sanshoku doujun",
+        Yaku::Ittsu => "Ittsu",
+        Yaku::Chanta => "Chanta",
+        Yaku::Toitoi => "Toitoi",
+        Yaku::Sanankou => "Sanankou",
+        Yaku::Sankantsu => "Sankantsu",
+        Yaku::SanshokuDoukou => "Sanshoku doukou",
+        Yaku::Shousangen => "Shousangen",
+        Yaku::Honroutou => "Honroutou",
+        Yaku::Honitsu => "Honitsu",
+        Yaku::Junchan => "Junchan",
+        Yaku::Ryanpeikou => "Ryanpeikou",
+        Yaku::Chinitsu => "Chinitsu",
+        Yaku::Kokushi => "Kokushi musou",
+        Yaku::Suuankou => "Suuankou",
+        Yaku::Daisangen => "Daisangen",
+        Yaku::Shousuushii => "Shousuushii",
+        Yaku::Daisuushii => "Daisuushii",
+        Yaku::Tsuuiisou => "Tsuuiisou",
+        Yaku::Ryuuiisou => "Ryuuiisou",
+        Yaku::Chinroutou => "Chinroutou",
+        Yaku::ChuurenPoutou => "Chuuren poutou",
+        Yaku::Suukantsu => "Suukantsu",
+    };
+}
+
+// everything about the win that can't be derived from the tiles themselves
+#[derive(Clone)]
+pub struct WinConditions {
+    pub is_riichi: bool,
+    pub is_tsumo: bool,
+    pub is_dealer: bool,
+    pub round_wind: u8, // value of the wind tile: 1 - East, 2 - South, 3 - West, 4 - North
+    pub seat_wind: u8,
+    pub dora_indicators: Vec<Tile>,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct YakuHan {
+    pub yaku: Yaku,
+    pub han: u8, // yakuman are counted as 13 han
+}
+
+// an empty list of yaku means that the hand can't be won
+#[derive(Debug, Clone)]
+pub struct HandValue {
+    pub yaku: Vec<YakuHan>,
+    pub dora: u8,
+    pub han: u8,
+    pub fu: u8,
+    pub points: u32, // total number of points the winner receives
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum GroupType {
+    Sequence,
+    Triplet,
+    Kan,
+}
+
+#[derive(Copy, Clone)]
+struct Group {
+    group_type: GroupType,
+    tile_index: usize, // the first tile of a sequence
+    is_open: bool,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum WaitForm {
+    Ryanmen,
+    Kanchan,
+    Penchan,
+    Shanpon,
+    Tanki,
+}
+
+// all the details of one way to read the complete hand
+struct HandReading<'a> {
+    groups: Vec<Group>, // closed groups go first, then the melds
+    pair_index: usize,
+    wait_form: WaitForm,
+    is_closed: bool,
+    tiles: &'a [Tile],
+    conditions: &'a WinConditions,
+}
+
+const DRAGON_INDEXES: [usize; 3] = [34, 35, 36];
+const GREEN_TILE_INDEXES: [usize; 6] = [21, 22, 23, 25, 27, 35];
+
+fn is_honor_index(index: usize) -> bool {
+    return index >= 30;
+}
+
+fn is_terminal_index(index: usize) -> bool {
+    return index < 30 && (index.is_multiple_of(10) || index % 10 == 8);
+}
+
+fn is_terminal_or_honor_index(index: usize) -> bool {
+    return is_terminal_index(index) || is_honor_index(index);
+}
+
+fn get_wind_index(wind: u8) -> usize {
+    return 30 + wind as usize - 1;
+}
+
+fn get_group_tile_indexes(group: &Group) -> Vec<usize> {
+    return match group.group_type {
+        GroupType::Sequence => {
+            [group.tile_index, group.tile_index + 1, group.tile_index + 2].to_vec()
+        }
+        GroupType::Triplet => [group.tile_index; 3].to_vec(),
+        GroupType::Kan => [group.tile_index; 4].to_vec(),
+    };
+}
+
+fn has_terminal_or_honor(group: &Group) -> bool {
+    return get_group_tile_indexes(group)
+        .iter()
+        .any(|index| is_terminal_or_honor_index(*index));
+}
+
+fn is_triplet_or_kan(group: &Group) -> bool {
+    return group.group_type != GroupType::Sequence;
+}
+
+fn make_group_from_meld(meld: &Meld) -> Group {
+    let tile_index = meld
+        .tiles
+        .iter()
+        .map(get_tile_index)
+        .min()
+        .unwrap_or_default();
+    return match meld.meld_type {
+        MeldType::Chi => Group {
+            group_type: GroupType::Sequence,
+            tile_index: tile_index,
+            is_open: true,
+        },
+        MeldType::Pon => Group {
+            group_type: GroupType::Triplet,
+            tile_index: tile_index,
+            is_open: true,
+        },
+        MeldType::OpenKan => Group {
+            group_type: GroupType::Kan,
+            tile_index: tile_index,
+            is_open: true,
+        },
+        MeldType::ClosedKan => Group {
+            group_type: GroupType::Kan,
+            tile_index: tile_index,
+            is_open: false,
+        },
+    };
+}
+
+fn remove_sets(
+    table: &mut TileFrequencyTable,
+    current_sets: &mut Vec<Group>,
+    result: &mut Vec<Vec<Group>>,
+) {
+    let Some(i) = table.iter().position(|count| *count > 0) else {
+        result.push(current_sets.clone());
+        return;
+    };
+
+    // the first remaining tile can only be a part of a triplet or a sequence starting from it
+    if table[i] >= 3 {
+        table[i] -= 3;
+        current_sets.push(Group {
+            group_type: GroupType::Triplet,
+            tile_index: i,
+            is_open: false,
+        });
+        remove_sets(table, current_sets, result);
+        current_sets.pop();
+        table[i] += 3;
+    }
+
+    if i < 30 && i % 10 <= 6 && table[i + 1] > 0 && table[i + 2] > 0 {
+        table[i] -= 1;
+        table[i + 1] -= 1;
+        table[i + 2] -= 1;
+        current_sets.push(Group {
+            group_type: GroupType::Sequence,
+            tile_index: i,
+            is_open: false,
+        });
+        remove_sets(table, current_sets, result);
+        current_sets.pop();
+        table[i] += 1;
+        table[i + 1] += 1;
+        table[i + 2] += 1;
+    }
+}
+
+// returns every way to split the closed tiles into a pair and complete sets
+fn find_standard_decompositions(table: &TileFrequencyTable) -> Vec<(usize, Vec<Group>)> {
+    let mut result = Vec::new();
+    let mut table = *table;
+
+    for pair_index in 0..table.len() {
+        if table[pair_index] < 2 {
+            continue;
+        }
+
+        table[pair_index] -= 2;
+        let mut decompositions = Vec::new();
+        remove_sets(&mut table, &mut Vec::new(), &mut decompositions);
+        for groups in decompositions {
+            result.push((pair_index, groups));
+        }
+        table[pair_index] += 2;
+    }
+
+    return result;
+}
+
+// the winning tile can complete different groups of the same decomposition and it may change the value
+fn find_possible_wait_forms(
+    pair_index: usize,
+    closed_groups: &[Group],
+    winning_tile_index: usize,
+) -> Vec<(WaitForm, Option<usize>)> {
+    let mut result = Vec::new();
+
+    if pair_index == winning_tile_index {
+        result.push((WaitForm::Tanki, None));
+    }
+
+    for (group_position, group) in closed_groups.iter().enumerate() {
+        let wait_form = match group.group_type {
+            GroupType::Triplet if group.tile_index == winning_tile_index => WaitForm::Shanpon,
+            GroupType::Sequence if group.tile_index + 1 == winning_tile_index => WaitForm::Kanchan,
+            GroupType::Sequence
+                if group.tile_index == winning_tile_index && group.tile_index % 10 == 6 =>
+            {
+                WaitForm::Penchan
+            }
+            GroupType::Sequence
+                if group.tile_index + 2 == winning_tile_index && group.tile_index % 10 == 0 =>
+            {
+                WaitForm::Penchan
+            }
+            GroupType::Sequence
+                if group.tile_index == winning_tile_index
+                    || group.tile_index + 2 == winning_tile_index =>
+            {
+                WaitForm::Ryanmen
+            }
+            _ => continue,
+        };
+        result.push((wait_form, Some(group_position)));
+    }
+
+    return result;
+}
+
+fn is_yakuhai_pair(pair_index: usize, conditions: &WinConditions) -> bool {
+    return DRAGON_INDEXES.contains(&pair_index)
+        || pair_index == get_wind_index(conditions.seat_wind)
+        || pair_index == get_wind_index(conditions.round_wind);
+}
+
+fn count_dora(tiles: &[Tile], dora_indicators: &[Tile]) -> u8 {
+    let mut result = 0;
+    for tile in tiles {
+        for indicator in dora_indicators {
+            if is_same_tile_ignoring_red(tile, &get_dora_from_indicator(indicator)) {
+                result += 1;
+            }
+        }
+        if tile.is_red {
+            result += 1;
+        }
+    }
+    return result;
+}
+
+// yaku that depend only on which tiles are in the hand, they apply to every form of a complete hand
+fn add_tile_set_yaku(
+    tile_indexes: &[usize],
+    is_closed: bool,
+    conditions: &WinConditions,
+    yaku: &mut Vec<YakuHan>,
+    yakuman: &mut Vec<YakuHan>,
+) {
+    let han_by_closeness = |closed_han: u8| {
+        if is_closed {
+            closed_han
+        } else {
+            closed_han - 1
+        }
+    };
+
+    if conditions.is_riichi && is_closed {
+        yaku.push(YakuHan {
+            yaku: Yaku::Riichi,
+            han: 1,
+        });
+    }
+    if conditions.is_tsumo && is_closed {
+        yaku.push(YakuHan {
+            yaku: Yaku::MenzenTsumo,
+            han: 1,
+        });
+    }
+    if tile_indexes
+        .iter()
+        .all(|index| !is_terminal_or_honor_index(*index))
+    {
+        yaku.push(YakuHan {
+            yaku: Yaku::Tanyao,
+            han: 1,
+        });
+    }
+
+    let has_honors = tile_indexes.iter().any(|index| is_honor_index(*index));
+    let suited_indexes: Vec<usize> = tile_indexes
+        .iter()
+        .filter(|index| !is_honor_index(**index))
+        .copied()
+        .collect();
+    let is_single_suit = suited_indexes
+        .iter()
+        .all(|index| suited_indexes.first().map(|first| first / 10) == Some(index / 10));
+
+    if suited_indexes.is_empty() {
+        yakuman.push(YakuHan {
+            yaku: Yaku::Tsuuiisou,
+            han: 13,
+        });
+    } else if is_single_suit && has_honors {
+        yaku.push(YakuHan {
+            yaku: Yaku::Honitsu,
+            han: han_by_closeness(3),
+        });
+    } else if is_single_suit {
+        yaku.push(YakuHan {
+            yaku: Yaku::Chinitsu,
+            han: han_by_closeness(6),
+        });
+    }
+
+    if tile_indexes
+        .iter()
+        .all(|index| is_terminal_or_honor_index(*index))
+    {
+        if !has_honors {
+            yakuman.push(YakuHan {
+                yaku: Yaku::Chinroutou,
+                han: 13,
+            });
+        } else if !suited_indexes.is_empty() {
+            yaku.push(YakuHan {
+                yaku: Yaku::Honroutou,
+                han: 2,
+            });
+        }
+    }
+
+    if tile_indexes
+        .iter()
+        .all(|index| GREEN_TILE_INDEXES.contains(index))
+    {
+        yakuman.push(YakuHan {
+            yaku: Yaku::Ryuuiisou,
+            han: 13,
+        });
+    }
+}
+
+fn get_standard_hand_yaku(reading: &HandReading, winning_group: Option<usize>) -> Vec<YakuHan> {
+    let conditions = reading.conditions;
+    let is_closed = reading.is_closed;
+    let han_by_closeness = |closed_han: u8| {
+        if is_closed {
+            closed_han
+        } else {
+            closed_han - 1
+        }
+    };
+
+    let mut tile_indexes = [reading.pair_index; 2].to_vec();
+    for group in &reading.groups {
+        tile_indexes.append(&mut get_group_tile_indexes(group));
+    }
+
+    let mut yaku = Vec::new();
+    let mut yakuman = Vec::new();
+    add_tile_set_yaku(
+        &tile_indexes,
+        is_closed,
+        conditions,
+        &mut yaku,
+        &mut yakuman,
+    );
+
+    let sequences: Vec<usize> = reading
+        .groups
+        .iter()
+        .filter(|group| group.group_type == GroupType::Sequence)
+        .map(|group| group.tile_index)
+        .collect();
+    let triplets: Vec<usize> = reading
+        .groups
+        .iter()
+        .filter(|group| is_triplet_or_kan(group))
+        .map(|group| group.tile_index)
+        .collect();
+    let kan_count = reading
+        .groups
+        .iter()
+        .filter(|group| group.group_type == GroupType::Kan)
+        .count();
+    // a triplet completed by a discarded tile is counted as an open one
+    let concealed_triplet_count = reading
+        .groups
+        .iter()
+        .enumerate()
+        .filter(|(position, group)| {
+            is_triplet_or_kan(group)
+                && !group.is_open
+                && (conditions.is_tsumo || winning_group != Some(*position))
+        })
+        .count();
+
+    if is_closed
+        && sequences.len() == 4
+        && !is_yakuhai_pair(reading.pair_index, conditions)
+        && reading.wait_form == WaitForm::Ryanmen
+    {
+        yaku.push(YakuHan {
+            yaku: Yaku::Pinfu,
+            han: 1,
+        });
+    }
+
+    if is_closed {
+        let mut identical_sequence_pairs = 0;
+        let mut sorted_sequences = sequences.clone();
+        sorted_sequences.sort();
+        let mut i = 1;
+        while i < sorted_sequences.len() {
+            if sorted_sequences[i] == sorted_sequences[i - 1] {
+                identical_sequence_pairs += 1;
+                i += 2;
+            } else {
+                i += 1;
+            }
+        }
+
+        if identical_sequence_pairs == 2 {
+            yaku.push(YakuHan {
+                yaku: Yaku::Ryanpeikou,
+                han: 3,
+            });
+        } else if identical_sequence_pairs == 1 {
+            yaku.push(YakuHan {
+                yaku: Yaku::Iipeikou,
+                han: 1,
+            });
+        }
+    }
+
+    for (dragon_index, dragon_yaku) in
+        DRAGON_INDEXES
+            .iter()
+            .zip([Yaku::Haku, Yaku::Hatsu, Yaku::Chun])
+    {
+        if triplets.contains(dragon_index) {
+            yaku.push(YakuHan {
+                yaku: dragon_yaku,
+                han: 1,
+            });
+        }
+    }
+    if triplets.contains(&get_wind_index(conditions.seat_wind)) {
+        yaku.push(YakuHan {
+            yaku: Yaku::SeatWind,
+            han: 1,
+        });
+    }
+    if triplets.contains(&get_wind_index(conditions.round_wind)) {
+        yaku.push(YakuHan {
+            yaku: Yaku::RoundWind,
+            han: 1,
+        });
+    }
+
+    if (0..7).any(|i| {
+        sequences.contains(&i) && sequences.contains(&(i + 10)) && sequences.contains(&(i + 20))
+    }) {
+        yaku.push(YakuHan {
+            yaku: Yaku::Sanshoku,
+            han: han_by_closeness(2),
+        });
+    }
+
+    if [0, 10, 20].iter().any(|suit_start| {
+        sequences.contains(suit_start)
+            && sequences.contains(&(suit_start + 3))
+            && sequences.contains(&(suit_start + 6))
+    }) {
+        yaku.push(YakuHan {
+            yaku: Yaku::Ittsu,
+            han: han_by_closeness(2),
+        });
+    }
+
+    if !sequences.is_empty()
+        && is_terminal_or_honor_index(reading.pair_index)
+        && reading.groups.iter().all(has_terminal_or_honor)
+    {
+        if tile_indexes.iter().any(|index| is_honor_index(*index)) {
+            yaku.push(YakuHan {
+                yaku: Yaku::Chanta,
+                han: han_by_closeness(2),
+            });
+        } else {
+            yaku.push(YakuHan {
+                yaku: Yaku::Junchan,
+                han: han_by_closeness(3),
+            });
+        }
+    }
+
+    if triplets.len() == 4 {
+        yaku.push(YakuHan {
+            yaku: Yaku::Toitoi,
+            han: 2,
+        });
+    }
+
+    if concealed_triplet_count == 4 {
+        yakuman.push(YakuHan {
+            yaku: Yaku::Suuankou,
+            han: 13,
+        });
+    } else if concealed_triplet_count == 3 {
+        yaku.push(YakuHan {
+            yaku: Yaku::Sanankou,
+            han: 2,
+        });
+    }
+
+    if kan_count == 4 {
+        yakuman.push(YakuHan {
+            yaku: Yaku::Suukantsu,
+            han: 13,
+        });
+    } else if kan_count == 3 {
+        yaku.push(YakuHan {
+            yaku: Yaku::Sankantsu,
+            han: 2,
+        });
+    }
+
+    if (0..9).any(|i| {
+        triplets.contains(&i) && triplets.contains(&(i + 10)) && triplets.contains(&(i + 20))
+    }) {
+        yaku.push(YakuHan {
+            yaku: Yaku::SanshokuDoukou,
+            han: 2,
+        });
+    }
+
+    let dragon_triplet_count = DRAGON_INDEXES
+        .iter()
+        .filter(|index| triplets.contains(index))
+        .count();
+    if dragon_triplet_count == 3 {
+        yakuman.push(YakuHan {
+            yaku: Yaku::Daisangen,
+            han: 13,
+        });
+    } else if dragon_triplet_count == 2 && DRAGON_INDEXES.contains(&reading.pair_index) {
+        yaku.push(YakuHan {
+            yaku: Yaku::Shousangen,
+            han: 2,
+        });
+    }
+
+    let wind_triplet_count = (30..34).filter(|index| triplets.contains(index)).count();
+    if wind_triplet_count == 4 {
+        yakuman.push(YakuHan {
+            yaku: Yaku::Daisuushii,
+            han: 13,
+        });
+    } else if wind_triplet_count == 3 && (30..34).contains(&reading.pair_index) {
+        yakuman.push(YakuHan {
+            yaku: Yaku::Shousuushii,
+            han: 13,
+        });
+    }
+
+    if reading.groups.iter().all(|group| !group.is_open) && kan_count == 0 {
+        let table = make_frequency_table(reading.tiles);
+        let suit_start = reading.pair_index / 10 * 10;
+        let required_counts = [3, 1, 1, 1, 1, 1, 1, 1, 3];
+        if reading.pair_index < 30
+            && table.iter().sum::<u8>() == 14
+            && (0..9).all(|i| table[suit_start + i] >= required_counts[i])
+        {
+            yakuman.push(YakuHan {
+                yaku: Yaku::ChuurenPoutou,
+                han: 13,
+            });
+        }
+    }
+
+    if !yakuman.is_empty() {
+        return yakuman;
+    }
+    return yaku;
+}
+
+fn round_up_to(value: u32, step: u32) -> u32 {
+    return value.div_ceil(step) * step;
+}
+
+fn calculate_standard_hand_fu(
+    reading: &HandReading,
+    winning_group: Option<usize>,
+    yaku: &[YakuHan],
+) -> u8 {
+    let conditions = reading.conditions;
+    if yaku.iter().any(|yaku_han| yaku_han.yaku == Yaku::Pinfu) {
+        return if conditions.is_tsumo { 20 } else { 30 };
+    }
+
+    let mut fu: u32 = 20;
+    if reading.is_closed && !conditions.is_tsumo {
+        fu += 10;
+    }
+    if conditions.is_tsumo {
+        fu += 2;
+    }
+
+    for (position, group) in reading.groups.iter().enumerate() {
+        if !is_triplet_or_kan(group) {
+            continue;
+        }
+
+        let is_concealed =
+            !group.is_open && (conditions.is_tsumo || winning_group != Some(position));
+        let mut group_fu = 2;
+        if is_terminal_or_honor_index(group.tile_index) {
+            group_fu *= 2;
+        }
+        if is_concealed {
+            group_fu *= 2;
+        }
+        if group.group_type == GroupType::Kan {
+            group_fu *= 4;
+        }
+        fu += group_fu;
+    }
+
+    if DRAGON_INDEXES.contains(&reading.pair_index) {
+        fu += 2;
+    }
+    if reading.pair_index == get_wind_index(conditions.seat_wind) {
+        fu += 2;
+    }
+    if reading.pair_index == get_wind_index(conditions.round_wind) {
+        fu += 2;
+    }
+
+    if [WaitForm::Kanchan, WaitForm::Penchan, WaitForm::Tanki].contains(&reading.wait_form) {
+        fu += 2;
+    }
+
+    // an open hand with no fu at all still gets 30
+    if fu == 20 {
+        return 30;
+    }
+
+    return round_up_to(fu, 10) as u8;
+}
+
+fn get_base_points(han: u8, fu: u8, yakuman_count: u8) -> u32 {
+    return match han {
+        _ if yakuman_count > 0 => 8000 * yakuman_count as u32,
+        13.. => 8000,
+        11..=12 => 6000,
+        8..=10 => 4000,
+        6..=7 => 3000,
+        5 => 2000,
+        _ => std::cmp::min(fu as u32 * 2u32.pow(han as u32 + 2), 2000),
+    };
+}
+
+fn calculate_points(han: u8, fu: u8, yakuman_count: u8, conditions: &WinConditions) -> u32 {
+    if han == 0 {
+        return 0;
+    }
+
+    let base_points = get_base_points(han, fu, yakuman_count);
+    return match (conditions.is_tsumo, conditions.is_dealer) {
+        (false, false) => round_up_to(base_points * 4, 100),
+        (false, true) => round_up_to(base_points * 6, 100),
+        (true, false) => round_up_to(base_points, 100) * 2 + round_up_to(base_points * 2, 100),
+        (true, true) => round_up_to(base_points * 2, 100) * 3,
+    };
+}
+
+fn make_hand_value(
+    yaku: Vec<YakuHan>,
+    fu: u8,
+    tiles: &[Tile],
+    conditions: &WinConditions,
+) -> HandValue {
+    let yakuman_count = yaku.iter().filter(|yaku_han| yaku_han.han >= 13).count() as u8;
+    let dora = if yaku.is_empty() || yakuman_count > 0 {
+        0
+    } else {
+        count_dora(tiles, &conditions.dora_indicators)
+    };
+    let han = if yaku.is_empty() {
+        0
+    } else {
+        yaku.iter().map(|yaku_han| yaku_han.han).sum::<u8>() + dora
+    };
+
+    return HandValue {
+        points: calculate_points(han, fu, yakuman_count, conditions),
+        yaku: yaku,
+        dora: dora,
+        han: han,
+        fu: fu,
+    };
+}
+
+fn is_better_value(new_value: &HandValue, old_value: &Option<HandValue>) -> bool {
+    let Some(old_value) = old_value else {
+        return true;
+    };
+
+    return (new_value.points, new_value.han, new_value.fu)
+        > (old_value.points, old_value.han, old_value.fu);
+}
+
+fn get_chiitoitsu_value(
+    table: &TileFrequencyTable,
+    tiles: &[Tile],
+    conditions: &WinConditions,
+) -> Option<HandValue> {
+    if table.iter().filter(|count| **count == 2).count() != 7 {
+        return None;
+    }
+
+    let tile_indexes: Vec<usize> = tiles.iter().map(get_tile_index).collect();
+    let mut yaku = Vec::new();
+    let mut yakuman = Vec::new();
+    add_tile_set_yaku(&tile_indexes, true, conditions, &mut yaku, &mut yakuman);
+    if !yakuman.is_empty() {
+        return Some(make_hand_value(yakuman, 25, tiles, conditions));
+    }
+
+    yaku.push(YakuHan {
+        yaku: Yaku::Chiitoitsu,
+        han: 2,
+    });
+    return Some(make_hand_value(yaku, 25, tiles, conditions));
+}
+
+fn get_kokushi_value(
+    table: &TileFrequencyTable,
+    tiles: &[Tile],
+    conditions: &WinConditions,
+) -> Option<HandValue> {
+    const TERMINALS_AND_HONORS_IDX: [usize; 13] =
+        [0, 8, 10, 18, 20, 28, 30, 31, 32, 33, 34, 35, 36];
+
+    if TERMINALS_AND_HONORS_IDX
+        .iter()
+        .any(|index| table[*index] == 0)
+        || tiles
+            .iter()
+            .any(|tile| !is_terminal_or_honor_index(get_tile_index(tile)))
+    {
+        return None;
+    }
+
+    let yakuman = [YakuHan {
+        yaku: Yaku::Kokushi,
+        han: 13,
+    }]
+    .to_vec();
+    return Some(make_hand_value(yakuman, 0, tiles, conditions));
+}
+
+// closed tiles are the tiles of the tenpai hand before the winning tile is added
+// returns the most expensive way to read the hand with the given winning tile
+pub fn calculate_hand_value(
+    closed_tiles: &[Tile],
+    melds: &[Meld],
+    winning_tile: &Tile,
+    conditions: &WinConditions,
+) -> Result<HandValue, String> {
+    assert!(
+        closed_tiles.len() % 3 == 1 && closed_tiles.len() + 3 * melds.len() == 13,
+        "calculate_hand_value is expected to be called on 13 tiles minus melds"
+    );
+
+    let mut winning_closed_tiles = closed_tiles.to_vec();
+    winning_closed_tiles.push(*winning_tile);
+    let table = make_frequency_table(&winning_closed_tiles);
+
+    let mut all_tiles = winning_closed_tiles.clone();
+    for meld in melds {
+        all_tiles.extend_from_slice(&meld.tiles);
+    }
+
+    let is_closed = melds
+        .iter()
+        .all(|meld| meld.meld_type == MeldType::ClosedKan);
+    let meld_groups: Vec<Group> = melds.iter().map(make_group_from_meld).collect();
+    let winning_tile_index = get_tile_index(winning_tile);
+
+    let mut best_value: Option<HandValue> = None;
+
+    if melds.is_empty() {
+        for value in [
+            get_kokushi_value(&table, &all_tiles, conditions),
+            get_chiitoitsu_value(&table, &all_tiles, conditions),
+        ]
+        .into_iter()
+        .flatten()
+        {
+            if is_better_value(&value, &best_value) {
+                best_value = Some(value);
+            }
+        }
+    }
+
+    for (pair_index, closed_groups) in find_standard_decompositions(&table) {
+        for (wait_form, winning_group) in
+            find_possible_wait_forms(pair_index, &closed_groups, winning_tile_index)
+        {
+            let reading = HandReading {
+                groups: [closed_groups.clone(), meld_groups.clone()].concat(),
+                pair_index: pair_index,
+                wait_form: wait_form,
+                is_closed: is_closed,
+                tiles: &winning_closed_tiles,
+                conditions: conditions,
+            };
+            let yaku = get_standard_hand_yaku(&reading, winning_group);
+            let fu = calculate_standard_hand_fu(&reading, winning_group, &yaku);
+            let value = make_hand_value(yaku, fu, &all_tiles, conditions);
+            if is_better_value(&value, &best_value) {
+                best_value = Some(value);
+            }
+        }
+    }
+
+    return best_value.ok_or("The hand is not complete with this tile".to_string());
+}
+
+pub struct WaitValue {
+    pub tile: Tile,
+    pub available_tiles: u8,
+    pub value: HandValue,
+}
+
+// the wait tiles are expected to be the result of filter_tiles_finishing_hand
+pub fn calculate_waits_value(
+    closed_tiles: &[Tile],
+    melds: &[Meld],
+    wait_tiles: &[Tile],
+    visible_tiles: &TileFrequencyTable,
+    conditions: &WinConditions,
+) -> Vec<WaitValue> {
+    let mut result = Vec::with_capacity(wait_tiles.len());
+
+    for tile in wait_tiles {
+        match calculate_hand_value(closed_tiles, melds, tile, conditions) {
+            Ok(value) => result.push(WaitValue {
+                tile: *tile,
+                available_tiles: find_potentially_available_tile_count(visible_tiles, &[*tile]),
+                value: value,
+            }),
+            Err(err) => eprintln!("Failed to calculate value of a wait: {}", err),
+        }
+    }
+
+    return result;
+}
+
+// average number of points for a win, weighted by the number of tiles that can still come
+pub fn get_waits_expected_value(waits: &[WaitValue]) -> f32 {
+    let total_tiles: u32 = waits.iter().map(|wait| wait.available_tiles as u32).sum();
+    if total_tiles == 0 {
+        return 0.0;
+    }
+
+    let total_points: u32 = waits
+        .iter()
+        .map(|wait| wait.value.points * wait.available_tiles as u32)
+        .sum();
+    return total_points as f32 / total_tiles as f32;
+}
+
+// the trainer doesn't track rounds and seats, so the player is assumed to be the East round dealer
+// who declares riichi with a closed hand and wins by ron
+pub fn get_trainer_win_conditions(game_state: &GameState, hand_index: usize) -> WinConditions {
+    return WinConditions {
+        is_riichi: game_state.hands[hand_index]
+            .melds
+            .iter()
+            .all(|meld| meld.meld_type == MeldType::ClosedKan),
+        is_tsumo: false,
+        is_dealer: true,
+        round_wind: 1,
+        seat_wind: 1,
+        dora_indicators: get_opened_dora_indicators(game_state).to_vec(),
+    };
+}
+
+// rough estimation of how many han the hand can be worth when it is completed
+// a hand that has no way to get a yaku is estimated as worthless
+fn estimate_hand_han(closed_tiles: &[Tile], melds: &[Meld], conditions: &WinConditions) -> f32 {
+    let mut all_tiles = closed_tiles.to_vec();
+    for meld in melds {
+        all_tiles.extend_from_slice(&meld.tiles);
+    }
+    let table = make_frequency_table(&all_tiles);
+
+    let mut yaku_han = 0.0;
+    // riichi
+    if melds
+        .iter()
+        .all(|meld| meld.meld_type == MeldType::ClosedKan)
+    {
+        yaku_han += 1.0;
+    }
+    // tanyao, every terminal or honor tile left in the closed hand has to be replaced first,
+    // so it counts less the more of them there are, and not at all with four of them
+    let melds_are_simples = melds
+        .iter()
+        .flat_map(|meld| &meld.tiles)
+        .all(|tile| !is_terminal_or_honor_index(get_tile_index(tile)));
+    if melds_are_simples {
+        let terminal_and_honor_count = closed_tiles
+            .iter()
+            .filter(|tile| is_terminal_or_honor_index(get_tile_index(tile)))
+            .count();
+        yaku_han += f32::max(1.0 - terminal_and_honor_count as f32 / 4.0, 0.0);
+    }
+    // a yakuhai pair still needs one more tile to become a yaku
+    let yakuhai_indexes = [
+        DRAGON_INDEXES.to_vec(),
+        [
+            get_wind_index(conditions.seat_wind),
+            get_wind_index(conditions.round_wind),
+        ]
+        .to_vec(),
+    ]
+    .concat();
+    for index in yakuhai_indexes {
+        if table[index] >= 3 {
+            yaku_han += 1.0;
+        } else if table[index] == 2 {
+            yaku_han += 0.5;
+        }
+    }
+
+    if yaku_han == 0.0 {
+        return 0.0;
+    }
+
+    let dora = count_dora(&all_tiles, &conditions.dora_indicators) as f32;
+    // everything above mangan grows much slower than the number of han
+    return f32::min(yaku_han + dora, 5.0);
+}
+
+// lookahead score of every discard weighted by the value of the hand that is left after the discard
+// every han doubles the value of the hand, the same way it doubles the points
+pub fn calculate_best_discards_with_value(
+    hand: &Hand,
+    minimal_shanten: i8,
+    visible_tiles: &mut TileFrequencyTable,
+    conditions: &WinConditions,
+    settings: &ScoreCalculationSettings,
+) -> Vec<WeightedDiscard> {
+    let closed_tiles = get_closed_tiles(hand);
+    let mut discards = calculate_best_discards_with_depth(
+        &closed_tiles,
+        minimal_shanten,
+        visible_tiles,
+        settings.lookahead_depth,
+        settings,
+    );
+
+    let mut weighted_scores = Vec::with_capacity(discards.len());
+    for discard in &discards {
+        let mut tiles_after_discard = closed_tiles.clone();
+        if let Some(position) = tiles_after_discard
+            .iter()
+            .position(|tile| *tile == discard.tile)
+        {
+            tiles_after_discard.remove(position);
+        }
+        let han = estimate_hand_han(&tiles_after_discard, &hand.melds, conditions);
+        weighted_scores.push(if han == 0.0 {
+            0
+        } else {
+            (discard.score as f32 * han.exp2()).round() as u32
+        });
+    }
+
+    // if the hand can't have a yaku anyway, the efficiency is the only thing left to consider
+    if weighted_scores.iter().all(|score| *score == 0) {
+        return discards;
+    }
+
+    for (discard, score) in discards.iter_mut().zip(weighted_scores) {
+        discard.score = score;
+    }
+    sort_weighted_discards(&mut discards, settings);
+
+    return discards;
+}
+
+// calculate the discards using the ranking chosen in the settings
+pub fn calculate_best_discards(
+    hand: &Hand,
+    minimal_shanten: i8,
+    visible_tiles: &mut TileFrequencyTable,
+    conditions: &WinConditions,
+    settings: &ScoreCalculationSettings,
+) -> Vec<WeightedDiscard> {
+    return match settings.discard_ranking {
+        DiscardRanking::Efficiency => calculate_best_discards_with_depth(
+            &get_closed_tiles(hand),
+            minimal_shanten,
+            visible_tiles,
+            settings.lookahead_depth,
+            settings,
+        ),
+        DiscardRanking::EfficiencyAndValue => calculate_best_discards_with_value(
+            hand,
+            minimal_shanten,
+            visible_tiles,
+            conditions,
+            settings,
+        ),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::user_settings::DEFAULT_SCORE_SETTINGS;
+
+    fn make_conditions(is_riichi: bool, dora_indicators: &str) -> WinConditions {
+        return WinConditions {
+            is_riichi: is_riichi,
+            is_tsumo: false,
+            is_dealer: false,
+            round_wind: 1,
+            seat_wind: 2,
+            dora_indicators: if dora_indicators.is_empty() {
+                Vec::new()
+            } else {
+                input_output::make_tile_sequence_from_string(dora_indicators).unwrap()
+            },
+        };
+    }
+
+    fn get_tile(tile_string: &str) -> Tile {
+        return input_output::make_tile_sequence_from_string(tile_string).unwrap()[0];
+    }
+
+    fn get_yaku_list(value: &HandValue) -> Vec<Yaku> {
+        return value.yaku.iter().map(|yaku_han| yaku_han.yaku).collect();
+    }
+
+    #[test]
+    fn test_riichi_pinfu_tanyao() {
+        let hand = input_output::make_hand_from_string("234m567p22s456s78s").unwrap();
+        let value = calculate_hand_value(
+            &get_closed_tiles(&hand),
+            &hand.melds,
+            &get_tile("6s"),
+            &make_conditions(true, ""),
+        )
+        .unwrap();
+
+        assert_eq!(
+            get_yaku_list(&value),
+            [Yaku::Riichi, Yaku::Tanyao, Yaku::Pinfu].to_vec()
+        );
+        assert_eq!(value.han, 3);
+        assert_eq!(value.fu, 30);
+        assert_eq!(value.points, 3900);
+    }
+
+    #[test]
+    fn test_dora_and_red_fives_are_counted() {
+        let hand = input_output::make_hand_from_string("234m067p22s456s78s").unwrap();
+        let value = calculate_hand_value(
+            &get_closed_tiles(&hand),
+            &hand.melds,
+            &get_tile("6s"),
+            &make_conditions(true, "1m"),
+        )
+        .unwrap();
+
+        assert_eq!(value.dora, 2);
+        assert_eq!(value.han, 5);
+        assert_eq!(value.points, 8000);
+    }
+
+    #[test]
+    fn test_chiitoitsu() {
+        let hand = input_output::make_hand_from_string("1122m3344p5566s7z").unwrap();
+        let value = calculate_hand_value(
+            &get_closed_tiles(&hand),
+            &hand.melds,
+            &get_tile("7z"),
+            &make_conditions(false, ""),
+        )
+        .unwrap();
+
+        assert_eq!(get_yaku_list(&value), [Yaku::Chiitoitsu].to_vec());
+        assert_eq!(value.fu, 25);
+        assert_eq!(value.points, 1600);
+    }
+
+    #[test]
+    fn test_kokushi() {
+        let hand = input_output::make_hand_from_string("19m19p19s1234567z").unwrap();
+        let value = calculate_hand_value(
+            &get_closed_tiles(&hand),
+            &hand.melds,
+            &get_tile("1m"),
+            &make_conditions(false, ""),
+        )
+        .unwrap();
+
+        assert_eq!(get_yaku_list(&value), [Yaku::Kokushi].to_vec());
+        assert_eq!(value.points, 32000);
+    }
+
+    #[test]
+    fn test_open_honitsu_ittsu_with_yakuhai() {
+        let hand = input_output::make_hand_from_string("123456789m7z+555z").unwrap();
+        let value = calculate_hand_value(
+            &get_closed_tiles(&hand),
+            &hand.melds,
+            &get_tile("7z"),
+            &make_conditions(false, ""),
+        )
+        .unwrap();
+
+        assert_eq!(
+            get_yaku_list(&value),
+            [Yaku::Honitsu, Yaku::Haku, Yaku::Ittsu].to_vec()
+        );
+        assert_eq!(value.han, 4);
+        assert_eq!(value.fu, 30);
+        assert_eq!(value.points, 7700);
+    }
+
+    #[test]
+    fn test_waits_value_of_open_hand_without_yaku() {
+        let hand = input_output::make_hand_from_string("234m567p22s78s+456s").unwrap();
+        let closed_tiles = get_closed_tiles(&hand);
+        let waits = calculate_waits_value(
+            &closed_tiles,
+            &hand.melds,
+            &input_output::make_tile_sequence_from_string("69s").unwrap(),
+            &make_frequency_table(&closed_tiles),
+            &make_conditions(false, ""),
+        );
+
+        assert_eq!(waits.len(), 2);
+        assert_eq!(get_yaku_list(&waits[0].value), [Yaku::Tanyao].to_vec());
+        assert_eq!(waits[0].value.points, 1000);
+        assert!(waits[1].value.yaku.is_empty());
+        assert_eq!(waits[1].value.points, 0);
+        assert_eq!(get_waits_expected_value(&waits), 500.0);
+    }
+
+    #[test]
+    fn test_tanyao_is_estimated_for_hands_with_few_terminals() {
+        let conditions = make_conditions(false, "");
+        let hand = input_output::make_hand_from_string("2345m34678p2s+456s").unwrap();
+        let closed_tiles = get_closed_tiles(&hand);
+        assert_eq!(
+            estimate_hand_han(&closed_tiles, &hand.melds, &conditions),
+            1.0
+        );
+
+        let hand = input_output::make_hand_from_string("1345m34678p9s+456s").unwrap();
+        let closed_tiles = get_closed_tiles(&hand);
+        assert_eq!(
+            estimate_hand_han(&closed_tiles, &hand.melds, &conditions),
+            0.5
+        );
+
+        // a terminal in a meld can't be replaced
+        let hand = input_output::make_hand_from_string("2345m34678p2s+789s").unwrap();
+        let closed_tiles = get_closed_tiles(&hand);
+        assert_eq!(
+            estimate_hand_han(&closed_tiles, &hand.melds, &conditions),
+            0.0
+        );
+    }
+
+    #[test]
+    fn test_value_ranking_keeps_dora() {
+        let mut settings = DEFAULT_SCORE_SETTINGS;
+        // 1m and 9m are equally efficient discards, but 9m is the dora
+        let hand = input_output::make_hand_from_string("19m234567p3456s88s").unwrap();
+        let closed_tiles = get_closed_tiles(&hand);
+        let shanten = calculate_shanten(&closed_tiles, &settings).get_calculated_shanten();
+        let conditions = make_conditions(true, "8m");
+
+        let discards = calculate_best_discards(
+            &hand,
+            shanten,
+            &mut make_frequency_table(&closed_tiles),
+            &conditions,
+            &settings,
+        );
+        assert_eq!(
+            get_best_discard_scores(&discards).tiles,
+            input_output::make_tile_sequence_from_string("19m").unwrap()
+        );
+
+        settings.discard_ranking = DiscardRanking::EfficiencyAndValue;
+        let discards = calculate_best_discards(
+            &hand,
+            shanten,
+            &mut make_frequency_table(&closed_tiles),
+            &conditions,
+            &settings,
+        );
+        assert_eq!(discards[0].tile, get_tile("1m"));
+    }
+}